@@ -1,6 +1,3 @@
 (define first car)
 
-(define rest cdr)
-
-(define (neq? a b) 
-    (not (eq? a b)))
\ No newline at end of file
+(define rest cdr)
\ No newline at end of file
@@ -82,7 +82,4 @@
 (define (skip n lst)
     (if (eq? n 0)
         lst
-        (skip (- n 1) (cdr lst))))
-
-(define (reduce func lst)
-    (foldr func (first lst) (rest lst)))
\ No newline at end of file
+        (skip (- n 1) (cdr lst))))
\ No newline at end of file
@@ -1,13 +1,3 @@
-;; even? : num -> bool
-;; Determines whether or not the specified number is even.
-(define (even? n)
-    (eq? 0 (modulo n 2)))
-
-;; odd? : num -> bool
-;; Determines whether or not the specified number is odd.
-(define (odd? n)
-    (not (even? n)))
-
 ;; pos? : num -> bool
 ;; Determines whether or not the specified number is positive.
 (define (pos? n)
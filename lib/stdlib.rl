@@ -1,10 +1,10 @@
 
 (define (when pred body...)
-    (if pred 
-        (apply begin body)
+    (if pred
+        (if (empty? body) empty (last body))
         empty))
 
 (define (unless pred body...)
     (if (not pred)
-        (apply begin body)
+        (if (empty? body) empty (last body))
         empty))
\ No newline at end of file
@@ -1,6 +1,9 @@
 #[derive(Debug)]
 pub struct RLError {
     pub description: String,
+    /// The expressions being evaluated when the error occurred, from the
+    /// innermost to the outermost.
+    pub backtrace: Vec<String>,
 }
 
 impl AsRef<str> for RLError {
@@ -21,7 +24,10 @@ impl From<String> for RLError {
     /// Produces an `Error` with a description equal to the specified string.
     #[inline]
     fn from(s: String) -> RLError {
-        RLError { description: s }
+        RLError {
+            description: s,
+            backtrace: vec![],
+        }
     }
 }
 
@@ -31,13 +37,27 @@ impl<'a> From<&'a str> for RLError {
     fn from(s: &str) -> RLError {
         RLError {
             description: s.to_string(),
+            backtrace: vec![],
         }
     }
 }
 
+use parser::{ParseAllError, ParseError};
 use std::error::Error;
 use std::io;
 
+impl From<ParseError> for RLError {
+    fn from(err: ParseError) -> RLError {
+        RLError::from(err.to_string())
+    }
+}
+
+impl From<ParseAllError> for RLError {
+    fn from(err: ParseAllError) -> RLError {
+        RLError::from(err.error)
+    }
+}
+
 impl From<io::Error> for RLError {
     fn from(err: io::Error) -> RLError {
         RLError::from(err.description())
@@ -46,8 +66,17 @@ impl From<io::Error> for RLError {
 
 use std::fmt;
 impl fmt::Display for RLError {
+    /// Displays the description of the error, followed by its backtrace, if
+    /// any.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.description)
+        write!(f, "{}", self.description)?;
+        if !self.backtrace.is_empty() {
+            write!(f, "\nBacktrace:")?;
+            for expr in &self.backtrace {
+                write!(f, "\n  {}", expr)?;
+            }
+        }
+        Ok(())
     }
 }
 
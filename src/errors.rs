@@ -16,14 +16,79 @@ pub fn arity_exact(expected: usize, found: usize) -> Error {
     format!("Expected {} arg(s), found {}.", expected, found).into()
 }
 
+/// Reports that the function with the specified name, if it has one, was
+/// called with the wrong number of arguments for the specified parameters.
+pub fn arity_mismatch(name: Option<&str>, params: &SExpr, found: usize) -> Error {
+    let plural = if found == 1 { "" } else { "s" };
+    match name {
+        Some(name) => format!("{} expects {}, got {} argument{}.", name, params, found, plural),
+        None => format!("The function expects {}, got {} argument{}.", params, found, plural),
+    }.into()
+}
+
+pub fn values_exact(expected: usize, found: usize) -> Error {
+    format!("Expected {} value(s), found {}.", expected, found).into()
+}
+
 pub fn unbound(ident: &str) -> Error {
     format!("Variable {} is unbound.", ident).into()
 }
 
+/// Reports that the specified identifier is unbound, suggesting the
+/// specified name which was likely meant instead.
+pub fn unbound_suggest(ident: &str, suggestion: &str) -> Error {
+    format!("Variable {} is unbound. Did you mean {}?", ident, suggestion).into()
+}
+
+/// Produces the number of single character insertions, deletions, and
+/// substitutions needed to turn one str into the other.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == *cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(diagonal + 1).min(row[j] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Produces the candidate closest to the specified name, provided that it is
+/// close enough to be a likely typo. Ties are broken alphabetically, so that
+/// the suggestion does not depend on the order of the candidates.
+pub fn closest<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+pub fn misplaced_variadic(ident: &str) -> Error {
+    format!(
+        "{}... is variadic. Variadic identifiers may only end a parameter list or pattern.",
+        ident
+    ).into()
+}
+
 pub fn not_a_function(val: &Value) -> Error {
     format!("{} is not a function.", val).into()
 }
 
+pub fn macro_not_first_class(val: &Value) -> Error {
+    format!("{} is a macro. Macros cannot be used as first-class functions.", val).into()
+}
+
 pub fn not_a_number(val: &Value) -> Error {
     format!("{} is not a number.", val).into()
 }
@@ -40,6 +105,10 @@ pub fn not_a_bool(val: &SExpr) -> Error {
     format!("{} is not a bool.", val).into()
 }
 
+pub fn protected_name(ident: &str) -> Error {
+    format!("{} is an intrinsic function, and may not be redefined.", ident).into()
+}
+
 pub fn reserved_word(val: &str) -> Error {
     format!("\"{}\" is a reserved word.", val).into()
 }
@@ -1,11 +1,165 @@
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::slice::Iter;
-use super::Value;
+use std::io::{self, Write};
+use std::iter;
+use std::mem;
+use std::rc::{Rc, Weak};
+use std::slice::{self, Iter};
+use super::{Value, HIDDEN_PARAM};
 use parser::SExpr;
 
+/// Represents the bindings of a single scope, which are shared with any
+/// closures created within it.
+pub type Bindings = Rc<RefCell<HashMap<String, Value>>>;
+
+/// Represents a single scope captured by a function. A function bound within a
+/// scope it has captured, such as by an internal `define`, holds that scope
+/// weakly, as otherwise the two would keep each other alive.
+#[derive(Clone)]
+enum Captured {
+    Strong(Bindings),
+    Weak(Weak<RefCell<HashMap<String, Value>>>),
+}
+
+impl Captured {
+    fn upgrade(&self) -> Option<Bindings> {
+        match self {
+            Captured::Strong(bindings) => Some(bindings.clone()),
+            Captured::Weak(bindings) => bindings.upgrade(),
+        }
+    }
+
+    fn is(&self, bindings: &Bindings) -> bool {
+        self.as_ptr() == Rc::as_ptr(bindings)
+    }
+
+    fn as_ptr(&self) -> *const RefCell<HashMap<String, Value>> {
+        match self {
+            Captured::Strong(bindings) => Rc::as_ptr(bindings),
+            Captured::Weak(bindings) => bindings.as_ptr(),
+        }
+    }
+}
+
+/// Represents the scopes captured by a function when it is created, ordered
+/// from the outermost to the innermost.
+#[derive(Clone, Default)]
+pub struct Captures(Rc<Vec<Captured>>);
+
+impl Captures {
+    /// Produces captures consisting of a single scope with the specified
+    /// bindings.
+    pub fn new(mapping: HashMap<String, Value>) -> Captures {
+        let bindings = Rc::new(RefCell::new(mapping));
+        Captures(Rc::new(vec![Captured::Strong(bindings)]))
+    }
+
+    /// Produces the captured bindings which are still alive, from the
+    /// innermost to the outermost.
+    fn bindings(&self) -> impl Iterator<Item = Bindings> + '_ {
+        self.0.iter().rev().filter_map(Captured::upgrade)
+    }
+
+    /// Produces the captures with the specified bindings held weakly, or
+    /// `None` if they are not captured.
+    fn detach(&self, bindings: &Bindings) -> Option<Captures> {
+        if !self.0.iter().any(|captured| captured.is(bindings)) {
+            return None;
+        }
+        let captures = self.0.iter().map(|captured| match captured {
+            Captured::Strong(b) if captured.is(bindings) => Captured::Weak(Rc::downgrade(b)),
+            captured => captured.clone(),
+        });
+        Some(Captures(Rc::new(captures.collect())))
+    }
+
+    /// Produces the captures with every living scope held strongly, or `None`
+    /// if they already are.
+    fn attach(&self) -> Option<Captures> {
+        let captures = self.0.iter().map(|captured| match captured.upgrade() {
+            Some(bindings) => Captured::Strong(bindings),
+            None => captured.clone(),
+        });
+        match self.0.iter().any(|captured| matches!(captured, Captured::Weak(_))) {
+            true => Some(Captures(Rc::new(captures.collect()))),
+            false => None,
+        }
+    }
+}
+
+impl PartialEq for Captures {
+    /// Captures are only equal if they capture the same scopes, as comparing
+    /// their bindings could recurse into the function that captured them.
+    fn eq(&self, other: &Captures) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().zip(other.0.iter()).all(|(a, b)| a.as_ptr() == b.as_ptr())
+    }
+}
+
+/// Prepares the specified value to be bound within the specified bindings. A
+/// function which has captured them holds them weakly while it is bound there.
+fn detach(val: Value, bindings: &Bindings) -> Value {
+    match val {
+        Value::Func(mut lambda) => {
+            if let Some(captures) = lambda.captures.detach(bindings) {
+                lambda.captures = captures;
+            }
+            Value::Func(lambda)
+        }
+        val => val,
+    }
+}
+
+/// Prepares the specified value to be read out of its bindings. A function
+/// holds its captured scopes strongly again, as it may outlive them.
+fn attach(val: Value) -> Value {
+    match val {
+        Value::Func(mut lambda) => {
+            if let Some(captures) = lambda.captures.attach() {
+                lambda.captures = captures;
+            }
+            Value::Func(lambda)
+        }
+        val => val,
+    }
+}
+
 pub struct Scope {
     pub caller: SExpr,
-    pub mapping: HashMap<String, Value>,
+    pub mapping: Bindings,
+    pub captures: Captures,
+}
+
+impl Scope {
+    fn new(caller: SExpr) -> Scope {
+        Scope {
+            caller,
+            mapping: Bindings::default(),
+            captures: Captures::default(),
+        }
+    }
+
+    /// Produces the bindings in which the specified key is defined, searching
+    /// the scope itself before the scopes it has captured.
+    fn bindings(&self, key: &str) -> Option<Bindings> {
+        iter::once(self.mapping.clone())
+            .chain(self.captures.bindings())
+            .find(|bindings| bindings.borrow().contains_key(key))
+    }
+}
+
+impl Clone for Scope {
+    /// Copies the bindings of the scope, so that defining or assigning in the
+    /// copy does not affect the original. Captured scopes remain shared.
+    fn clone(&self) -> Scope {
+        let mapping = self.mapping.borrow();
+        let mapping = mapping.iter().map(|(k, v)| (k.clone(), attach(v.clone())));
+        Scope {
+            caller: self.caller.clone(),
+            mapping: Rc::new(RefCell::new(mapping.collect())),
+            captures: self.captures.clone(),
+        }
+    }
 }
 
 type StructFields = Vec<String>;
@@ -26,21 +180,135 @@ impl FieldIndex for StructFields {
     }
 }
 
+/// Determines how `define` treats an identifier that would shadow an
+/// intrinsic function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShadowPolicy {
+    /// Intrinsics may be shadowed freely.
+    Allow,
+    /// Intrinsics may be shadowed, but a warning is recorded.
+    Warn,
+    /// Shadowing an intrinsic is an error.
+    Protect,
+}
+
+/// Determines which values are accepted as conditions, such as by `if` and
+/// `not`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Truthiness {
+    /// Only bools are accepted, and any other value is an error.
+    Strict,
+    /// Any value is accepted, and only `#f` and the empty list are false.
+    Lenient,
+}
+
+/// Determines how nums are displayed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FloatFormat {
+    /// The fewest digits which identify the num, without an exponent.
+    Shortest,
+    /// The specified number of digits after the decimal point, without an
+    /// exponent.
+    Fixed(usize),
+    /// An exponent, with the specified number of digits after the decimal
+    /// point, or else the fewest digits which identify the num.
+    Scientific(Option<usize>),
+}
+
+thread_local! {
+    /// The format in which nums are displayed. Values are displayed without
+    /// access to an environment, so it is kept per thread instead.
+    static FLOAT_FORMAT: Cell<FloatFormat> = const { Cell::new(FloatFormat::Shortest) };
+}
+
+impl FloatFormat {
+    /// Produces the format in which nums are currently displayed on the
+    /// current thread.
+    pub fn current() -> FloatFormat {
+        FLOAT_FORMAT.with(Cell::get)
+    }
+
+    /// Sets the format in which nums are displayed on the current thread, by
+    /// every environment on it.
+    pub fn set(format: FloatFormat) {
+        FLOAT_FORMAT.with(|current| current.set(format));
+    }
+
+    /// Produces the specified num, formatted according to the format.
+    pub fn format(self, n: f64) -> String {
+        match self {
+            FloatFormat::Shortest => n.to_string(),
+            FloatFormat::Fixed(precision) => format!("{:.*}", precision, n),
+            FloatFormat::Scientific(Some(precision)) => format!("{:.*e}", precision, n),
+            FloatFormat::Scientific(None) => format!("{:e}", n),
+        }
+    }
+}
+
+/// The values yielded so far by a generator being evaluated.
+#[derive(Clone)]
+pub struct Yields {
+    pub values: Vec<Value>,
+    pub limit: usize,
+    /// Whether or not the generator was stopped for attempting to yield more
+    /// values than its limit.
+    pub stopped: bool,
+}
+
+/// Represents a shared destination for the interpreter's output, such as
+/// printed values or traced function calls.
+pub type Sink = Rc<RefCell<dyn Write>>;
+
+/// The destination to which function calls are traced.
+#[derive(Clone)]
+enum Trace {
+    Off,
+    /// The error output of the environment, wherever it is redirected.
+    ErrorOutput,
+    Sink(Sink),
+}
+
+/// The state of the interpreter. Cloning an environment produces an
+/// independent snapshot, which may be mutated and discarded without affecting
+/// the original.
+#[derive(Clone)]
 pub struct Environment {
     base: Scope,
     stack: Vec<Scope>,
     structs: HashMap<String, StructFields>,
+    docs: HashMap<String, &'static str>,
+    fuel: Option<usize>,
+    shadow_policy: ShadowPolicy,
+    truthiness: Truthiness,
+    warnings: Vec<String>,
+    generators: Vec<Yields>,
+    trace: Trace,
+    out: Sink,
+    err: Sink,
+}
+
+/// Writes the specified str to the specified sink, flushing it immediately.
+fn write_to(sink: &Sink, s: &str) -> io::Result<()> {
+    let mut sink = sink.borrow_mut();
+    sink.write_all(s.as_bytes())?;
+    sink.flush()
 }
 
 impl Default for Environment {
     fn default() -> Self {
         let mut env = Self {
-            base: Scope {
-                caller: SExpr::Nil,
-                mapping: HashMap::new(),
-            },
+            base: Scope::new(SExpr::Nil),
             stack: vec![],
             structs: HashMap::new(),
+            docs: HashMap::new(),
+            fuel: None,
+            shadow_policy: ShadowPolicy::Allow,
+            truthiness: Truthiness::Strict,
+            warnings: vec![],
+            generators: vec![],
+            trace: Trace::Off,
+            out: Rc::new(RefCell::new(io::stdout())),
+            err: Rc::new(RefCell::new(io::stderr())),
         };
         env.enter_scope(SExpr::Nil);
         env
@@ -64,6 +332,17 @@ impl Environment {
         self.structs_mut().insert(name.into(), fields);
     }
 
+    /// Records the documentation of the intrinsic with the specified name.
+    pub fn add_doc<S: Into<String>>(&mut self, name: S, doc: &'static str) {
+        self.docs.insert(name.into(), doc);
+    }
+
+    /// Produces the documentation of the intrinsic with the specified name, if
+    /// it is documented.
+    pub fn doc(&self, name: &str) -> Option<&'static str> {
+        self.docs.get(name).cloned()
+    }
+
     pub fn get_struct<S: Into<String>>(&self, name: S) -> Option<&StructFields> {
         let name = name.into();
         let fields = self.structs().get(&name);
@@ -73,6 +352,163 @@ impl Environment {
         }
     }
 
+    /// Limits evaluation to the specified number of steps, after which any
+    /// further evaluation produces an error.
+    pub fn set_fuel(&mut self, fuel: usize) {
+        self.fuel = Some(fuel);
+    }
+
+    /// Removes the limit on the number of evaluation steps.
+    pub fn set_unlimited_fuel(&mut self) {
+        self.fuel = None;
+    }
+
+    /// Produces the number of remaining evaluation steps, if limited.
+    pub fn fuel(&self) -> Option<usize> {
+        self.fuel
+    }
+
+    /// Consumes a single evaluation step, producing `false` if no fuel
+    /// remains.
+    pub fn consume_fuel(&mut self) -> bool {
+        match self.fuel {
+            Some(0) => false,
+            Some(ref mut fuel) => {
+                *fuel -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Sets how `define` treats an identifier that would shadow an
+    /// intrinsic function.
+    pub fn set_shadow_policy(&mut self, policy: ShadowPolicy) {
+        self.shadow_policy = policy;
+    }
+
+    pub fn shadow_policy(&self) -> ShadowPolicy {
+        self.shadow_policy
+    }
+
+    /// Sets which values are accepted as conditions.
+    pub fn set_truthiness(&mut self, truthiness: Truthiness) {
+        self.truthiness = truthiness;
+    }
+
+    pub fn truthiness(&self) -> Truthiness {
+        self.truthiness
+    }
+
+    /// Produces the truth of the specified value as a condition, or `None` if
+    /// it is not accepted as one under the current truthiness.
+    pub fn truth_of(&self, val: &Value) -> Option<bool> {
+        match (val, self.truthiness) {
+            (Value::Bool(b), _) => Some(*b),
+            (_, Truthiness::Lenient) => Some(val.is_truthy()),
+            (_, Truthiness::Strict) => None,
+        }
+    }
+
+    /// Records the specified warning, to be reported once evaluation is
+    /// complete.
+    pub fn warn<S: Into<String>>(&mut self, warning: S) {
+        self.warnings.push(warning.into());
+    }
+
+    /// Removes and produces all warnings recorded so far.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        self.warnings.split_off(0)
+    }
+
+    /// Begins collecting the values yielded by a generator, which may yield up
+    /// to the specified number of values.
+    pub fn enter_generator(&mut self, limit: usize) {
+        self.generators.push(Yields {
+            values: vec![],
+            limit,
+            stopped: false,
+        });
+    }
+
+    /// Stops collecting the values yielded by the innermost generator,
+    /// producing them.
+    pub fn exit_generator(&mut self) -> Yields {
+        self.generators
+            .pop()
+            .expect("Attempted to exit nonexistent generator.")
+    }
+
+    /// Produces the values yielded so far by the innermost generator being
+    /// evaluated, if any.
+    pub fn generator_mut(&mut self) -> Option<&mut Yields> {
+        self.generators.last_mut()
+    }
+
+    /// Traces each function call and its return value to the specified
+    /// output, or stops tracing if there is none.
+    pub fn set_trace(&mut self, output: Option<Sink>) {
+        self.trace = match output {
+            Some(output) => Trace::Sink(output),
+            None => Trace::Off,
+        };
+    }
+
+    /// Traces each function call and its return value to the error output,
+    /// following it if it is later redirected, or stops tracing.
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.trace = if tracing { Trace::ErrorOutput } else { Trace::Off };
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        !matches!(self.trace, Trace::Off)
+    }
+
+    /// Writes the specified line to the trace output, indented by the
+    /// specified scope depth.
+    pub fn trace(&self, depth: usize, line: impl AsRef<str>) {
+        let output = match self.trace {
+            Trace::Off => return,
+            Trace::ErrorOutput => &self.err,
+            Trace::Sink(ref output) => output,
+        };
+        let indent = "  ".repeat(depth.saturating_sub(1));
+        // Failing to trace should not interrupt evaluation
+        let _ = writeln!(output.borrow_mut(), "{}{}", indent, line.as_ref());
+    }
+
+    /// Redirects printed output to the specified sink, producing the sink to
+    /// which it was previously written.
+    pub fn set_output(&mut self, out: Sink) -> Sink {
+        mem::replace(&mut self.out, out)
+    }
+
+    /// Produces the sink to which printed output is written.
+    pub fn output(&self) -> Sink {
+        self.out.clone()
+    }
+
+    /// Writes the specified str to the printed output.
+    pub fn write_output(&self, s: &str) -> io::Result<()> {
+        write_to(&self.out, s)
+    }
+
+    /// Redirects reported errors and warnings to the specified sink,
+    /// producing the sink to which they were previously written.
+    pub fn set_error_output(&mut self, err: Sink) -> Sink {
+        mem::replace(&mut self.err, err)
+    }
+
+    /// Produces the sink to which errors and warnings are reported.
+    pub fn error_output(&self) -> Sink {
+        self.err.clone()
+    }
+
+    /// Writes the specified str to the error output.
+    pub fn write_error(&self, s: &str) -> io::Result<()> {
+        write_to(&self.err, s)
+    }
+
     pub fn prev_scope(&self) -> &Scope {
         let len = self.stack.len();
         if len > 1 {
@@ -93,53 +529,177 @@ impl Environment {
     }
 
     pub fn enter_scope(&mut self, caller: SExpr) {
-        self.stack.push(Scope {
-            caller: caller,
-            mapping: HashMap::new(),
-        });
+        self.stack.push(Scope::new(caller));
+    }
+
+    /// Makes the specified captured scopes visible from the current scope,
+    /// such that its own bindings shadow them.
+    pub fn enter_captures(&mut self, captures: Captures) {
+        self.cur_scope_mut().captures = captures;
+    }
+
+    /// Captures every scope above the global scope, to be shared with a
+    /// function created in the current scope.
+    pub fn capture(&self) -> Captures {
+        let mut captured = vec![];
+        for scope in self.stack.iter().skip(1) {
+            captured.extend(scope.captures.0.iter().cloned());
+            captured.push(Captured::Strong(scope.mapping.clone()));
+        }
+        Captures(Rc::new(captured))
+    }
+
+    /// Produces the number of scopes that have been entered.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Exits scopes until only the specified number remain.
+    pub fn truncate_scopes(&mut self, depth: usize) {
+        while self.stack.len() > depth {
+            self.exit_scope();
+        }
     }
 
     pub fn exit_scope(&mut self) {
-        self.stack
-            .pop()
-            .expect("Attempted to exit nonexistent scope.");
+        self.stack.pop().expect("Attempted to exit nonexistent scope.");
     }
 
     pub fn define<K>(&mut self, key: K, value: Value)
     where
         K: Into<String>,
     {
-        let scope = self.cur_scope_mut();
-        scope.mapping.insert(key.into(), value);
+        let mapping = &self.cur_scope().mapping;
+        mapping.borrow_mut().insert(key.into(), detach(value, mapping));
     }
 
-    pub fn get<K>(&self, key: K) -> Option<&Value>
+    /// Assigns the specified value to the nearest existing binding of the
+    /// specified key, producing `false` if there is none.
+    pub fn set<K>(&mut self, key: K, value: Value) -> bool
     where
         K: AsRef<str>,
     {
-        for scope in self.stack.iter().rev() {
-            if let Some(value) = scope.mapping.get(key.as_ref()) {
-                return Some(value);
+        let key = key.as_ref();
+        match self.stack.iter().rev().find_map(|scope| scope.bindings(key)) {
+            Some(bindings) => {
+                let value = detach(value, &bindings);
+                bindings.borrow_mut().insert(key.to_string(), value);
+                true
             }
+            None => false,
         }
-        None
     }
 
-    pub fn get_super<K>(&self, key: K) -> Option<&Value>
+    pub fn get<K>(&self, key: K) -> Option<Value>
+    where
+        K: AsRef<str>,
+    {
+        let key = key.as_ref();
+        self.stack
+            .iter()
+            .rev()
+            .find_map(|scope| scope.bindings(key))
+            .map(|bindings| attach(bindings.borrow()[key].clone()))
+    }
+
+    /// Produces the value bound to the specified key in the global scope,
+    /// ignoring any bindings which shadow it.
+    pub fn get_global<K>(&self, key: K) -> Option<Value>
+    where
+        K: AsRef<str>,
+    {
+        let scope = self.stack.first()?;
+        let mapping = scope.mapping.borrow();
+        mapping.get(key.as_ref()).cloned().map(attach)
+    }
+
+    pub fn get_super<K>(&self, key: K) -> Option<Value>
     where
         K: AsRef<str>,
     {
         let key = key.as_ref();
         let len = self.stack.len();
-        if len > 1 {
-            for scope in (&self.stack[..len - 1]).iter().rev() {
-                if let Some(value) = scope.mapping.get(key) {
-                    return Some(value);
-                }
-            }
-            None
+        let scopes = if len > 1 {
+            &self.stack[..len - 1]
         } else {
-            self.base.mapping.get(key)
+            slice::from_ref(&self.base)
+        };
+        scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.bindings(key))
+            .map(|bindings| attach(bindings.borrow()[key].clone()))
+    }
+
+    /// Produces the names of every binding visible from the current scope,
+    /// including those captured by the current function. The hidden
+    /// parameters of destructured arguments are omitted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .stack
+            .iter()
+            .flat_map(|scope| iter::once(scope.mapping.clone()).chain(scope.captures.bindings()))
+            .flat_map(|bindings| bindings.borrow().keys().cloned().collect::<Vec<_>>())
+            .filter(|name| !name.starts_with(HIDDEN_PARAM))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use interpreter::Lambda;
+
+    #[test]
+    fn clone_is_independent() {
+        let mut env = Environment::default();
+        env.define("a", Value::Num(1.0));
+
+        let mut snapshot = env.clone();
+        snapshot.define("a", Value::Num(2.0));
+        snapshot.define("b", Value::Num(3.0));
+        snapshot.add_struct("point", vec!["x".to_string(), "y".to_string()]);
+        snapshot.enter_scope(SExpr::Nil);
+        assert!(snapshot.get("a") == Some(Value::Num(2.0)));
+        drop(snapshot);
+
+        assert!(env.get("a") == Some(Value::Num(1.0)));
+        assert!(env.get("b").is_none());
+        assert!(env.get_struct("point").is_none());
+        assert_eq!(env.scope_iter().count(), 1);
+    }
+
+    #[test]
+    fn closures_release_scopes() {
+        let mut env = Environment::default();
+        let define_closure = |env: &mut Environment| {
+            let mut lambda = Lambda::new(vec![], SExpr::Nil, false);
+            lambda.captures = env.capture();
+            env.define("f", Value::Func(lambda));
+            Rc::downgrade(&env.cur_scope().mapping)
+        };
+
+        // A closure bound in the scope it captures does not keep it alive
+        for _ in 0..3 {
+            env.enter_scope(SExpr::Nil);
+            let bindings = define_closure(&mut env);
+            env.exit_scope();
+            assert!(bindings.upgrade().is_none());
         }
+
+        // A closure which escapes the scope keeps it alive until it is dropped
+        env.enter_scope(SExpr::Nil);
+        let bindings = define_closure(&mut env);
+        let f = env.get("f").unwrap();
+        env.truncate_scopes(1);
+        assert!(bindings.upgrade().is_some());
+        assert!(f == bindings.upgrade().unwrap().borrow()["f"]);
+        env.define("g", f);
+        assert!(bindings.upgrade().is_some());
+        env.define("g", Value::Num(0.0));
+        assert!(bindings.upgrade().is_none());
     }
 }
@@ -0,0 +1,171 @@
+use super::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::slice;
+
+/// Produces the value which marks the end of an iterator. It is distinct from
+/// every other value, so it is never produced by accident.
+pub fn iter_end() -> Value {
+    Value::IterEnd
+}
+
+/// Determines whether or not the specified value marks the end of an
+/// iterator.
+pub fn is_iter_end(val: &Value) -> bool {
+    matches!(val, Value::IterEnd)
+}
+
+/// The source from which an iterator lazily produces its values.
+#[derive(Clone)]
+pub enum IterSource {
+    /// Counts from `start` toward `end` by `step`, excluding `end`. The
+    /// `index` of the next value is counted instead of summing the steps, so
+    /// that rounding errors do not accumulate.
+    Range {
+        start: f64,
+        end: f64,
+        step: f64,
+        index: u64,
+    },
+
+    /// Produces the remaining elements of a list.
+    List(SharedList),
+
+    /// Calls a function without arguments to produce each value, until it
+    /// produces the end marker.
+    Generator(Value),
+
+    /// Applies a function to each value of another iterator.
+    Map(Value, SharedIter),
+
+    /// Produces only the values of another iterator which satisfy a
+    /// predicate.
+    Filter(Value, SharedIter),
+
+    /// Produces no more values.
+    Done,
+}
+
+struct IterState {
+    source: IterSource,
+    peeked: Option<Value>,
+}
+
+/// An iterator, which lazily produces values from its source. Cloning an
+/// iterator shares its state, so that advancing either clone advances both.
+#[derive(Clone)]
+pub struct SharedIter(Rc<RefCell<IterState>>);
+
+impl SharedIter {
+    pub fn new(source: IterSource) -> SharedIter {
+        SharedIter(Rc::new(RefCell::new(IterState {
+            source,
+            peeked: None,
+        })))
+    }
+
+    /// Determines whether or not the two iterators share the same state.
+    pub fn ptr_eq(&self, other: &SharedIter) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Produces the next value of the iterator and advances past it, or
+    /// `None` if it has produced all of its values.
+    pub fn next(&self, env: &mut Environment) -> Result<Option<Value>> {
+        if let Some(val) = self.0.borrow_mut().peeked.take() {
+            return Ok(Some(val));
+        }
+
+        let next = self.advance(env)?;
+        if next.is_none() {
+            self.0.borrow_mut().source = IterSource::Done;
+        }
+        Ok(next)
+    }
+
+    /// Produces the next value of the iterator without advancing past it.
+    pub fn peek(&self, env: &mut Environment) -> Result<Option<Value>> {
+        if let Some(ref val) = self.0.borrow().peeked {
+            return Ok(Some(val.clone()));
+        }
+
+        let next = self.next(env)?;
+        self.0.borrow_mut().peeked = next.clone();
+        Ok(next)
+    }
+
+    /// Produces the next value from the source of the iterator.
+    fn advance(&self, env: &mut Environment) -> Result<Option<Value>> {
+        let source = {
+            let mut state = self.0.borrow_mut();
+            match state.source {
+                IterSource::Range {
+                    start,
+                    end,
+                    step,
+                    ref mut index,
+                } => {
+                    let val = start + *index as f64 * step;
+                    let in_range = if step > 0.0 { val < end } else { val > end };
+                    if !in_range {
+                        return Ok(None);
+                    }
+                    *index += 1;
+                    return Ok(Some(Value::Num(val)));
+                }
+                IterSource::List(ref mut list) => {
+                    let val = list.first().cloned();
+                    *list = list.tail();
+                    return Ok(val);
+                }
+                IterSource::Done => return Ok(None),
+                ref source => source.clone(),
+            }
+        };
+
+        // The state is not borrowed while calling functions, which may
+        // themselves advance the iterator
+        match source {
+            IterSource::Generator(func) => {
+                let val = call_fn(&SExpr::Nil, &func, &[], env)?;
+                Ok(if is_iter_end(&val) { None } else { Some(val) })
+            }
+            IterSource::Map(func, iter) => match iter.next(env)? {
+                Some(val) => call_fn(&SExpr::Nil, &func, &[val], env).map(Some),
+                None => Ok(None),
+            },
+            IterSource::Filter(pred, iter) => {
+                while let Some(val) = iter.next(env)? {
+                    let res = call_fn(&SExpr::Nil, &pred, slice::from_ref(&val), env)?;
+                    match env.truth_of(&res) {
+                        Some(true) => return Ok(Some(val)),
+                        Some(false) => (),
+                        None => return Err(format!("{} is not a bool.", res).into()),
+                    }
+                }
+                Ok(None)
+            }
+            _ => unreachable!("Sources without functions are advanced in place."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clones_share_state() {
+        let mut env = Environment::default();
+        let list = SharedList::from(vec![Value::Num(1.0), Value::Num(2.0)]);
+        let iter = SharedIter::new(IterSource::List(list));
+        let other = iter.clone();
+        assert!(iter.ptr_eq(&other));
+
+        assert!(iter.peek(&mut env).unwrap() == Some(Value::Num(1.0)));
+        assert!(other.next(&mut env).unwrap() == Some(Value::Num(1.0)));
+        assert!(iter.next(&mut env).unwrap() == Some(Value::Num(2.0)));
+        assert!(other.next(&mut env).unwrap().is_none());
+        assert!(iter.peek(&mut env).unwrap().is_none());
+    }
+}
@@ -0,0 +1,72 @@
+use super::Value;
+use std::iter::FromIterator;
+use std::ops::Deref;
+use std::rc::Rc;
+
+/// An immutable list of values. Cloning a list, or taking its tail, shares the
+/// underlying storage rather than copying its elements, so that traversing a
+/// list with `cdr` does not need to copy the rest of the list at each step.
+#[derive(Clone, Default)]
+pub struct SharedList {
+    items: Rc<Vec<Value>>,
+    start: usize,
+}
+
+impl SharedList {
+    /// Produces the list of all elements after the first element of the
+    /// list, sharing storage with the list. The tail of an empty list is empty.
+    pub fn tail(&self) -> SharedList {
+        SharedList {
+            items: Rc::clone(&self.items),
+            start: (self.start + 1).min(self.items.len()),
+        }
+    }
+}
+
+impl Deref for SharedList {
+    type Target = [Value];
+
+    /// Produces a slice containing the elements of the list.
+    fn deref(&self) -> &[Value] {
+        &self.items[self.start..]
+    }
+}
+
+impl From<Vec<Value>> for SharedList {
+    /// Produces a list taking ownership of the specified elements.
+    fn from(items: Vec<Value>) -> SharedList {
+        SharedList {
+            items: Rc::new(items),
+            start: 0,
+        }
+    }
+}
+
+impl From<&[Value]> for SharedList {
+    /// Produces a list containing clones of the specified elements.
+    fn from(items: &[Value]) -> SharedList {
+        SharedList::from(items.to_vec())
+    }
+}
+
+impl FromIterator<Value> for SharedList {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> SharedList {
+        SharedList::from(iter.into_iter().collect::<Vec<_>>())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tail_shares_storage() {
+        let list = SharedList::from(vec![Value::Num(1.0), Value::Num(2.0)]);
+        let tail = list.tail();
+        assert!(Rc::ptr_eq(&list.items, &tail.items));
+        assert_eq!(tail.len(), 1);
+        assert!(tail[0] == Value::Num(2.0));
+        assert!(tail.tail().is_empty());
+        assert!(tail.tail().tail().is_empty());
+    }
+}
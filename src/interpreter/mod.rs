@@ -1,18 +1,36 @@
 mod environment;
+mod iter;
+mod list;
 mod value;
 
 pub use self::environment::*;
+pub use self::iter::*;
+pub use self::list::*;
 pub use self::value::*;
 
+use err::RLError;
 use errors::*;
 use parser::SExpr;
 
 pub type FuncResult = Result<Value>;
 pub type Intrinsic = fn(&mut Environment, &[Value]) -> FuncResult;
-pub type Macro = fn(&mut Environment, &[SExpr]) -> FuncResult;
+pub type Macro = fn(&mut Environment, &[SExpr]) -> Result<Tail>;
+
+/// Represents the output of a macro: either a value, or an expression in tail
+/// position that remains to be evaluated in the macro's environment.
+pub enum Tail {
+    Value(Value),
+    Expr(SExpr),
+}
+
+impl From<Value> for Tail {
+    fn from(val: Value) -> Tail {
+        Tail::Value(val)
+    }
+}
 
 pub fn empty() -> Value {
-    Value::List(vec![])
+    Value::List(SharedList::default())
 }
 
 pub trait Eval {
@@ -22,90 +40,353 @@ pub trait Eval {
 const SUPER: &str = "#super:";
 const SUPER_LEN: usize = 7;
 
+/// The result of a single evaluation step.
+enum Step {
+    Done(Value),
+    Continue(SExpr),
+}
+
+/// The function call whose body is currently being evaluated in tail
+/// position, identified by the scope depth at which it was made.
+struct Frame {
+    depth: usize,
+}
+
 impl Eval for SExpr {
+    /// Evaluates the expression. Expressions in tail position, such as the
+    /// body of a function or the chosen branch of an `if`, are evaluated in
+    /// a loop rather than recursively, so tail calls do not grow the stack.
+    /// A function call in tail position replaces the scope of the call it is
+    /// made from, so mutually recursive functions also run in constant space.
+    /// All scopes entered during evaluation are exited once it completes,
+    /// even if an error occurs, after recording them in the error's
+    /// backtrace.
     fn eval(&self, env: &mut Environment) -> Result<Value> {
-        match *self {
-            // Primitives map directly
-            SExpr::Num(n) => Ok(Value::Num(n)),
-            SExpr::Bool(b) => Ok(Value::Bool(b)),
-            SExpr::Str(ref s) => Ok(Value::Str(s.clone())),
-
-            // Fetch value of identifier in context
-            SExpr::Ident(ref s, _) => {
-                // Previous scope if identifier begins with "super:"
-                let index = s.find(SUPER);
-                let contains_super = match index {
-                    Some(_) => true,
-                    _ => false,
-                };
+        let depth = env.depth();
+        let mut frame = None;
+        let mut next: Option<SExpr> = None;
+        let res = loop {
+            let expr = next.as_ref().unwrap_or(self);
+            match step(expr, env, &mut frame) {
+                Ok(Step::Done(val)) => break Ok(val),
+                Ok(Step::Continue(expr)) => next = Some(expr),
+                Err(mut why) => {
+                    // The backtrace is recorded where the error occurs, while
+                    // the callers' scopes are still intact
+                    if why.backtrace.is_empty() {
+                        why.backtrace.push(expr.to_string());
+                        let callers = env.scope_iter().rev().map(|scope| &scope.caller);
+                        why.backtrace.extend(
+                            callers
+                                .filter(|&caller| *caller != SExpr::Nil)
+                                .map(|caller| caller.to_string()),
+                        );
+                    }
+                    break Err(why);
+                }
+            }
+        };
+        env.truncate_scopes(depth);
 
-                let ident = match index {
-                    Some(_) => &s[SUPER_LEN..],
-                    None => s,
-                };
+        // Tail calls made in the loop all return at once
+        if let (Some(_), Ok(val)) = (&frame, &res) {
+            env.trace(depth, format!("< {:?}", val));
+        }
+        res
+    }
+}
 
-                let res = if contains_super {
-                    env.get_super(ident)
-                } else {
-                    env.get(ident)
-                };
+/// Traces a call to the specified function with the specified arguments.
+fn trace_call(
+    env: &Environment,
+    depth: usize,
+    func: &SExpr,
+    args: &[Value],
+    kwargs: &[(String, Value)],
+) {
+    let mut call = format!("> ({}", func);
+    for arg in args {
+        call.push_str(&format!(" {:?}", arg));
+    }
+    for (name, arg) in kwargs {
+        call.push_str(&format!(" #:{} {:?}", name, arg));
+    }
+    call.push(')');
+    env.trace(depth, call);
+}
 
-                match res {
-                    Some(val) => ok(val.clone()),
-                    None => err(unbound(ident)),
-                }
-            }
+/// Performs a single step of evaluating the specified expression, producing
+/// either its value or the expression in tail position to evaluate next.
+fn step(expr: &SExpr, env: &mut Environment, frame: &mut Option<Frame>) -> Result<Step> {
+    if !env.consume_fuel() {
+        return Err("Fuel exhausted.".into());
+    }
 
-            // Evaluate first element of the list, then apply subsequent
-            // elements to the first element if it is a function.
-            SExpr::List(ref vals) => {
-                if vals.is_empty() {
-                    Ok(empty())
-                } else {
-                    let func = vals[0].eval(env)?;
-                    match func {
-                        Value::Func(..) => {
-                            let mut args = Vec::<Value>::with_capacity(vals.len() - 1);
-                            for param in &vals[1..] {
-                                let arg = param.eval(env)?;
-                                args.push(arg);
-                            }
-                            eval_func(self.clone(), &func, &args, env)
-                        }
-                        Value::Intrinsic(ref func) => {
-                            let mut args: Vec<Value> = vec![];
+    let val = match *expr {
+        // Primitives map directly
+        SExpr::Num(n) => Value::Num(n),
+        SExpr::Bool(b) => Value::Bool(b),
+        SExpr::Str(ref s) => Value::Str(s.clone()),
+
+        // Fetch value of identifier in context
+        // Variadic identifiers only have meaning in parameter lists
+        SExpr::Ident(ref s, true) => return Err(misplaced_variadic(s)),
 
-                            for arg in &vals[1..] {
-                                let eval = arg.eval(env)?;
-                                args.push(eval);
-                            }
+        SExpr::Ident(ref s, false) => {
+            // Previous scope if identifier begins with "super:"
+            let index = s.find(SUPER);
+            let contains_super = index.is_some();
 
-                            func(env, &args)
+            let ident = match index {
+                Some(_) => &s[SUPER_LEN..],
+                None => s,
+            };
+
+            let res = if contains_super {
+                env.get_super(ident)
+            } else {
+                env.get(ident)
+            };
+
+            match res {
+                Some(val) => val,
+                None => return Err(unbound_ident(ident, env)),
+            }
+        }
+
+        // Evaluate first element of the list, then apply subsequent
+        // elements to the first element if it is a function.
+        SExpr::List(ref vals) => {
+            if vals.is_empty() {
+                empty()
+            } else {
+                let func = vals[0].eval(env)?;
+                match func {
+                    Value::Func(ref lambda) => {
+                        let (args, kwargs) = eval_call_args(&vals[1..], env)?;
+
+                        // A tail call replaces the scope of the current call
+                        let depth = match frame {
+                            Some(frame) => frame.depth,
+                            None => env.depth(),
+                        };
+                        env.truncate_scopes(depth);
+                        env.enter_scope(expr.clone());
+                        if env.is_tracing() {
+                            trace_call(env, depth, &vals[0], &args, &kwargs);
                         }
-                        Value::Macro(ref func) => func(env, vals),
-                        _ => Err(not_a_function(&func)),
+                        bind_args(lambda, &args, kwargs, env)?;
+
+                        let body = lambda.body.clone();
+                        *frame = Some(Frame { depth });
+                        return Ok(Step::Continue(body));
+                    }
+                    Value::Intrinsic(ref func) => {
+                        let args = eval_args(&vals[1..], env)?;
+                        func(env, &args)?
                     }
+                    Value::Memoized(_) => {
+                        let args = eval_args(&vals[1..], env)?;
+                        eval_func(expr.clone(), &func, &args, env)?
+                    }
+                    // Macros operate on unevaluated expressions, so they may
+                    // only be called by name
+                    Value::Macro(_) if !names_macro(&vals[0], env) => {
+                        return Err(macro_not_first_class(&func))
+                    }
+                    Value::Macro(ref func) => match func(env, vals)? {
+                        Tail::Value(val) => val,
+                        Tail::Expr(expr) => return Ok(Step::Continue(expr)),
+                    },
+                    _ => return Err(not_a_function(&func)),
                 }
             }
+        }
 
-            // Quoted expression
-            SExpr::Quote(ref expr) => {
-                let r = expr.as_ref().clone();
-                Ok(r.into())
-            }
+        // Quoted expression
+        SExpr::Quote(ref expr) => {
+            let r = expr.as_ref().clone();
+            r.into()
+        }
 
-            // Nil evaluates to an empty list
-            SExpr::Nil => Ok(empty()),
-        }.map_err(|why| {
-            let mut s = format!("Evaluating {}.", self);
-            for scope in env.scope_iter() {
-                if scope.caller != SExpr::Nil {
-                    s.push_str("\n  ");
-                    s.push_str(&scope.caller.to_string());
-                }
+        // Nil evaluates to an empty list
+        SExpr::Nil => empty(),
+    };
+    Ok(Step::Done(val))
+}
+
+/// Determines whether or not the specified expression is a name bound to a
+/// macro, rather than an expression which merely produces one, such as a call.
+fn names_macro(expr: &SExpr, env: &Environment) -> bool {
+    match expr {
+        SExpr::Ident(name, false) => matches!(env.get(name), Some(Value::Macro(_))),
+        _ => false,
+    }
+}
+
+/// Reports that the specified identifier is unbound. If it is shaped like the
+/// constructor, predicate, or an accessor of a struct which has not been
+/// defined, the closest defined struct is suggested instead. Otherwise, the
+/// closest name bound in the environment is suggested, if any is close enough.
+fn unbound_ident(ident: &str, env: &Environment) -> RLError {
+    let structs = env.structs();
+    let names = || structs.keys().map(String::as_str);
+
+    let suggestion = if let Some(name) = ident.strip_prefix("make-") {
+        closest(name, names()).map(|name| format!("make-{}", name))
+    } else if let Some(name) = ident.strip_suffix('?') {
+        closest(name, names()).map(|name| format!("{}?", name))
+    } else if let Some(i) = ident.rfind('-') {
+        let field = &ident[i + 1..];
+        let candidates = structs
+            .iter()
+            .filter(|(_, fields)| fields.iter().any(|f| f == field))
+            .map(|(name, _)| name.as_str());
+        closest(&ident[..i], candidates).map(|name| format!("{}-{}", name, field))
+    } else {
+        None
+    };
+    let suggestion = suggestion.or_else(|| {
+        let names = env.names();
+        closest(ident, names.iter().map(String::as_str)).map(str::to_string)
+    });
+
+    match suggestion {
+        Some(suggestion) => unbound_suggest(ident, &suggestion),
+        None => unbound(ident),
+    }
+}
+
+/// Evaluates the specified argument expression.
+fn eval_arg(expr: &SExpr, env: &mut Environment) -> Result<Value> {
+    match expr.eval(env)? {
+        // Multiple values cannot be passed as a single argument
+        Value::Values(vals) => Err(values_exact(1, vals.len())),
+        val => Ok(val),
+    }
+}
+
+/// Evaluates each of the specified argument expressions.
+fn eval_args(exprs: &[SExpr], env: &mut Environment) -> Result<Vec<Value>> {
+    let mut args = Vec::<Value>::with_capacity(exprs.len());
+    for expr in exprs {
+        args.push(eval_arg(expr, env)?);
+    }
+    Ok(args)
+}
+
+/// Produces the name of the specified expression if it is a keyword, such as
+/// `#:name`.
+pub fn keyword_name(expr: &SExpr) -> Option<&str> {
+    match expr {
+        SExpr::Ident(s, false) if s.starts_with("#:") => Some(&s[2..]),
+        _ => None,
+    }
+}
+
+/// Represents the keyword arguments passed to a function, by name.
+type KeywordArgs = Vec<(String, Value)>;
+
+/// Evaluates each of the specified argument expressions of a function call,
+/// separating the positional arguments from the keyword arguments, which are
+/// passed in the form: #:name value
+fn eval_call_args(exprs: &[SExpr], env: &mut Environment) -> Result<(Vec<Value>, KeywordArgs)> {
+    let mut args = Vec::<Value>::with_capacity(exprs.len());
+    let mut kwargs = KeywordArgs::new();
+    let mut iter = exprs.iter();
+    while let Some(expr) = iter.next() {
+        match keyword_name(expr) {
+            Some(name) => {
+                let arg = match iter.next() {
+                    Some(arg) => arg,
+                    None => return Err(format!("Expected a value after #:{}.", name).into()),
+                };
+                kwargs.push((name.to_string(), eval_arg(arg, env)?));
             }
-            format!("{}\n{}", s, why).into()
-        })
+            None => args.push(eval_arg(expr, env)?),
+        }
+    }
+    Ok((args, kwargs))
+}
+
+/// Binds the specified arguments to the parameters of the specified function
+/// in the current scope. Optional and keyword parameters that are not passed
+/// are bound to their default values, which are evaluated in the current
+/// scope.
+fn bind_args(
+    lambda: &Lambda,
+    args: &[Value],
+    kwargs: KeywordArgs,
+    env: &mut Environment,
+) -> Result<()> {
+    // Captured bindings are entered first, so the parameters shadow them
+    env.enter_captures(lambda.captures.clone());
+
+    let (params, variadic) = (&lambda.params, lambda.variadic);
+
+    // The variadic parameter and optional parameters do not need to be filled
+    let required = if variadic { params.len() - 1 } else { params.len() };
+    let positional = required + lambda.optional.len();
+    let args_len = args.len();
+
+    // Check arity
+    if args_len < required || !variadic && args_len > positional {
+        let name = lambda.name.as_deref();
+        return Err(arity_mismatch(name, &lambda.params_expr(), args_len));
+    }
+
+    for i in 0..required {
+        env.define(params[i].clone(), args[i].clone());
+    }
+
+    // Optional parameters that are not filled are bound to their defaults
+    for (i, (name, default)) in lambda.optional.iter().enumerate() {
+        let val = match args.get(required + i) {
+            Some(val) => val.clone(),
+            None => default.eval(env)?,
+        };
+        env.define(name.clone(), val);
+    }
+
+    if variadic {
+        let rest = args.get(positional..).unwrap_or(&[]);
+        env.define(params[required].clone(), Value::List(rest.into()));
+    }
+
+    let mut passed = Vec::<String>::with_capacity(kwargs.len());
+    for (name, val) in kwargs {
+        if !lambda.keywords.iter().any(|(keyword, _)| *keyword == name) {
+            return Err(format!("Unknown keyword argument #:{}.", name).into());
+        } else if passed.contains(&name) {
+            return Err(format!("Keyword argument #:{} was passed twice.", name).into());
+        }
+        env.define(name.clone(), val);
+        passed.push(name);
+    }
+    for (name, default) in &lambda.keywords {
+        if !passed.contains(name) {
+            let val = default.eval(env)?;
+            env.define(name.clone(), val);
+        }
+    }
+    Ok(())
+}
+
+/// Calls the specified function or intrinsic with the specified arguments. The
+/// caller is the expression recorded for the call in error backtraces, and is
+/// only used when calling a function. Macros operate on unevaluated
+/// expressions, so they cannot be called.
+pub fn call_fn(
+    caller: &SExpr,
+    func: &Value,
+    args: &[Value],
+    env: &mut Environment,
+) -> Result<Value> {
+    match func {
+        Value::Func(..) | Value::Memoized(_) => eval_func(caller.clone(), func, args, env),
+        Value::Intrinsic(f) => f(env, args),
+        Value::Macro(_) => Err(macro_not_first_class(func)),
+        val => Err(not_a_function(val)),
     }
 }
 
@@ -118,49 +399,33 @@ pub fn eval_func(
     env: &mut Environment,
 ) -> Result<Value> {
     match func {
-        &Value::Func(ref params, ref body, variadic) => {
+        Value::Func(lambda) => {
+            let depth = env.depth();
+            if env.is_tracing() {
+                // The function is not called by name
+                let name = SExpr::Ident("lambda".to_string(), false);
+                trace_call(env, depth, &name, args, &[]);
+            }
             env.enter_scope(caller);
-
-            let params_len = params.len();
-            let args_len = args.len();
-
-            // Check arity
-            if variadic {
-                // Variadic parameter does not need to be filled
-                if params_len - 1 > args_len {
-                    return Err(arity_at_least(params_len - 1, args_len));
-                }
-            } else if params_len != args_len {
-                return Err(arity_exact(params_len, args_len));
+            let res = bind_args(lambda, args, vec![], env).and_then(|_| lambda.body.eval(env));
+            env.truncate_scopes(depth);
+            if let Ok(ref val) = res {
+                env.trace(depth, format!("< {:?}", val));
             }
-
-            if variadic {
-                for i in 0..params_len - 1 {
-                    let val = &args[i];
-                    env.define(params[i].clone(), val.clone());
-                }
-
-                let variadic_arg = if args_len >= params_len {
-                    let mut variadic_arg = Vec::<Value>::with_capacity(args_len - params_len + 1);
-                    for extra_arg in &args[params_len - 1..] {
-                        variadic_arg.push(extra_arg.clone());
-                    }
-                    variadic_arg
-                } else {
-                    Vec::new()
-                };
-
-                env.define(params[params_len - 1].clone(), Value::List(variadic_arg));
-            } else {
-                for i in 0..params_len {
-                    let val = &args[i];
-                    env.define(params[i].clone(), val.clone());
-                }
+            res
+        }
+        Value::Memoized(memo) => {
+            let cached = memo.cache.borrow().get(args).cloned();
+            if let Some(val) = cached {
+                return Ok(val);
             }
 
-            let res = body.eval(env)?;
-            env.exit_scope();
-            Ok(res)
+            let val = match &memo.func {
+                Value::Intrinsic(func) => func(env, args)?,
+                func => eval_func(caller, func, args, env)?,
+            };
+            memo.cache.borrow_mut().insert(args.to_vec(), val.clone());
+            Ok(val)
         }
         _ => Err(not_a_function(func)),
     }
@@ -1,6 +1,252 @@
 use super::*;
 use interpreter::SExpr;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::rc::Rc;
+
+/// The name of the symbol which begins a quotation.
+const QUOTE: &str = "quote";
+
+/// The prefix of the hidden parameters to which destructured arguments are
+/// bound. As the reader rejects it, no user binding may begin with it.
+pub const HIDDEN_PARAM: &str = "#arg";
+
+/// Represents a function defined in lisp.
+#[derive(Clone, PartialEq)]
+pub struct Lambda {
+    /// The names of the required positional parameters. If the function is
+    /// variadic, the final parameter is bound to a list of all remaining
+    /// arguments instead.
+    pub params: Vec<String>,
+    pub variadic: bool,
+
+    /// The names of the optional positional parameters, which follow the
+    /// required parameters, along with the expressions that produce their
+    /// default values.
+    pub optional: Vec<(String, SExpr)>,
+
+    /// The names of the keyword parameters, along with the expressions that
+    /// produce their default values.
+    pub keywords: Vec<(String, SExpr)>,
+    pub body: SExpr,
+
+    /// The name with which the function was defined, if any.
+    pub name: Option<String>,
+
+    /// The scopes in which the function was created, whose bindings remain
+    /// visible to its body.
+    pub captures: Captures,
+}
+
+impl Lambda {
+    /// Produces a function with the specified positional parameters and body.
+    pub fn new(params: Vec<String>, body: SExpr, variadic: bool) -> Lambda {
+        Lambda {
+            params,
+            variadic,
+            optional: vec![],
+            keywords: vec![],
+            body,
+            name: None,
+            captures: Captures::default(),
+        }
+    }
+
+    /// Produces the parameter list of the function, in the form accepted by
+    /// `lambda`: (params ... #:opt name default ... rest... #:key name default ...)
+    pub fn params_expr(&self) -> SExpr {
+        let required = if self.variadic { self.params.len() - 1 } else { self.params.len() };
+        let mut exprs: Vec<SExpr> = self.params[..required]
+            .iter()
+            .map(|param| match self.pattern(param) {
+                Some(pattern) => pattern.clone(),
+                None => SExpr::Ident(param.clone(), false),
+            })
+            .collect();
+        for (name, default) in &self.optional {
+            exprs.push(SExpr::Ident("#:opt".to_string(), false));
+            exprs.push(SExpr::Ident(name.clone(), false));
+            exprs.push(default.clone());
+        }
+        if self.variadic {
+            exprs.push(SExpr::Ident(self.params[required].clone(), true));
+        }
+        for (name, default) in &self.keywords {
+            exprs.push(SExpr::Ident("#:key".to_string(), false));
+            exprs.push(SExpr::Ident(name.clone(), false));
+            exprs.push(default.clone());
+        }
+        SExpr::List(exprs)
+    }
+
+    /// Produces the body of the function as it was written, without the `let`
+    /// which destructures its patterned parameters.
+    pub fn body_expr(&self) -> &SExpr {
+        match self.destructuring() {
+            Some((_, body)) => body,
+            None => &self.body,
+        }
+    }
+
+    /// Produces the pattern that the hidden parameter with the specified name
+    /// is destructured into, if it is one.
+    fn pattern(&self, param: &str) -> Option<&SExpr> {
+        if !param.starts_with(HIDDEN_PARAM) {
+            return None;
+        }
+        let bindings = self.destructuring()?.0;
+        bindings.iter().find_map(|binding| match binding {
+            SExpr::List(pair) if pair.len() == 2 => match &pair[1] {
+                SExpr::Ident(name, false) if name == param => Some(&pair[0]),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    /// Produces the bindings and inner body of the `let` which `lambda` wraps
+    /// around the body of a function with patterned parameters.
+    fn destructuring(&self) -> Option<(&[SExpr], &SExpr)> {
+        if !self.params.iter().any(|param| param.starts_with(HIDDEN_PARAM)) {
+            return None;
+        }
+        match &self.body {
+            SExpr::List(exprs) if exprs.len() == 3 => match &exprs[1] {
+                SExpr::List(bindings) => Some((bindings, &exprs[2])),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Lambda {
+    /// Displays the function in the form: #<function name>, or if it has no
+    /// name, in the form: (lambda (params ...) body)
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "#<function {}>", name),
+            None => write!(f, "(lambda {} {})", self.params_expr(), self.body_expr()),
+        }
+    }
+}
+
+/// Represents a function whose outputs are cached by the arguments passed to
+/// it, so that it is only called once for any equal arguments.
+pub struct Memo {
+    pub func: Value,
+    pub cache: RefCell<HashMap<Vec<Value>, Value>>,
+}
+
+impl Memo {
+    /// Produces a memoized version of the specified function, with an empty
+    /// cache.
+    pub fn new(func: Value) -> Memo {
+        Memo {
+            func,
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+/// Represents a mutable cons cell, the building block of mutable lists. Unlike
+/// an immutable list, the cell is shared rather than copied, so that mutating
+/// it through one reference is observed through all others. As cells are
+/// reference counted, cells which refer to one another in a cycle are never
+/// freed.
+pub struct Pair {
+    pub car: Value,
+    pub cdr: Value,
+}
+
+impl Drop for Pair {
+    /// Drops the rest of the list one cell at a time, as dropping each cell
+    /// within the previous one could overflow the stack for a long list.
+    fn drop(&mut self) {
+        let mut cdr = mem::replace(&mut self.cdr, Value::nil());
+        while let Value::MPair(pair) = cdr {
+            cdr = match Rc::try_unwrap(pair) {
+                Ok(pair) => mem::replace(&mut pair.borrow_mut().cdr, Value::nil()),
+                // The rest of the list is still referred to elsewhere
+                Err(_) => break,
+            };
+        }
+    }
+}
+
+thread_local! {
+    /// The pairs of the mutable lists currently being written, so that a list
+    /// which contains itself is not written forever.
+    static WRITING: RefCell<HashSet<*const RefCell<Pair>>> = RefCell::new(HashSet::new());
+}
+
+/// Writes the mutable list starting at the specified pair in the form:
+/// {a b c ...}, or {a b . c} if it does not end in the empty list. If the
+/// list is cyclic, it ends in `...` once a pair repeats, and a list contained
+/// within itself is written as {...}.
+fn write_pairs(
+    f: &mut fmt::Formatter,
+    pair: &Rc<RefCell<Pair>>,
+    write: fn(&Value, &mut fmt::Formatter) -> fmt::Result,
+) -> fmt::Result {
+    if WRITING.with(|writing| writing.borrow().contains(&Rc::as_ptr(pair))) {
+        return write!(f, "{{...}}");
+    }
+
+    let mut written = vec![];
+    let res = write_cells(f, pair, write, &mut written);
+    WRITING.with(|writing| {
+        let mut writing = writing.borrow_mut();
+        for pair in written {
+            writing.remove(&pair);
+        }
+    });
+    res
+}
+
+/// Writes the cells of the mutable list starting at the specified pair,
+/// recording each pair as it is written.
+fn write_cells(
+    f: &mut fmt::Formatter,
+    pair: &Rc<RefCell<Pair>>,
+    write: fn(&Value, &mut fmt::Formatter) -> fmt::Result,
+    written: &mut Vec<*const RefCell<Pair>>,
+) -> fmt::Result {
+    let is_writing = |pair: &Rc<RefCell<Pair>>| {
+        WRITING.with(|writing| writing.borrow().contains(&Rc::as_ptr(pair)))
+    };
+    let mut pair = pair.clone();
+    write!(f, "{{")?;
+    loop {
+        written.push(Rc::as_ptr(&pair));
+        WRITING.with(|writing| writing.borrow_mut().insert(Rc::as_ptr(&pair)));
+        let cdr = {
+            let pair = pair.borrow();
+            write(&pair.car, f)?;
+            pair.cdr.clone()
+        };
+        match cdr {
+            Value::MPair(ref next) if is_writing(next) => {
+                write!(f, " ...")?;
+                break;
+            }
+            Value::MPair(next) => {
+                write!(f, " ")?;
+                pair = next;
+            }
+            Value::List(ref list) if list.is_empty() => break,
+            cdr => {
+                write!(f, " . ")?;
+                write(&cdr, f)?;
+                break;
+            }
+        }
+    }
+    write!(f, "}}")
+}
 
 #[derive(Clone)]
 pub enum Value {
@@ -8,11 +254,17 @@ pub enum Value {
     Bool(bool),
     Str(String),
     Symbol(String, bool),
-    List(Vec<Value>),
-    Func(Vec<String>, SExpr, bool),
+    List(SharedList),
+    Vector(Vec<Value>),
+    Func(Lambda),
     Intrinsic(Intrinsic),
+    Memoized(Rc<Memo>),
     Macro(Macro),
     Struct(String, Vec<Value>),
+    Values(Vec<Value>),
+    Iter(SharedIter),
+    IterEnd,
+    MPair(Rc<RefCell<Pair>>),
 }
 
 impl From<SExpr> for Value {
@@ -26,8 +278,12 @@ impl From<SExpr> for Value {
             SExpr::List(vals) => {
                 Value::List(vals.into_iter().map(|expr| Value::from(expr)).collect())
             }
-            SExpr::Nil => Value::List(vec![]),
-            SExpr::Quote(expr) => (*expr).into(),
+            SExpr::Nil => Value::List(SharedList::default()),
+            // Quoted data is represented as in lisp, by the list (quote expr)
+            SExpr::Quote(expr) => Value::List(SharedList::from(vec![
+                Value::Symbol(QUOTE.to_string(), false),
+                (*expr).into(),
+            ])),
         }
     }
 }
@@ -40,7 +296,16 @@ impl Into<SExpr> for Value {
             Value::Bool(n) => SExpr::Bool(n),
             Value::Str(s) => SExpr::Str(s),
             Value::Symbol(s, v) => SExpr::Ident(s, v),
-            Value::List(vals) => SExpr::List(vals.into_iter().map(|expr| expr.into()).collect()),
+            Value::List(ref vals) if self.quoted().is_some() => {
+                SExpr::Quote(Box::new(vals[1].clone().into()))
+            }
+            Value::List(vals) => SExpr::List(vals.iter().map(|expr| expr.clone().into()).collect()),
+            Value::Vector(vals) => {
+                let mut exprs: Vec<SExpr> = Vec::with_capacity(vals.len() + 1);
+                exprs.push(SExpr::Ident("vector".to_string(), false));
+                exprs.extend(vals.into_iter().map(|expr| expr.into()));
+                SExpr::List(exprs)
+            }
             Value::Struct(ref name, ref fields) => {
                 let mut exprs: Vec<SExpr> = Vec::with_capacity(fields.len() + 1);
                 exprs.push(SExpr::Ident(format!("make-{}", name), false));
@@ -49,27 +314,50 @@ impl Into<SExpr> for Value {
                 }
                 SExpr::List(exprs)
             }
-            _ => panic!("Evaluating other values is not yet supported."),
+            Value::Values(vals) => {
+                let mut exprs: Vec<SExpr> = Vec::with_capacity(vals.len() + 1);
+                exprs.push(SExpr::Ident("values".to_string(), false));
+                exprs.extend(vals.into_iter().map(|expr| expr.into()));
+                SExpr::List(exprs)
+            }
+            Value::Func(lambda) => SExpr::List(vec![
+                SExpr::Ident("lambda".to_string(), false),
+                lambda.params_expr(),
+                lambda.body_expr().clone(),
+            ]),
+            Value::Intrinsic(_) => SExpr::Ident("<function>".to_string(), false),
+            Value::Memoized(memo) => SExpr::List(vec![
+                SExpr::Ident("memoize".to_string(), false),
+                memo.func.clone().into(),
+            ]),
+            Value::Macro(_) => SExpr::Ident("<procedure>".to_string(), false),
+            Value::Iter(_) => SExpr::Ident("<iterator>".to_string(), false),
+            Value::IterEnd => SExpr::Ident("iter-end".to_string(), false),
+            Value::MPair(_) => SExpr::Ident("<mutable list>".to_string(), false),
         }
     }
 }
 
 impl fmt::Display for Value {
     /// Displays the `Value` in a human-readable format based on the type:
-    /// * *num:* Displays as is.
+    /// * *num:* Displays in the current float format.
     /// * *bool:* Displays as either `true` or `false`.
     /// * *str:* Displays the string as is.
     /// * *symbol:* Displays the symbol as is.
-    /// * *list:* Displays the list in the form: (a b c ...)
+    /// * *list:* Displays the list in the form: (a b c ...), or 'a if it is a
+    ///   quotation
+    /// * *vector:* Displays the vector in the form: #(a b c ...)
     /// * *lambda:* Displays the lambda in the form: (lambda (params ...) body)
     /// * *struct:* Displays the struct in the form: (make-{struct} fields ...)
+    /// * *values:* Displays each value on its own line.
+    /// * *mutable list:* Displays the list in the form: {a b c ...}
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::Value::*;
         use color::*;
         match self {
             // num
             Num(n) => {
-                let out = n.to_string();
+                let out = FloatFormat::current().format(*n);
                 write!(f, "{}", number(out))
             }
 
@@ -97,6 +385,9 @@ impl fmt::Display for Value {
                 Ok(())
             }
 
+            // 'a
+            List(_) if self.quoted().is_some() => write!(f, "'{}", self.quoted().unwrap()),
+
             // (a b c ...)
             List(exps) => {
                 if !exps.is_empty() {
@@ -114,32 +405,39 @@ impl fmt::Display for Value {
                 }
             }
 
-            // (lambda (params ...) body)
-            Func(args, body, variadic) => {
-                // Write lambda
-                write!(f, "(lambda (")?;
-
-                // Write args
-                if !args.is_empty() {
-                    for i in 0..args.len() - 1 {
-                        write!(f, "{} ", &args[i])?;
-                    }
-                    write!(f, "{}", &args[args.len() - 1])?;
-                    if *variadic {
-                        write!(f, "...")?;
+            // #(a b c ...)
+            Vector(vals) => {
+                write!(f, "#(")?;
+                for (i, val) in vals.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
                     }
+                    write!(f, "{}", val)?;
                 }
-
-                // Write body
-                write!(f, ") {})", body)
+                write!(f, ")")
             }
 
+            // (lambda (params ...) body)
+            Func(lambda) => write!(f, "{}", lambda),
+
             // <function>
             Intrinsic(_) => write!(f, "<function>"),
 
+            // (memoize func)
+            Memoized(memo) => write!(f, "(memoize {})", memo.func),
+
             // <procedure>
             Macro(_) => write!(f, "<procedure>"),
 
+            // <iterator>
+            Iter(_) => write!(f, "<iterator>"),
+
+            // iter-end
+            IterEnd => write!(f, "iter-end"),
+
+            // {a b c ...}
+            MPair(pair) => write_pairs(f, pair, fmt::Display::fmt),
+
             // (make-{struct} {field1} ...)
             Struct(name, values) => {
                 // Write opening bracket
@@ -153,49 +451,276 @@ impl fmt::Display for Value {
                 // Write closing bracket
                 write!(f, ")")
             }
+
+            // a
+            // b ...
+            Values(vals) => {
+                for (i, val) in vals.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", val)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
-impl Into<Value> for f64 {
+impl fmt::Debug for Value {
+    /// Displays the `Value` without color, in a form that distinguishes the
+    /// types from one another:
+    /// * *str:* Displays the string in quotes, with special characters escaped.
+    /// * *symbol:* Displays the symbol with a leading quote: 'symbol
+    /// * *list:* Displays the list in the form: (a b c ...), or () if empty,
+    ///   or 'a if it is a quotation
+    /// * *intrinsic:* Displays as `<function>`.
+    /// * *macro:* Displays as `<procedure>`.
+    /// * *values:* Displays the values in the form: (values a b c ...)
+    ///
+    /// All other values display in the same form as with `Display`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Value::*;
+
+        fn write_all(f: &mut fmt::Formatter, vals: &[Value]) -> fmt::Result {
+            for (i, val) in vals.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "{:?}", val)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Num(n) => write!(f, "{}", FloatFormat::current().format(*n)),
+            Bool(b) => write!(f, "{}", b),
+            Str(s) => write!(f, "{:?}", s),
+            Symbol(s, v) => {
+                write!(f, "'{}", s)?;
+                if *v {
+                    write!(f, "...")?;
+                }
+                Ok(())
+            }
+            List(_) if self.quoted().is_some() => write!(f, "'{:?}", self.quoted().unwrap()),
+            List(vals) => {
+                write!(f, "(")?;
+                write_all(f, vals)?;
+                write!(f, ")")
+            }
+            Vector(vals) => {
+                write!(f, "#(")?;
+                write_all(f, vals)?;
+                write!(f, ")")
+            }
+            Func(lambda) => write!(f, "{}", lambda),
+            Intrinsic(_) => write!(f, "<function>"),
+            Memoized(memo) => write!(f, "(memoize {:?})", memo.func),
+            Macro(_) => write!(f, "<procedure>"),
+            Iter(_) => write!(f, "<iterator>"),
+            IterEnd => write!(f, "iter-end"),
+            MPair(pair) => write_pairs(f, pair, fmt::Debug::fmt),
+            Struct(name, vals) => {
+                write!(f, "(make-{}", name)?;
+                for val in vals {
+                    write!(f, " {:?}", val)?;
+                }
+                write!(f, ")")
+            }
+            Values(vals) => {
+                write!(f, "(values")?;
+                for val in vals {
+                    write!(f, " {:?}", val)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl Value {
+    /// Produces a num `Value`.
+    pub fn num(n: f64) -> Value {
+        Value::Num(n)
+    }
+
+    /// Produces a bool `Value`.
+    pub fn boolean(b: bool) -> Value {
+        Value::Bool(b)
+    }
+
+    /// Produces a str `Value`.
+    pub fn string<S: Into<String>>(s: S) -> Value {
+        Value::Str(s.into())
+    }
+
+    /// Produces a non-variadic symbol `Value` with the specified name.
+    pub fn symbol<S: Into<String>>(name: S) -> Value {
+        Value::Symbol(name.into(), false)
+    }
+
+    /// Produces a list `Value` containing the specified values.
+    pub fn list<I: IntoIterator<Item = Value>>(vals: I) -> Value {
+        Value::List(vals.into_iter().collect())
+    }
+
+    /// Produces a vector `Value` containing the specified values.
+    pub fn vector<I: IntoIterator<Item = Value>>(vals: I) -> Value {
+        Value::Vector(vals.into_iter().collect())
+    }
+
+    /// Produces the empty list.
+    pub fn nil() -> Value {
+        Value::List(SharedList::default())
+    }
+
+    /// Produces the quoted value if the value is a quotation, which is a list
+    /// of the form (quote value).
+    pub fn quoted(&self) -> Option<&Value> {
+        match self {
+            Value::List(vals) if vals.len() == 2 => match &vals[0] {
+                Value::Symbol(s, false) if s == QUOTE => Some(&vals[1]),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Determines whether or not the value is truthy. `#f` and the empty list
+    /// are falsy, and every other value is truthy.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::List(list) => !list.is_empty(),
+            _ => true,
+        }
+    }
+}
+
+impl From<f64> for Value {
     /// Converts the specified `f64` into a num `Value`.
-    fn into(self) -> Value {
-        Value::Num(self)
+    fn from(n: f64) -> Value {
+        Value::Num(n)
+    }
+}
+
+impl From<i64> for Value {
+    /// Converts the specified `i64` into a num `Value`.
+    fn from(n: i64) -> Value {
+        Value::Num(n as f64)
     }
 }
 
-impl Into<Value> for bool {
+impl From<bool> for Value {
     /// Converts the specified `bool` into a bool `Value`.
-    fn into(self) -> Value {
-        Value::Bool(self)
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
     }
 }
 
-impl Into<Value> for String {
+impl From<String> for Value {
     /// Converts the specified `String` into a str `Value`.
-    fn into(self) -> Value {
-        Value::Str(self)
+    fn from(s: String) -> Value {
+        Value::Str(s)
+    }
+}
+
+impl From<&str> for Value {
+    /// Converts the specified string slice into a str `Value`.
+    fn from(s: &str) -> Value {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    /// Converts the specified `Vec` into a list `Value`.
+    fn from(vals: Vec<Value>) -> Value {
+        Value::List(vals.into())
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = RLError;
+
+    /// Converts a num `Value` into an `f64`.
+    fn try_from(val: Value) -> Result<f64> {
+        match val {
+            Value::Num(n) => Ok(n),
+            val => Err(not_a_number(&val)),
+        }
+    }
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = RLError;
+
+    /// Converts a num `Value` with an integer value into an `i64`, provided
+    /// that it is within the range of an `i64`.
+    fn try_from(val: Value) -> Result<i64> {
+        // The bounds are exactly representable, as they are powers of two
+        let (min, max) = (i64::MIN as f64, -(i64::MIN as f64));
+        match val {
+            Value::Num(n) if n.fract() != 0.0 => Err(format!("{} is not an integer.", val).into()),
+            Value::Num(n) if n < min || n >= max => {
+                Err(format!("{} is out of the range of an integer.", val).into())
+            }
+            Value::Num(n) => Ok(n as i64),
+            val => Err(format!("{} is not an integer.", val).into()),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = RLError;
+
+    /// Converts a bool `Value` into a `bool`.
+    fn try_from(val: Value) -> Result<bool> {
+        match val {
+            Value::Bool(b) => Ok(b),
+            val => Err(format!("{} is not a bool.", val).into()),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = RLError;
+
+    /// Converts a str `Value` into a `String`.
+    fn try_from(val: Value) -> Result<String> {
+        match val {
+            Value::Str(s) => Ok(s),
+            val => Err(format!("{} is not a str.", val).into()),
+        }
+    }
+}
+
+impl TryFrom<Value> for Vec<Value> {
+    type Error = RLError;
+
+    /// Converts a list `Value` into a `Vec` of its elements.
+    fn try_from(val: Value) -> Result<Vec<Value>> {
+        match val {
+            Value::List(vals) => Ok(vals.to_vec()),
+            val => Err(format!("{} is not a list.", val).into()),
+        }
     }
 }
 
 use err::RLError;
-use errors::Result;
+use errors::{not_a_number, Result};
+use std::convert::TryFrom;
 
 /// Wrap the specified value in an `Ok`.
-pub fn ok<T>(val: T) -> Result<Value>
+pub fn ok<T, R>(val: T) -> Result<R>
 where
     T: Into<Value>,
+    R: From<Value>,
 {
-    Ok(val.into())
+    Ok(R::from(val.into()))
 }
 
-impl Into<Value> for Vec<Value> {
-    fn into(self) -> Value {
-        Value::List(self)
-    }
-}
-
-pub fn err<T>(msg: T) -> Result<Value>
+pub fn err<T, R>(msg: T) -> Result<R>
 where
     T: Into<RLError>,
 {
@@ -203,28 +728,30 @@ where
 }
 
 impl PartialEq for Value {
-    /// Compare the two values to one another for equality.
+    /// Compare the two values to one another for structural equality. Unlike
+    /// IEEE 754 equality, NaN is equal to itself, so that every value is equal
+    /// to itself. Zero remains equal to negative zero. Intrinsic functions,
+    /// macros, and memoized functions are only equal to themselves, and
+    /// functions defined in lisp are equal if they have the same definition
+    /// and captures.
     fn eq(&self, other: &Value) -> bool {
         use self::Value::*;
         match (self, other) {
-            (&Num(a), &Num(b)) => a == b,
+            (&Num(a), &Num(b)) => a == b || a.is_nan() && b.is_nan(),
             (&Bool(a), &Bool(b)) => a == b,
-            (&Str(ref a), &Str(ref b)) => a == b,
+            (Str(a), Str(b)) => a == b,
             (&Symbol(ref a, a_vec), &Symbol(ref b, b_vec)) => a == b && a_vec == b_vec,
-            (&List(ref a), &List(ref b)) => {
-                if a.len() == b.len() {
-                    for i in 0..a.len() {
-                        let (a_val, b_val) = (&a[i], &b[i]);
-                        if a_val != b_val {
-                            return false;
-                        }
-                    }
-                    true
-                } else {
-                    false
-                }
-            }
-            (&Struct(ref a_type, ref a_fields), &Struct(ref b_type, ref b_fields)) => {
+            (List(a), List(b)) => a[..] == b[..],
+            (Vector(a), Vector(b)) => a == b,
+            (Values(a), Values(b)) => a == b,
+            (Func(a), Func(b)) => a == b,
+            (&Intrinsic(a), &Intrinsic(b)) => a as usize == b as usize,
+            (&Macro(a), &Macro(b)) => a as usize == b as usize,
+            (Memoized(a), Memoized(b)) => Rc::ptr_eq(a, b),
+            (Iter(a), Iter(b)) => a.ptr_eq(b),
+            (IterEnd, IterEnd) => true,
+            (MPair(a), MPair(b)) => Rc::ptr_eq(a, b),
+            (Struct(a_type, a_fields), Struct(b_type, b_fields)) => {
                 let a_len = a_fields.len();
                 let b_len = b_fields.len();
                 if a_type == b_type && a_len == b_len {
@@ -243,3 +770,137 @@ impl PartialEq for Value {
         }
     }
 }
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    /// Hashes the value consistently with its structural equality, so that
+    /// equal values produce equal hashes. Only the kind of functions,
+    /// iterators, and mutable lists is hashed.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        use self::Value::*;
+        mem::discriminant(self).hash(state);
+        match self {
+            // Zero is equal to negative zero, and NaN is equal to itself
+            Num(n) if *n == 0.0 => 0.0f64.to_bits().hash(state),
+            Num(n) if n.is_nan() => f64::NAN.to_bits().hash(state),
+            Num(n) => n.to_bits().hash(state),
+            Bool(b) => b.hash(state),
+            Str(s) => s.hash(state),
+            Symbol(s, variadic) => {
+                s.hash(state);
+                variadic.hash(state);
+            }
+            List(list) => list[..].hash(state),
+            Vector(vals) | Values(vals) => vals.hash(state),
+            Struct(name, fields) => {
+                name.hash(state);
+                fields.hash(state);
+            }
+            Func(_) | Intrinsic(_) | Memoized(_) | Macro(_) | Iter(_) | IterEnd | MPair(_) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use self::Value::*;
+
+    #[test]
+    fn debug_format() {
+        let sym = |s: &str| Symbol(s.to_string(), false);
+        assert_eq!(format!("{:?}", Num(1.5)), "1.5");
+        assert_eq!(format!("{:?}", Bool(true)), "true");
+        assert_eq!(format!("{:?}", Str("a \"b\"".to_string())), "\"a \\\"b\\\"\"");
+        assert_eq!(format!("{:?}", sym("a")), "'a");
+        assert_eq!(format!("{:?}", Symbol("a".to_string(), true)), "'a...");
+        assert_eq!(format!("{:?}", List(SharedList::default())), "()");
+
+        let list: Value = vec![Num(1.0), sym("a"), Str("a".to_string())].into();
+        assert_eq!(format!("{:?}", list), "(1 'a \"a\")");
+        assert_eq!(format!("{:?}", Vector(vec![Num(1.0), Num(2.0)])), "#(1 2)");
+
+        let body = SExpr::List(vec![SExpr::Ident("+".to_string(), false)]);
+        let mut lambda = Lambda::new(vec!["a".to_string(), "b".to_string()], body, true);
+        assert_eq!(format!("{:?}", Func(lambda.clone())), "(lambda (a b...) (+))");
+        lambda.keywords.push(("c".to_string(), SExpr::Num(1.0)));
+        assert_eq!(format!("{:?}", Func(lambda)), "(lambda (a b... #:key c 1) (+))");
+
+        fn intrinsic(_: &mut Environment, _: &[Value]) -> FuncResult {
+            Ok(Num(0.0))
+        }
+        fn mac(_: &mut Environment, _: &[SExpr]) -> Result<Tail> {
+            Ok(Tail::Value(Num(0.0)))
+        }
+        assert_eq!(format!("{:?}", Intrinsic(intrinsic)), "<function>");
+        assert_eq!(format!("{:?}", Macro(mac)), "<procedure>");
+
+        let point = Struct("point".to_string(), vec![Num(1.0), Str("y".to_string())]);
+        assert_eq!(format!("{:?}", point), "(make-point 1 \"y\")");
+    }
+
+    #[test]
+    fn builders() {
+        assert!(Value::num(1.5) == Num(1.5));
+        assert!(Value::boolean(true) == Bool(true));
+        assert!(Value::string("a") == Str("a".to_string()));
+        assert!(Value::symbol("a") == Symbol("a".to_string(), false));
+        assert!(Value::nil() == List(SharedList::default()));
+
+        let list = Value::list(vec![Value::num(1.0), Value::string("b")]);
+        assert_eq!(format!("{:?}", list), "(1 \"b\")");
+        let vector = Value::vector((1..3).map(|n| Value::from(n as i64)));
+        assert_eq!(format!("{:?}", vector), "#(1 2)");
+    }
+
+    #[test]
+    fn conversions_in() {
+        assert!(Value::from(3i64) == Num(3.0));
+        assert!(Value::from(2.5) == Num(2.5));
+        assert!(Value::from(false) == Bool(false));
+        assert!(Value::from("s") == Str("s".to_string()));
+        assert!(Value::from("s".to_string()) == Str("s".to_string()));
+        let list = Value::from(vec![Value::from(1i64)]);
+        assert_eq!(format!("{:?}", list), "(1)");
+    }
+
+    #[test]
+    fn conversions_out() {
+        assert_eq!(f64::try_from(Num(2.5)).unwrap(), 2.5);
+        assert_eq!(i64::try_from(Num(-4.0)).unwrap(), -4);
+        assert!(i64::try_from(Num(0.5)).is_err());
+        assert_eq!(i64::try_from(Num(-9223372036854775808.0)).unwrap(), i64::MIN);
+        assert!(i64::try_from(Num(9223372036854775808.0)).is_err());
+        assert!(i64::try_from(Num(1e300)).is_err());
+        assert!(i64::try_from(Num(f64::INFINITY)).is_err());
+        assert!(i64::try_from(Num(f64::NAN)).is_err());
+        assert!(bool::try_from(Bool(true)).unwrap());
+        assert_eq!(String::try_from(Value::from("s")).unwrap(), "s");
+        assert!(String::try_from(Num(1.0)).is_err());
+
+        let vals = Vec::<Value>::try_from(Value::list(vec![Num(1.0), Num(2.0)])).unwrap();
+        assert!(vals == vec![Num(1.0), Num(2.0)]);
+        assert!(Vec::<Value>::try_from(Num(1.0)).is_err());
+    }
+
+    #[test]
+    fn float_formats() {
+        let num = Num(1234.5678);
+        let vals = Value::list(vec![Num(0.5), Num(2.0)]);
+        assert_eq!(format!("{:?} {:?}", num, vals), "1234.5678 (0.5 2)");
+
+        FloatFormat::set(FloatFormat::Fixed(2));
+        assert_eq!(format!("{:?} {:?}", num, vals), "1234.57 (0.50 2.00)");
+
+        FloatFormat::set(FloatFormat::Scientific(None));
+        assert_eq!(format!("{:?} {:?}", num, vals), "1.2345678e3 (5e-1 2e0)");
+
+        FloatFormat::set(FloatFormat::Scientific(Some(2)));
+        assert_eq!(format!("{:?} {:?}", num, vals), "1.23e3 (5.00e-1 2.00e0)");
+
+        FloatFormat::set(FloatFormat::Shortest);
+        assert_eq!(FloatFormat::current(), FloatFormat::Shortest);
+        assert_eq!(format!("{:?}", num), "1234.5678");
+    }
+}
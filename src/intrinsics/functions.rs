@@ -1,9 +1,14 @@
 use super::*;
 use Value::*;
-use parser::Parser;
-use std::io::{stdout, BufReader, Write};
+use parser::{ParseError, Parser};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, Read, Write};
+use std::rc::Rc;
+use std::slice;
 
-use std::process::exit;
+use std::process::{exit, Command};
 
 /// Represents the output of a function.
 type EvalResult = Result<Value>;
@@ -35,46 +40,94 @@ pub fn _exit(_: Env, args: Args) -> EvalResult {
 
 /// `print : A... -> nil`
 ///
-/// Prints the specified values to the standard output.
+/// Prints the specified values to the environment's output, which is the
+/// standard output unless redirected.
 pub fn _print(env: Env, args: Args) -> EvalResult {
     let out = _concat(env, args)?;
     match out {
         Str(s) => {
-            print!("{}", s);
-            stdout().flush().expect("Failed to flush stdout.");
+            env.write_output(&s)?;
             ok(nil())
         }
         _ => err("Concat failed to produce a string."),
     }
 }
 
+/// `set-trace! : bool -> nil`
+///
+/// Enables or disables tracing each function call and its return value to the
+/// environment's error output, which is the standard error unless redirected.
+pub fn _set_trace(env: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match args[0] {
+        Bool(tracing) => env.set_tracing(tracing),
+        ref val => return err(format!("{} is not a bool.", val)),
+    }
+    ok(nil())
+}
+
+/// The most decimal places with which nums may be displayed, which is enough
+/// to identify any num while keeping its displayed length bounded.
+const MAX_PRECISION: usize = 17;
+
+/// `set-float-format! : symbol -> nil`
+///
+/// `set-float-format! : symbol num -> nil`
+///
+/// Sets the format in which nums are displayed to `shortest`, which uses the
+/// fewest digits that identify each num, `fixed`, which uses the specified
+/// number of decimal places, or six by default, or `scientific`, which uses
+/// an exponent with the specified number of decimal places, or the fewest
+/// digits that identify each num by default. At most 17 decimal places may
+/// be specified. The format is shared by every environment on the current
+/// thread.
+pub fn _set_float_format(_: Env, args: Args) -> EvalResult {
+    let len = args.len();
+    if len < 1 {
+        return Err(arity_at_least(1, len));
+    } else if len > 2 {
+        return Err(arity_at_most(2, len));
+    }
+
+    let precision = match args.get(1) {
+        Some(Num(n)) => match to_count(*n)? {
+            precision if precision > MAX_PRECISION => {
+                return err(format!("{} is more than {} decimal places.", precision, MAX_PRECISION))
+            }
+            precision => Some(precision),
+        },
+        Some(arg) => return Err(not_a_number(arg)),
+        None => None,
+    };
+    let format = match (&args[0], precision) {
+        (Symbol(s, false), None) if s == "shortest" => FloatFormat::Shortest,
+        (Symbol(s, false), precision) if s == "fixed" => {
+            FloatFormat::Fixed(precision.unwrap_or(6))
+        }
+        (Symbol(s, false), precision) if s == "scientific" => FloatFormat::Scientific(precision),
+        (val, _) => return err(format!("{} is not a float format.", val)),
+    };
+    FloatFormat::set(format);
+    ok(nil())
+}
+
 /// `println : A... -> nil`
 ///
-/// Prints the specified values, followed by a newline to the standard output.
+/// Prints the specified values, followed by a newline, to the environment's
+/// output, which is the standard output unless redirected.
 pub fn _println(env: Env, args: Args) -> EvalResult {
     let out = _concat(env, args)?;
     match out {
-        Str(s) => {
-            println!("{}", s);
+        Str(mut s) => {
+            s.push('\n');
+            env.write_output(&s)?;
             ok(nil())
         }
         _ => err("Concat failed to produce a string."),
     }
 }
 
-/// `begin : A... -> A`
-///
-/// Produces the final values of the specified values. In practice, this
-/// function evaluates all statements provided and produces the final
-/// value.
-pub fn _begin(_: Env, args: Args) -> EvalResult {
-    if args.is_empty() {
-        Ok(nil())
-    } else {
-        Ok(args[args.len() - 1].clone())
-    }
-}
-
 /// `+ : num... -> num`
 ///
 /// Produces the sum of 0 and the specified nums.
@@ -163,18 +216,61 @@ pub fn _div(_: Env, args: Args) -> EvalResult {
     }
 }
 
-/// Produces the modulo of the two specified `f64`s.
+/// Produces the remainder of the truncated division of the two specified
+/// `f64`s.
 fn modulo(x: f64, y: f64) -> f64 {
     x % y
 }
 
 /// `modulo : num num -> num`
 ///
-/// Produces the modulo of the two specified nums.
+/// Produces the remainder of dividing the first num by the second, where the
+/// quotient is truncated toward zero. The result has the sign of the dividend,
+/// such that `(modulo -7 3)` is -1. See `mod` for floored semantics.
 pub fn _modulo(_: Env, args: Args) -> EvalResult {
     binary_fn(args, modulo)
 }
 
+/// Produces the remainder of the floored division of the two specified
+/// `f64`s.
+fn floored_modulo(x: f64, y: f64) -> f64 {
+    let rem = x % y;
+    if rem != 0.0 && (rem < 0.0) != (y < 0.0) {
+        rem + y
+    } else {
+        rem
+    }
+}
+
+/// `mod : num num -> num`
+///
+/// Produces the remainder of dividing the first num by the second, where the
+/// quotient is floored. The result has the sign of the divisor, such that
+/// `(mod -7 3)` is 2. See `modulo` for truncated semantics.
+pub fn _mod(_: Env, args: Args) -> EvalResult {
+    binary_fn(args, floored_modulo)
+}
+
+/// `divmod : num num -> (values num num)`
+///
+/// Produces both the floored quotient of the two specified nums and the
+/// remainder, such that the remainder has the same sign as the divisor.
+pub fn _divmod(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    match (&args[0], &args[1]) {
+        (&Num(x), &Num(y)) => {
+            if y == 0.0 {
+                return err("Cannot divide by zero.");
+            }
+            let quotient = (x / y).floor();
+            let remainder = x - y * quotient;
+            ok(Values(vec![Num(quotient), Num(remainder)]))
+        }
+        (x, y) => err(format!("Expected (num num), found ({} {}).", x, y)),
+    }
+}
+
 /// `sqrt : num -> num`
 ///
 /// Produces the square root of the specified num.
@@ -182,6 +278,31 @@ pub fn _sqrt(_: Env, args: Args) -> EvalResult {
     unary_fn(args, f64::sqrt)
 }
 
+/// `isqrt : num -> num`
+///
+/// Produces the integer square root of the specified non-negative integer,
+/// which is the largest integer whose square does not exceed it. Unlike
+/// `(floor (sqrt n))`, the result is exact for large integers.
+pub fn _isqrt(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    let n = match &args[0] {
+        &Num(n) if n.fract() == 0.0 && n >= 0.0 && n < u64::MAX as f64 => n as u64,
+        arg => return err(format!("Expected a non-negative integer, found {}.", arg)),
+    };
+
+    // The float estimate may be off by one in either direction, so correct it
+    // using exact integer arithmetic
+    let mut root = (n as f64).sqrt() as u64;
+    while root.checked_mul(root).is_none_or(|sq| sq > n) {
+        root -= 1;
+    }
+    while (root + 1).checked_mul(root + 1).is_some_and(|sq| sq <= n) {
+        root += 1;
+    }
+    ok(root as f64)
+}
+
 /// `log : num num -> num`
 ///
 /// Produces the logarithm of the first specified num, using the second
@@ -205,6 +326,43 @@ pub fn _pow(_: Env, args: Args) -> EvalResult {
     binary_fn(args, f64::powf)
 }
 
+/// `expt : num num -> num`
+///
+/// Produces the num equal to the first num raised to the power of the second
+/// num. If the base is an integer and the exponent is a non-negative integer,
+/// the power is computed exactly, and it is an error for it to exceed the
+/// range of a 64-bit integer, or for it not to be exactly representable as a
+/// num. Otherwise, it is computed as with `pow`.
+pub fn _expt(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    let (base, exp) = match (&args[0], &args[1]) {
+        (&Num(base), &Num(exp)) => (base, exp),
+        (base, exp) => return err(format!("Expected (num num), found ({} {}).", base, exp)),
+    };
+
+    let is_int = |x: f64| x.fract() == 0.0 && x.abs() < i64::MAX as f64;
+    if !is_int(base) || !is_int(exp) || exp < 0.0 || base.abs() <= 1.0 {
+        return ok(base.powf(exp));
+    }
+
+    let overflow = || format!("{} raised to the power of {} overflows.", Num(base), Num(exp));
+    if exp > f64::from(u32::MAX) {
+        return err(overflow());
+    }
+    let n = match (base as i64).checked_pow(exp as u32) {
+        Some(n) => n,
+        None => return err(overflow()),
+    };
+
+    // Nums are floats, so large powers may be rounded when stored
+    if n as f64 as i128 != i128::from(n) {
+        let (base, exp) = (Num(base), Num(exp));
+        return err(format!("{} raised to the power of {} is not exact as a num.", base, exp));
+    }
+    ok(n)
+}
+
 /// Converts a slice of values and a function taking one `f64` into a
 /// `Result<Value, String`. It checks that the number of arguments is equal to
 /// one.
@@ -301,16 +459,65 @@ pub fn _atan2(_: Env, args: Args) -> EvalResult {
 pub fn load_trig_fns(env: Env) {
     use super::Intrinsics;
 
-    env.define_intrinsic("sin", _sin);
-    env.define_intrinsic("cos", _cos);
-    env.define_intrinsic("tan", _tan);
-    env.define_intrinsic("csc", _csc);
-    env.define_intrinsic("sec", _sec);
-    env.define_intrinsic("cot", _cot);
-    env.define_intrinsic("asin", _asin);
-    env.define_intrinsic("acos", _acos);
-    env.define_intrinsic("atan", _atan);
-    env.define_intrinsic("atan2", _atan2);
+    env.define_intrinsic(
+        "sin",
+        _sin,
+        "sin : num -> num\n\
+         Produces the sine of the specified num.",
+    );
+    env.define_intrinsic(
+        "cos",
+        _cos,
+        "cos : num -> num\n\
+         Produces the cosine of the specified num.",
+    );
+    env.define_intrinsic(
+        "tan",
+        _tan,
+        "tan : num -> num\n\
+         Produces the tangent of the specified num.",
+    );
+    env.define_intrinsic(
+        "csc",
+        _csc,
+        "csc : num -> num\n\
+         Produces the cosecant of the specified num.",
+    );
+    env.define_intrinsic(
+        "sec",
+        _sec,
+        "sec : num -> num\n\
+         Produces the secant of the specified num.",
+    );
+    env.define_intrinsic(
+        "cot",
+        _cot,
+        "cot : num -> num\n\
+         Produces the cotangent of the specified num.",
+    );
+    env.define_intrinsic(
+        "asin",
+        _asin,
+        "asin : num -> num\n\
+         Produces the inverse sine of the specified num.",
+    );
+    env.define_intrinsic(
+        "acos",
+        _acos,
+        "acos : num -> num\n\
+         Produces the inverse cosine of the specified num.",
+    );
+    env.define_intrinsic(
+        "atan",
+        _atan,
+        "atan : num -> num\n\
+         Produces the inverse tangent of the specified num.",
+    );
+    env.define_intrinsic(
+        "atan2",
+        _atan2,
+        "atan2 : num num -> num",
+    );
 }
 
 /// `num? : A -> bool`
@@ -382,17 +589,48 @@ pub fn _is_lambda(_: Env, args: Args) -> EvalResult {
     match &args[0] {
         Intrinsic(_) => ok(true),
         Func(..) => ok(true),
+        Memoized(_) => ok(true),
         _ => ok(false),
     }
 }
 
 pub fn load_checks(env: Env) {
-    env.define_intrinsic("num?", _is_num);
-    env.define_intrinsic("bool?", _is_num);
-    env.define_intrinsic("str?", _is_str);
-    env.define_intrinsic("symbol?", _is_symbol);
-    env.define_intrinsic("cons?", _is_cons);
-    env.define_intrinsic("lambda?", _is_lambda);
+    env.define_intrinsic(
+        "num?",
+        _is_num,
+        "num? : A -> bool\n\
+         Determines whether or not the specified value is a num.",
+    );
+    env.define_intrinsic(
+        "bool?",
+        _is_num,
+        "bool? : A -> bool\n\
+         Determines whether or not the specified value is a bool.",
+    );
+    env.define_intrinsic(
+        "str?",
+        _is_str,
+        "str? : A -> bool\n\
+         Determines whether or not the specified value is a str.",
+    );
+    env.define_intrinsic(
+        "symbol?",
+        _is_symbol,
+        "symbol? : A -> bool\n\
+         Determines whether or not the specified value is a symbol.",
+    );
+    env.define_intrinsic(
+        "cons?",
+        _is_cons,
+        "cons? : A -> bool\n\
+         Determines whether or not the specified value is a list.",
+    );
+    env.define_intrinsic(
+        "lambda?",
+        _is_lambda,
+        "lambda? : A -> bool\n\
+         Determines whether or not the specified value is a function.",
+    );
 }
 
 /// `list : A... -> [A]`
@@ -421,7 +659,7 @@ pub fn _cons(_: Env, args: Args) -> EvalResult {
 
             ok(new_list)
         }
-        (car, _) => err(format!("{} is not a list.", car)),
+        (_, cdr) => err(format!("Second argument to cons, {}, is not a list.", cdr)),
     }
 }
 
@@ -435,40 +673,134 @@ pub fn _car(_: Env, args: Args) -> EvalResult {
         List(vals) => {
             let len = vals.len();
             if len == 0 {
-                err(format!("Cannot call car on an empty list."))
+                err("Cannot call car on an empty list.")
             } else {
                 ok(vals[0].clone())
             }
         }
+        MPair(pair) => ok(pair.borrow().car.clone()),
         list => err(format!("{} is not a list.", list)),
     }
 }
 
 /// `cdr : [A] -> A`
 ///
-/// Produces the rest of the specified list after the first element.
+/// Produces the rest of the specified list after the first element. The rest
+/// of the list shares its elements with the specified list, rather than
+/// copying them.
 pub fn _cdr(_: Env, args: Args) -> EvalResult {
     check_arity(1, args.len())?;
 
     match &args[0] {
         List(vals) => {
-            let len = vals.len();
-            if len == 0 {
-                err(format!("Cannot call cdr on an empty list."))
+            if vals.is_empty() {
+                err("Cannot call cdr on an empty list.")
             } else {
-                let rest = &vals[1..];
-                let mut new_list = Vec::<Value>::with_capacity(len - 1);
-                for value in rest.iter() {
-                    new_list.push(value.clone());
-                }
-
-                ok(new_list)
+                Ok(List(vals.tail()))
             }
         }
+        MPair(pair) => ok(pair.borrow().cdr.clone()),
         list => err(format!("{} is not a list.", list)),
     }
 }
 
+/// Produces a new mutable cons cell with the specified car and cdr.
+fn mcons(car: Value, cdr: Value) -> Value {
+    MPair(Rc::new(RefCell::new(Pair { car, cdr })))
+}
+
+/// `mcons : A B -> mpair`
+///
+/// Produces a mutable cons cell with the specified car and cdr. Unlike a
+/// list, the cell is shared rather than copied, so that mutating it with
+/// `set-car!` or `set-cdr!` is observed through every reference to it. Cells
+/// which refer to one another in a cycle are never freed.
+pub fn _mcons(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+    ok(mcons(args[0].clone(), args[1].clone()))
+}
+
+/// `mlist : A... -> mpair`
+///
+/// Produces a mutable list of the specified values, made of mutable cons
+/// cells and ending in the empty list.
+pub fn _mlist(_: Env, args: Args) -> EvalResult {
+    let list = args
+        .iter()
+        .rev()
+        .fold(nil(), |cdr, car| mcons(car.clone(), cdr));
+    ok(list)
+}
+
+/// `mpair? : A -> bool`
+///
+/// Determines whether or not the specified value is a mutable cons cell.
+pub fn _is_mpair(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match &args[0] {
+        MPair(_) => ok(true),
+        _ => ok(false),
+    }
+}
+
+/// `set-car! : mpair A -> ()`
+///
+/// Replaces the car of the specified mutable cons cell with the specified
+/// value.
+pub fn _set_car(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    match &args[0] {
+        MPair(pair) => pair.borrow_mut().car = args[1].clone(),
+        pair => return err(format!("{} is not a mutable pair.", pair)),
+    }
+    ok(nil())
+}
+
+/// `set-cdr! : mpair A -> ()`
+///
+/// Replaces the cdr of the specified mutable cons cell with the specified
+/// value.
+pub fn _set_cdr(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    match &args[0] {
+        MPair(pair) => pair.borrow_mut().cdr = args[1].clone(),
+        pair => return err(format!("{} is not a mutable pair.", pair)),
+    }
+    ok(nil())
+}
+
+/// `mlist->list : mpair -> [A]`
+///
+/// Produces an immutable list containing the elements of the specified
+/// mutable list. It is an error for the mutable list to be cyclic, or not to
+/// end in the empty list.
+pub fn _mlist_to_list(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    let mut vals = vec![];
+    let mut seen = HashSet::new();
+    let mut cur = args[0].clone();
+    loop {
+        let next = match &cur {
+            MPair(pair) if !seen.insert(Rc::as_ptr(pair)) => {
+                return err(format!("{} is cyclic.", args[0]));
+            }
+            MPair(pair) => {
+                let pair = pair.borrow();
+                vals.push(pair.car.clone());
+                pair.cdr.clone()
+            }
+            List(list) if list.is_empty() => break,
+            _ => return err(format!("{} is not a proper mutable list.", args[0])),
+        };
+        cur = next;
+    }
+    ok(vals)
+}
+
 /// `len : [A] -> num`
 ///
 /// Determines the length of the specified list.
@@ -480,6 +812,33 @@ pub fn _len(_: Env, args: Args) -> EvalResult {
     }
 }
 
+/// The largest number of elements of a list produced by `make-list`.
+const MAX_LIST_LEN: usize = 1 << 24;
+
+/// `make-list : num A -> [A]`
+///
+/// Produces a list containing the specified number of copies of the specified
+/// value. The count must be a non-negative integer, no greater than 2^24.
+pub fn _make_list(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    let count = match &args[0] {
+        Num(n) => to_count(*n)?,
+        val => return err(not_a_number(val)),
+    };
+    let too_long = || format!("Cannot make a list of {} elements.", count);
+    if count > MAX_LIST_LEN {
+        return err(too_long());
+    }
+
+    let mut vals = Vec::new();
+    if vals.try_reserve_exact(count).is_err() {
+        return err(too_long());
+    }
+    vals.resize(count, args[1].clone());
+    ok(vals)
+}
+
 /// `nth : [A] num -> A`
 ///
 /// Produces the nth value of the specified list.
@@ -510,424 +869,2877 @@ pub fn _append(_: Env, args: Args) -> EvalResult {
 
     match (&args[0], &args[1]) {
         (value, &List(ref list)) => {
-            let mut buf = list.clone();
+            let mut buf = list.to_vec();
             buf.push(value.clone());
-            Ok(List(buf))
+            ok(buf)
         }
         (_, list) => err(format!("{} is not a list.", list)),
     }
 }
 
-/// `< : num num -> bool`
-///
-/// Determines whether or not the first argument is less than the second
-/// argument.
-pub fn _is_l(_: Env, args: Args) -> EvalResult {
-    check_arity(2, args.len())?;
-
-    let (a, b) = (&args[0], &args[1]);
-    cmp(a, b)
-        .map(|dif| Value::Bool(dif < 0.0))
-        .ok_or_else(|| format!("Cannot compare {} to {}.", a, b).into())
+/// Converts the specified num into an index into a collection of the
+/// specified length, producing an error if it is not a valid index.
+fn to_index(num: f64, len: usize) -> Result<usize> {
+    let index = num as usize;
+    if num != index as f64 {
+        Err("Index must be a non-negative integer.".into())
+    } else if index >= len {
+        Err(format!("Index {} is out of bounds for length {}.", index, len).into())
+    } else {
+        Ok(index)
+    }
 }
 
-/// `<= : num num -> bool`
+/// `vector : A... -> #[A]`
 ///
-/// Determines whether or not the first argument is less than or equal to
-/// the second argument.
-pub fn _is_le(_: Env, args: Args) -> EvalResult {
-    check_arity(2, args.len())?;
-
-    let (a, b) = (&args[0], &args[1]);
-    cmp(a, b)
-        .map(|dif| Value::Bool(dif <= 0.0))
-        .ok_or_else(|| format!("Cannot compare {} to {}.", a, b).into())
+/// Wraps all specified values in a vector.
+pub fn _vector(_: Env, args: Args) -> EvalResult {
+    Ok(Vector(Vec::from(args)))
 }
 
-/// `> : num num -> bool`
+/// `vector-ref : #[A] num -> A`
 ///
-/// Determines whether or not the first argument is greater than the second
-/// argument.
-pub fn _is_g(_: Env, args: Args) -> EvalResult {
+/// Produces the value at the specified index of the specified vector.
+pub fn _vector_ref(_: Env, args: Args) -> EvalResult {
     check_arity(2, args.len())?;
 
-    let (a, b) = (&args[0], &args[1]);
-    cmp(a, b)
-        .map(|dif| Value::Bool(dif > 0.0))
-        .ok_or_else(|| format!("Cannot compare {} to {}.", a, b).into())
+    match (&args[0], &args[1]) {
+        (Vector(vals), Num(num)) => {
+            let index = to_index(*num, vals.len())?;
+            ok(vals[index].clone())
+        }
+        (Vector(_), index) => err(not_a_number(index)),
+        (vector, _) => err(format!("{} is not a vector.", vector)),
+    }
 }
 
-/// `>= : num num -> bool`
+/// `vector-set : #[A] num A -> #[A]`
 ///
-/// Determines whether or not the first argument is greater than or equal
-/// to the second argument.
-pub fn _is_ge(_: Env, args: Args) -> EvalResult {
-    check_arity(2, args.len())?;
+/// Produces a copy of the specified vector, with the value at the specified
+/// index replaced by the specified value. The original vector is unchanged.
+pub fn _vector_set(_: Env, args: Args) -> EvalResult {
+    check_arity(3, args.len())?;
 
-    let (a, b) = (&args[0], &args[1]);
-    cmp(a, b)
-        .map(|dif| Value::Bool(dif >= 0.0))
-        .ok_or_else(|| format!("Cannot compare {} to {}.", a, b).into())
+    match (&args[0], &args[1]) {
+        (Vector(vals), Num(num)) => {
+            let index = to_index(*num, vals.len())?;
+            let mut buf = vals.clone();
+            buf[index] = args[2].clone();
+            Ok(Vector(buf))
+        }
+        (Vector(_), index) => err(not_a_number(index)),
+        (vector, _) => err(format!("{} is not a vector.", vector)),
+    }
 }
 
-/// `eq? : A A -> bool`
+/// `vector-length : #[A] -> num`
 ///
-/// Determines whether or not the two specified values are equal to one
-/// another.
-pub fn _is_eq(_: Env, args: Args) -> EvalResult {
-    check_arity(2, args.len())?;
-
-    let (a, b) = (&args[0], &args[1]);
-    ok(a == b)
-}
+/// Determines the length of the specified vector.
+pub fn _vector_length(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
 
-/// `or : bool... -> bool`
-///
-/// Produces the logical `or` of all the specified boolean values.
-pub fn _or(_: Env, args: Args) -> EvalResult {
-    for arg in args {
-        match arg {
-            Bool(b) => if *b {
-                return ok(true);
-            },
-            arg => return err(format!("{} is not a bool.", arg)),
-        }
+    match &args[0] {
+        Vector(vals) => ok(vals.len() as f64),
+        vector => err(format!("{} is not a vector.", vector)),
     }
-
-    ok(false)
 }
 
-/// `and : bool... -> bool`
+/// `list->vector : [A] -> #[A]`
 ///
-/// Produces the logical `and` of all the specified boolean values.
-pub fn _and(_: Env, args: Args) -> EvalResult {
-    for arg in args {
-        match arg {
-            Bool(b) => if !b {
-                return ok(false);
-            },
-            arg => return err(format!("{} is not a bool.", arg)),
-        }
-    }
+/// Produces a vector containing the elements of the specified list, in order.
+pub fn _list_to_vector(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
 
-    ok(true)
+    match &args[0] {
+        List(vals) => Ok(Vector(vals.to_vec())),
+        list => err(format!("{} is not a list.", list)),
+    }
 }
 
-/// `apply : (A... -> B) [A] -> B`
+/// `vector->list : #[A] -> [A]`
 ///
-/// Expands the specified list of values into a variadic input for the
-/// specified function, producing that function's output.
-pub fn _apply(env: Env, args: Args) -> EvalResult {
-    check_arity(2, args.len())?;
+/// Produces a list containing the elements of the specified vector, in order.
+pub fn _vector_to_list(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
 
-    match (&args[0], &args[1]) {
-        (Func(..), List(list)) => eval_func(SExpr::Nil, &args[0], list, env),
-        (Intrinsic(func), List(list)) => func(env, list),
-        (func, args) => err(format!("Contract not satisfied: {} {}.", func, args)),
+    match &args[0] {
+        Vector(vals) => ok(vals.clone()),
+        vector => err(format!("{} is not a vector.", vector)),
     }
 }
 
-/// `not : bool -> bool`
+/// `string->list : str -> [str]`
 ///
-/// Inverts the specified boolean value.
-pub fn _not(_: Env, args: Args) -> EvalResult {
+/// Produces a list containing each char of the specified str, in order, as a
+/// single char str.
+pub fn _string_to_list(_: Env, args: Args) -> EvalResult {
     check_arity(1, args.len())?;
 
     match &args[0] {
-        Bool(b) => ok(!b),
-        arg => err(format!("{} is not a bool.", arg)),
+        Str(s) => ok(s.chars().map(|c| Str(c.to_string())).collect::<Vec<_>>()),
+        val => err(format!("{} is not a str.", val)),
     }
 }
 
-/// `A... -> str`
+/// `list->string : [str] -> str`
 ///
-/// Produces a string containing all arguments concatenated together.
-pub fn _concat(_: Env, args: Args) -> EvalResult {
+/// Produces the str consisting of each of the single char strs in the
+/// specified list, in order.
+pub fn _list_to_string(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    let vals = match &args[0] {
+        List(vals) => vals,
+        list => return err(format!("{} is not a list.", list)),
+    };
     let mut buf = String::new();
+    for val in vals.iter() {
+        match val {
+            Str(c) if c.chars().count() == 1 => buf.push_str(c),
+            val => return err(format!("{} is not a single char str.", val)),
+        }
+    }
+    ok(buf)
+}
 
+/// `zip : [A] [B] ... -> [(A B ...)]`
+///
+/// Produces a list of tuples, represented as lists, where the nth tuple
+/// contains the nth element of each of the specified lists. The result is as
+/// long as the shortest list.
+pub fn _zip(_: Env, args: Args) -> EvalResult {
+    let mut lists = vec![];
     for arg in args {
-        let arg_str = format!("{}", arg);
-        buf.push_str(&arg_str);
+        match arg {
+            List(vals) => lists.push(vals),
+            list => return err(format!("{} is not a list.", list)),
+        }
     }
 
-    ok(buf)
+    let len = lists.iter().map(|vals| vals.len()).min().unwrap_or(0);
+    let tuples = (0..len)
+        .map(|i| lists.iter().map(|vals| vals[i].clone()).collect::<Vec<_>>().into())
+        .collect::<Vec<Value>>();
+    ok(tuples)
 }
 
-pub fn _eval(env: Env, args: Args) -> EvalResult {
+/// `unzip : [(A B ...)] -> ([A] [B] ...)`
+///
+/// Produces a list of lists, where the nth list contains the nth element of
+/// each of the tuples in the specified list. All of the tuples must have the
+/// same length, such that `(apply zip (unzip tuples))` produces the tuples.
+pub fn _unzip(_: Env, args: Args) -> EvalResult {
     check_arity(1, args.len())?;
 
-    let arg = (&args[0]).clone();
-    let expr: SExpr = arg.into();
+    let tuples = match &args[0] {
+        List(tuples) => tuples,
+        list => return err(format!("{} is not a list.", list)),
+    };
 
-    expr.eval(env)
+    let mut lists: Vec<Vec<Value>> = vec![];
+    for (i, tuple) in tuples.iter().enumerate() {
+        let vals = match tuple {
+            List(vals) => vals,
+            tuple => return err(format!("{} is not a list.", tuple)),
+        };
+        if i == 0 {
+            lists.resize(vals.len(), vec![]);
+        } else if vals.len() != lists.len() {
+            let why = format!("Expected a tuple of length {}, found {}.", lists.len(), tuple);
+            return err(why);
+        }
+        for (list, val) in lists.iter_mut().zip(vals.iter()) {
+            list.push(val.clone());
+        }
+    }
+    ok(lists.into_iter().map(Value::from).collect::<Vec<_>>())
 }
 
-/// Produces an error if the number of arguments found doesn't match the
-/// number of arguments expected.
-fn check_arity(expected: usize, found: usize) -> Result<()> {
-    if found != expected {
-        Err(arity_exact(expected, found))
-    } else {
-        Ok(())
+/// Produces an iterator over the specified value, which may be an iterator
+/// itself, a list, or a vector.
+fn to_iter(val: &Value) -> Result<SharedIter> {
+    match val {
+        Iter(iter) => Ok(iter.clone()),
+        List(list) => Ok(SharedIter::new(IterSource::List(list.clone()))),
+        Vector(vals) => Ok(SharedIter::new(IterSource::List(vals.clone().into()))),
+        val => err(format!("{} is not iterable.", val)),
     }
 }
 
-/// Compares the two specified values. If they are numbers, their difference
-/// is returned. Otherwise, `None` is returned.
-fn cmp(a: &Value, b: &Value) -> Option<f64> {
-    match (a, b) {
-        (&Num(a), &Num(b)) => Some(a - b),
-        _ => None,
+/// Produces an error if the specified value cannot be called as a function.
+fn check_callable(val: &Value) -> Result<()> {
+    match val {
+        Func(..) | Intrinsic(_) | Memoized(_) => Ok(()),
+        Macro(_) => Err(macro_not_first_class(val)),
+        val => Err(not_a_function(val)),
     }
 }
 
-#[derive(Debug)]
-enum StrSection<'a> {
-    Str(&'a str),
-    Expr(&'a str),
+/// `iter : [A] -> iterator`
+///
+/// Produces an iterator over the elements of the specified list or vector.
+/// An iterator is produced unchanged.
+pub fn _iter(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+    to_iter(&args[0]).map(Iter)
 }
 
-fn split_str(s: &str) -> Result<Vec<StrSection>> {
-    use self::StrSection::*;
-    let mut strs = Vec::new();
-    let mut in_expr = false;
-    let mut last = 0 as usize;
-    let mut i = 0 as usize;
-    let mut last_ch = '\0';
-    for ch in s.chars() {
-        const INTERPOLATION_CHAR: char = '$';
-        match ch {
-            '{' if last_ch == INTERPOLATION_CHAR => {
-                strs.push(Str(&s[last..i - 1]));
-                in_expr = true;
-                last = i + 1; // Begin expression after opening brace
-            }
-            '}' if in_expr => {
-                strs.push(Expr(&s[last..i])); // Push section from expression
-                in_expr = false;
-                last = i + 1; // Begin next section after ending brace
-            }
-            _ => (),
-        }
-        i += 1;
-        last_ch = ch;
-    }
-    if last != i {
-        strs.push(Str(&s[last..i]));
+/// `iter-range : num -> iterator`
+///
+/// `iter-range : num num -> iterator`
+///
+/// `iter-range : num num num -> iterator`
+///
+/// Produces an iterator counting from the start, or zero, toward the end by
+/// the step, or one, excluding the end. Unlike a list, the range is never
+/// stored, so it may be arbitrarily long.
+pub fn _iter_range(_: Env, args: Args) -> EvalResult {
+    let nums = args
+        .iter()
+        .map(|arg| match arg {
+            Num(n) => Ok(*n),
+            arg => Err(not_a_number(arg)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (start, end, step) = match nums[..] {
+        [end] => (0.0, end, 1.0),
+        [start, end] => (start, end, 1.0),
+        [start, end, step] => (start, end, step),
+        _ if nums.is_empty() => return Err(arity_at_least(1, 0)),
+        _ => return Err(arity_at_most(3, nums.len())),
+    };
+    if step == 0.0 || step.is_nan() {
+        return err(format!("{} is not a valid step for a range.", step));
     }
+    ok(Iter(SharedIter::new(IterSource::Range {
+        start,
+        end,
+        step,
+        index: 0,
+    })))
+}
 
-    if in_expr {
-        Err("Unclosed expression while interpolating string.".into())
-    } else {
-        Ok(strs)
-    }
+/// `iter-generate : (-> A) -> iterator`
+///
+/// Produces an iterator which calls the specified function to produce each of
+/// its values, until the function produces `iter-end`.
+pub fn _iter_generate(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+    check_callable(&args[0])?;
+    ok(Iter(SharedIter::new(IterSource::Generator(args[0].clone()))))
 }
 
-fn format_str(env: Env, sections: &[StrSection]) -> EvalResult {
-    use self::StrSection::*;
+/// `iter-next : iterator -> A`
+///
+/// Produces the next value of the specified iterator and advances past it, or
+/// `iter-end` if it has produced all of its values.
+pub fn _iter_next(env: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+    let next = to_iter(&args[0])?.next(env)?;
+    ok(next.unwrap_or_else(iter_end))
+}
 
-    let mut buf = String::new();
+/// `iter-has-next? : iterator -> bool`
+///
+/// Determines whether or not the specified iterator has another value,
+/// without advancing past it.
+pub fn _iter_has_next(env: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+    ok(to_iter(&args[0])?.peek(env)?.is_some())
+}
 
-    for section in sections {
-        match section {
-            Str(s) => buf.push_str(s),
-            Expr(s) => {
-                let reader = BufReader::new(s.as_bytes());
-                let mut parser = Parser::new(reader);
+/// `yield : A -> ()`
+///
+/// Passes the specified value to the innermost generator being evaluated. If
+/// the generator has already yielded as many values as its limit, it is
+/// stopped instead.
+pub fn _yield(env: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
 
-                // Get contents
-                let expr = parser.parse()?;
-                env.enter_scope(expr.clone());
-                let res = expr.eval(env)?;
-                env.exit_scope();
-                let res = format!("{}", res);
-                buf.push_str(&res);
-            }
-        }
+    let yields = match env.generator_mut() {
+        Some(yields) => yields,
+        None => return err("yield may only be called within a generator."),
+    };
+    if yields.values.len() >= yields.limit {
+        yields.stopped = true;
+        return err(format!("The generator reached its limit of {} values.", yields.limit));
     }
-
-    ok(buf)
+    yields.values.push(args[0].clone());
+    ok(nil())
 }
 
-pub fn _format(env: Env, args: Args) -> EvalResult {
+/// `iter-end? : A -> bool`
+///
+/// Determines whether or not the specified value is `iter-end`, which marks
+/// the end of an iterator.
+pub fn _is_iter_end(_: Env, args: Args) -> EvalResult {
     check_arity(1, args.len())?;
+    ok(is_iter_end(&args[0]))
+}
 
-    match &args[0] {
-        Str(s) => {
-            let sections = split_str(s)?;
-            let formatted = format_str(env, &sections)?;
+/// `iter-map : (A -> B) iterator -> iterator`
+///
+/// Produces an iterator which lazily applies the specified function to each
+/// value of the specified iterator, list, or vector.
+pub fn _iter_map(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+    check_callable(&args[0])?;
+    let source = IterSource::Map(args[0].clone(), to_iter(&args[1])?);
+    ok(Iter(SharedIter::new(source)))
+}
 
-            ok(formatted)
+/// `iter-filter : (A -> bool) iterator -> iterator`
+///
+/// Produces an iterator which lazily skips each value of the specified
+/// iterator, list, or vector that does not satisfy the specified predicate.
+pub fn _iter_filter(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+    check_callable(&args[0])?;
+    let source = IterSource::Filter(args[0].clone(), to_iter(&args[1])?);
+    ok(Iter(SharedIter::new(source)))
+}
+
+/// `iter-for-each : (A -> B) iterator -> ()`
+///
+/// Applies the specified function to each remaining value of the specified
+/// iterator, list, or vector in order, discarding its outputs.
+pub fn _iter_for_each(env: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+    check_callable(&args[0])?;
+
+    let iter = to_iter(&args[1])?;
+    let caller = caller_expr("iter-for-each", &args[0]);
+    while let Some(val) = iter.next(env)? {
+        call_fn(&caller, &args[0], slice::from_ref(&val), env)?;
+    }
+    ok(nil())
+}
+
+/// `iter-take : num iterator -> [A]`
+///
+/// Produces a list of up to the specified number of values from the specified
+/// iterator, advancing past them.
+pub fn _iter_take(env: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    let count = match &args[0] {
+        Num(n) => to_count(*n)?,
+        n => return Err(not_a_number(n)),
+    };
+    let iter = to_iter(&args[1])?;
+    let mut vals = vec![];
+    while vals.len() < count {
+        match iter.next(env)? {
+            Some(val) => vals.push(val),
+            None => break,
         }
-        format => err(format!("{} is not a str.", format)),
     }
+    ok(vals)
 }
 
-pub fn _read_line(_: Env, args: Args) -> EvalResult {
-    check_arity(0, args.len())?;
+/// `iter->list : iterator -> [A]`
+///
+/// Produces a list of all remaining values of the specified iterator. It is
+/// an error for the iterator to be infinite.
+pub fn _iter_to_list(env: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
 
-    let mut buf = String::new();
-    ::std::io::stdin()
-        .read_line(&mut buf)
-        .expect("Failed to read input");
-    let buf = buf.trim().to_string();
+    let iter = to_iter(&args[0])?;
+    let mut vals = vec![];
+    while let Some(val) = iter.next(env)? {
+        vals.push(val);
+    }
+    ok(vals)
+}
 
-    ok(buf)
+/// `< : num num -> bool`
+///
+/// Determines whether or not the first argument is less than the second
+/// argument.
+pub fn _is_l(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    let (a, b) = (&args[0], &args[1]);
+    cmp(a, b)
+        .map(|dif| Value::Bool(dif < 0.0))
+        .ok_or_else(|| format!("Cannot compare {} to {}.", a, b).into())
 }
 
-pub fn _parse(env: Env, args: Args) -> EvalResult {
-    check_arity(1, args.len())?;
+/// `<= : num num -> bool`
+///
+/// Determines whether or not the first argument is less than or equal to
+/// the second argument.
+pub fn _is_le(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
 
-    match &args[0] {
-        Str(s) => {
-            let s = format!("'{}", s);
-            let bytes = s.as_bytes();
-            let mut parser = Parser::new(BufReader::new(bytes));
+    let (a, b) = (&args[0], &args[1]);
+    cmp(a, b)
+        .map(|dif| Value::Bool(dif <= 0.0))
+        .ok_or_else(|| format!("Cannot compare {} to {}.", a, b).into())
+}
 
-            let expr = parser.parse()?;
-            let val = expr.eval(env)?;
+/// `> : num num -> bool`
+///
+/// Determines whether or not the first argument is greater than the second
+/// argument.
+pub fn _is_g(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
 
-            ok(val)
-        }
+    let (a, b) = (&args[0], &args[1]);
+    cmp(a, b)
+        .map(|dif| Value::Bool(dif > 0.0))
+        .ok_or_else(|| format!("Cannot compare {} to {}.", a, b).into())
+}
 
-        input => err(format!("{} is not a str.", input)),
-    }
+/// `>= : num num -> bool`
+///
+/// Determines whether or not the first argument is greater than or equal
+/// to the second argument.
+pub fn _is_ge(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    let (a, b) = (&args[0], &args[1]);
+    cmp(a, b)
+        .map(|dif| Value::Bool(dif >= 0.0))
+        .ok_or_else(|| format!("Cannot compare {} to {}.", a, b).into())
 }
 
-fn fib(n: u64) -> u64 {
-    match n {
-        0 | 1 => n,
-        n => fib(n - 1) + fib(n - 2),
+/// `= : num num -> bool`
+///
+/// Determines whether or not the two specified nums are numerically equal.
+/// Unlike `eq?`, this follows IEEE 754, so NaN is not equal to itself. Zero is
+/// equal to negative zero.
+pub fn _num_eq(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    match (&args[0], &args[1]) {
+        (&Num(a), &Num(b)) => ok(a == b),
+        (a, b) => err(format!("Expected (num num), found ({} {}).", a, b)),
     }
 }
 
-pub fn _fib_rust(_: Env, args: Args) -> EvalResult {
-    check_arity(1, args.len())?;
+/// `eq? : A A -> bool`
+///
+/// Determines whether or not the two specified values are structurally equal
+/// to one another. NaN is equal to itself, and zero is equal to negative
+/// zero.
+pub fn _is_eq(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
 
-    match &args[0] {
-        Num(n) => {
-            let n = *n as u64;
-            let res = fib(n);
-            let res = res as f64;
-            ok(res)
-        }
-        n => err(not_a_number(n)),
-    }
+    let (a, b) = (&args[0], &args[1]);
+    ok(a == b)
 }
 
-use std::fs::File;
+/// `neq? : A A -> bool`
+///
+/// Determines whether or not the two specified values are not equal to one
+/// another.
+pub fn _is_neq(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
 
-/// `run-file : str... -> A`
-/// Opens and runs the specified file.
-pub fn _import(env: Env, args: Args) -> EvalResult {
-    let mut vals = Vec::<Value>::new();
+    let (a, b) = (&args[0], &args[1]);
+    ok(a != b)
+}
 
-    for arg in args {
-        match arg {
-            Str(file_name) => {
-                let file = File::open(file_name)?;
-                let reader = BufReader::new(file);
-                let mut parser = Parser::new(reader);
-                let exprs = parser.parse_all()?;
+/// `set-equal? : [A] [A] -> bool`
+///
+/// Determines whether or not the two specified lists contain the same
+/// elements, as compared by `eq?`, regardless of their order. Lists are
+/// treated as sets, so the number of times each element occurs is ignored.
+pub fn _is_set_equal(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
 
-                let mut list = vec![SExpr::Ident("begin".to_string(), false)];
-                list.extend(exprs);
-                let expr = SExpr::List(list);
-                let res = expr.eval(env)?;
-                vals.push(res);
-            }
-            arg => return err(format!("{} is not a str.", arg)),
-        }
-    }
+    let (a, b) = match (&args[0], &args[1]) {
+        (List(a), List(b)) => (a, b),
+        (List(_), b) => return err(format!("{} is not a list.", b)),
+        (a, _) => return err(format!("{} is not a list.", a)),
+    };
 
-    Ok(nil())
+    let contains_all = |a: &SharedList, b: &SharedList| a.iter().all(|val| b.contains(val));
+    ok(contains_all(a, b) && contains_all(b, a))
 }
 
-use std::io::prelude::*;
-
-pub fn _read_file(_: Env, args: Args) -> EvalResult {
+/// Applies the specified predicate to a single num argument.
+fn num_predicate(args: Args, f: impl Fn(f64) -> Result<bool>) -> EvalResult {
     check_arity(1, args.len())?;
 
     match &args[0] {
-        Str(path) => {
-            // Open file
-            let mut file = File::open(path)?;
-
-            // Read file to buffer
-            let mut contents = String::from("(begin ");
-            file.read_to_string(&mut contents)?;
-            contents.push(')');
+        Num(x) => ok(f(*x)?),
+        arg => err(not_a_number(arg)),
+    }
+}
 
-            ok(contents)
-        }
-        arg => err(format!("{} is not a str.", arg)),
+/// Produces the remainder of the specified num divided by two, provided that
+/// it is an integer.
+fn parity(x: f64) -> Result<f64> {
+    if x.fract() == 0.0 {
+        Ok(x % 2.0)
+    } else {
+        Err(format!("{} is not an integer.", Num(x)).into())
     }
 }
 
-/// `write-file : str str -> bool`
+/// `zero? : num -> bool`
 ///
-/// Attempts to write the specified string to the file with the specified path.
-pub fn _write_file(_: Env, args: Args) -> EvalResult {
-    check_arity(2, args.len())?;
+/// Determines whether or not the specified num is zero.
+pub fn _is_zero(_: Env, args: Args) -> EvalResult {
+    num_predicate(args, |x| Ok(x == 0.0))
+}
 
-    match (&args[0], &args[1]) {
-        (Str(path), data) => {
-            let mut file = File::create(path)?;
-            let data_str = data.to_string();
-            let data_bytes = data_str.as_bytes();
-            file.write(data_bytes)?;
-            Ok(nil())
-        }
-        (file, data) => err(format!(
-            "Contract not satisfied. Expected str str, found {} {}.",
-            file, data
-        )),
-    }
+/// `positive? : num -> bool`
+///
+/// Determines whether or not the specified num is greater than zero.
+pub fn _is_positive(_: Env, args: Args) -> EvalResult {
+    num_predicate(args, |x| Ok(x > 0.0))
 }
 
-fn symbol<S: Into<String>>(s: S) -> Value {
-    let s = s.into();
-    Value::Symbol(s, false)
+/// `negative? : num -> bool`
+///
+/// Determines whether or not the specified num is less than zero.
+pub fn _is_negative(_: Env, args: Args) -> EvalResult {
+    num_predicate(args, |x| Ok(x < 0.0))
 }
 
-fn type_of(val: &Value) -> String {
-    match val {
-        Num(_) => "number",
-        Bool(_) => "bool",
-        Str(_) => "string",
-        Symbol(_, _) => "symbol",
-        List(_) => "list",
-        Func(_, _, _) => "function",
-        Intrinsic(_) => "function",
-        Macro(_) => "function",
-        Struct(struct_type, _) => struct_type,
-    }.to_string()
+/// `even? : num -> bool`
+///
+/// Determines whether or not the specified integer is even.
+pub fn _is_even(_: Env, args: Args) -> EvalResult {
+    num_predicate(args, |x| Ok(parity(x)? == 0.0))
 }
 
-/// `type-of : A -> symbol`
+/// `odd? : num -> bool`
 ///
-/// Produces a symbol representing the type of the specified value.
-pub fn _type_of(_: Env, args: Args) -> EvalResult {
-    check_arity(1, args.len())?;
+/// Determines whether or not the specified integer is odd.
+pub fn _is_odd(_: Env, args: Args) -> EvalResult {
+    num_predicate(args, |x| Ok(parity(x)? != 0.0))
+}
 
-    args.first()
-        .map(type_of)
-        .map(symbol)
-        .ok_or_else(|| format!("Unknown type.").into())
+/// `nan? : num -> bool`
+///
+/// Determines whether or not the specified num is not a number, such as the
+/// square root of a negative num.
+pub fn _is_nan(_: Env, args: Args) -> EvalResult {
+    num_predicate(args, |x| Ok(x.is_nan()))
 }
 
-// String functions
+/// `infinite? : num -> bool`
+///
+/// Determines whether or not the specified num is positive or negative
+/// infinity.
+pub fn _is_infinite(_: Env, args: Args) -> EvalResult {
+    num_predicate(args, |x| Ok(x.is_infinite()))
+}
 
-/// `substring : string num num -> string`
-pub fn _substring(_: Env, args: Args) -> EvalResult {
-    check_arity(3, args.len())?;
+/// `finite? : num -> bool`
+///
+/// Determines whether or not the specified num is neither infinite nor not a
+/// number.
+pub fn _is_finite(_: Env, args: Args) -> EvalResult {
+    num_predicate(args, |x| Ok(x.is_finite()))
+}
 
-    match (&args[0], &args[1], &args[2]) {
-        (Str(string), Num(start), Num(end)) => ok(string
-            .chars()
-            .skip(*start as usize)
+/// `or : bool... -> bool`
+///
+/// Produces the logical `or` of all the specified boolean values. Under
+/// lenient truthiness, any value is accepted in place of a bool.
+pub fn _or(env: Env, args: Args) -> EvalResult {
+    for arg in args {
+        match env.truth_of(arg) {
+            Some(b) => if b {
+                return ok(true);
+            },
+            None => return err(format!("{} is not a bool.", arg)),
+        }
+    }
+
+    ok(false)
+}
+
+/// `and : bool... -> bool`
+///
+/// Produces the logical `and` of all the specified boolean values, accepting
+/// any value under lenient truthiness.
+pub fn _and(env: Env, args: Args) -> EvalResult {
+    for arg in args {
+        match env.truth_of(arg) {
+            Some(b) => if !b {
+                return ok(false);
+            },
+            None => return err(format!("{} is not a bool.", arg)),
+        }
+    }
+
+    ok(true)
+}
+
+/// `identity : A -> A`
+///
+/// Produces the specified value unchanged.
+pub fn _identity(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+    ok(args[0].clone())
+}
+
+/// `const : A -> (B... -> A)`
+///
+/// Produces a function which ignores its arguments and always produces the
+/// specified value.
+pub fn _const(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    let mut mapping = HashMap::new();
+    mapping.insert("x".to_string(), args[0].clone());
+    let body = SExpr::Ident("x".to_string(), false);
+    let mut lambda = Lambda::new(vec!["args".to_string()], body, true);
+    lambda.captures = Captures::new(mapping);
+    ok(Func(lambda))
+}
+
+/// `memoize : (A... -> B) -> (A... -> B)`
+///
+/// Produces a version of the specified function which caches its outputs, so
+/// that calling it again with equal arguments produces the cached output
+/// instead. A function argument is only equal to the same function, so a call
+/// with an equivalent but separately created function is not cached.
+pub fn _memoize(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match &args[0] {
+        Func(..) | Intrinsic(_) | Memoized(_) => ok(Memoized(Rc::new(Memo::new(args[0].clone())))),
+        Macro(_) => err(macro_not_first_class(&args[0])),
+        val => err(not_a_function(val)),
+    }
+}
+
+/// `apply : (A... -> B) [A] -> B`
+///
+/// Expands the specified list of values into a variadic input for the
+/// specified function, producing that function's output. Macros operate on
+/// unevaluated expressions, so they cannot be applied.
+pub fn _apply(env: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    let list = match &args[1] {
+        List(list) => list,
+        val => return err(format!("Second argument to apply, {}, is not a list.", val)),
+    };
+
+    match &args[0] {
+        Func(..) | Memoized(_) => {
+            let caller = SExpr::List(vec![
+                SExpr::Ident("apply".to_string(), false),
+                args[0].clone().into(),
+                SExpr::Quote(Box::new(args[1].clone().into())),
+            ]);
+            call_fn(&caller, &args[0], list, env)
+        }
+        Intrinsic(..) | Macro(..) => call_fn(&SExpr::Nil, &args[0], list, env),
+        val => err(format!("First argument to apply, {}, is not a function.", val)),
+    }
+}
+
+/// Produces the expression `(name func)`, recorded in error backtraces as the
+/// caller of a function called by the specified higher-order intrinsic.
+fn caller_expr(name: &str, func: &Value) -> SExpr {
+    SExpr::List(vec![SExpr::Ident(name.to_string(), false), func.clone().into()])
+}
+
+/// Calls the specified predicate with the specified value, as with `call_fn`,
+/// producing an error if it does not produce a bool.
+fn call_pred(caller: &SExpr, pred: &Value, val: &Value, env: Env) -> Result<bool> {
+    match call_fn(caller, pred, slice::from_ref(val), env)? {
+        Bool(b) => Ok(b),
+        res => Err(format!("{} is not a bool.", res).into()),
+    }
+}
+
+/// `reduce : (A A -> A) [A] -> A`
+///
+/// Performs a left-associative fold on the specified list, using its first
+/// element as the initial accumulator. Unlike `foldl`, there is no seed, so it
+/// is an error to reduce an empty list.
+pub fn _reduce(env: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    let vals = match &args[1] {
+        List(vals) => vals,
+        list => return err(format!("{} is not a list.", list)),
+    };
+    let (first, rest) = match vals.split_first() {
+        Some(split) => split,
+        None => return err("Cannot reduce an empty list."),
+    };
+
+    let func = &args[0];
+    let caller = caller_expr("reduce", func);
+    rest.iter().try_fold(first.clone(), |acc, val| call_fn(&caller, func, &[acc, val.clone()], env))
+}
+
+/// Splits the specified list after its leading elements for which the
+/// specified predicate produces the specified bool. The predicate must
+/// produce a bool.
+fn split_while<'a>(
+    name: &str,
+    args: Args<'a>,
+    env: Env,
+    expected: bool,
+) -> Result<(&'a [Value], &'a [Value])> {
+    check_arity(2, args.len())?;
+
+    let vals = match &args[1] {
+        List(vals) => vals,
+        list => return Err(format!("{} is not a list.", list).into()),
+    };
+
+    let pred = &args[0];
+    let caller = caller_expr(name, pred);
+    for (i, val) in vals.iter().enumerate() {
+        if call_pred(&caller, pred, val, env)? != expected {
+            return Ok(vals.split_at(i));
+        }
+    }
+    Ok((vals, &[]))
+}
+
+/// `take-while : (A -> bool) [A] -> [A]`
+///
+/// Produces the leading elements of the specified list for which the
+/// specified predicate holds.
+pub fn _take_while(env: Env, args: Args) -> EvalResult {
+    let (taken, _) = split_while("take-while", args, env, true)?;
+    ok(taken.to_vec())
+}
+
+/// `drop-while : (A -> bool) [A] -> [A]`
+///
+/// Produces the specified list without the leading elements for which the
+/// specified predicate holds.
+pub fn _drop_while(env: Env, args: Args) -> EvalResult {
+    let (_, rest) = split_while("drop-while", args, env, true)?;
+    ok(rest.to_vec())
+}
+
+/// `partition : (A -> bool) [A] -> ([A] [A])`
+///
+/// Produces a pair of lists, where the first contains the elements of the
+/// specified list for which the specified predicate holds, and the second
+/// contains the rest. The order of the elements is preserved.
+pub fn _partition(env: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    let vals = match &args[1] {
+        List(vals) => vals,
+        list => return err(format!("{} is not a list.", list)),
+    };
+
+    let pred = &args[0];
+    let caller = caller_expr("partition", pred);
+    let (mut pass, mut fail) = (vec![], vec![]);
+    for val in vals.iter() {
+        if call_pred(&caller, pred, val, env)? {
+            pass.push(val.clone());
+        } else {
+            fail.push(val.clone());
+        }
+    }
+    ok(vec![Value::from(pass), Value::from(fail)])
+}
+
+/// `count : (A -> bool) [A] -> num`
+///
+/// Determines the number of elements of the specified list for which the
+/// specified predicate holds.
+pub fn _count(env: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    let vals = match &args[1] {
+        List(vals) => vals,
+        list => return err(format!("{} is not a list.", list)),
+    };
+
+    let pred = &args[0];
+    let caller = caller_expr("count", pred);
+    let mut count = 0;
+    for val in vals.iter() {
+        if call_pred(&caller, pred, val, env)? {
+            count += 1;
+        }
+    }
+    ok(count as f64)
+}
+
+/// `any? : (A -> bool) [A] -> bool`
+///
+/// Determines whether or not the specified predicate holds for any element of
+/// the specified list. The remaining elements are not tested once one is
+/// found.
+pub fn _any(env: Env, args: Args) -> EvalResult {
+    let (_, rest) = split_while("any?", args, env, false)?;
+    ok(!rest.is_empty())
+}
+
+/// `all? : (A -> bool) [A] -> bool`
+///
+/// Determines whether or not the specified predicate holds for every element
+/// of the specified list, which is vacuously true for an empty list. The
+/// remaining elements are not tested once one fails.
+pub fn _all(env: Env, args: Args) -> EvalResult {
+    let (_, rest) = split_while("all?", args, env, true)?;
+    ok(rest.is_empty())
+}
+
+/// `group-by : (A -> B) [A] -> [(B [A])]`
+///
+/// Groups the elements of the specified list by the key produced for each of
+/// them by the specified function. The groups are produced as an association
+/// list of `(key elements)` pairs, ordered by the first occurrence of each
+/// key. The order of the elements within each group is preserved.
+pub fn _group_by(env: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    let vals = match &args[1] {
+        List(vals) => vals,
+        list => return err(format!("{} is not a list.", list)),
+    };
+
+    let func = &args[0];
+    let caller = caller_expr("group-by", func);
+    let mut groups: Vec<(Value, Vec<Value>)> = vec![];
+    for val in vals.iter() {
+        let key = call_fn(&caller, func, slice::from_ref(val), env)?;
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, group)) => group.push(val.clone()),
+            None => groups.push((key, vec![val.clone()])),
+        }
+    }
+
+    let groups = groups
+        .into_iter()
+        .map(|(key, group)| vec![key, group.into()].into())
+        .collect::<Vec<Value>>();
+    ok(groups)
+}
+
+/// `not : bool -> bool`
+///
+/// Inverts the specified boolean value. Under lenient truthiness, any value
+/// is accepted, and its truthiness is inverted instead.
+pub fn _not(env: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match env.truth_of(&args[0]) {
+        Some(b) => ok(!b),
+        None => err(format!("{} is not a bool.", args[0])),
+    }
+}
+
+/// `ensure : bool str -> ()`
+///
+/// Produces an error with the specified message if the specified bool is
+/// false. Otherwise, nil is produced. Unlike `assert`, the message describes
+/// the failure to the caller rather than the failed expression.
+pub fn _ensure(env: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    let msg = match &args[1] {
+        Str(msg) => msg,
+        msg => return err(format!("{} is not a str.", msg)),
+    };
+    match env.truth_of(&args[0]) {
+        Some(true) => ok(nil()),
+        Some(false) => err(msg.clone()),
+        None => err(format!("{} is not a bool.", args[0])),
+    }
+}
+
+/// `->bool : A -> bool`
+///
+/// Converts the specified value to a bool. `#f` and the empty list are false,
+/// and every other value is true, including `0`, `""`, and `#()`. This is the
+/// same rule that lenient truthiness applies to conditions.
+pub fn _to_bool(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    ok(args[0].is_truthy())
+}
+
+/// `bool-> : bool -> bool`
+///
+/// Produces the specified bool unchanged, as the counterpart of `->bool`.
+pub fn _from_bool(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match &args[0] {
+        Bool(b) => ok(*b),
+        arg => err(format!("{} is not a bool.", arg)),
+    }
+}
+
+/// `A... -> str`
+///
+/// Produces a string containing all arguments concatenated together.
+pub fn _concat(_: Env, args: Args) -> EvalResult {
+    let mut buf = String::new();
+
+    for arg in args {
+        buf.push_str(&plain_str(arg));
+    }
+
+    ok(buf)
+}
+
+/// Produces the specified value as it is written into a str, such as by
+/// `concat` or `format`. Strs are written as is, and other values are written
+/// without color.
+fn plain_str(val: &Value) -> String {
+    match val {
+        Str(s) => s.clone(),
+        val => format!("{:?}", val),
+    }
+}
+
+pub fn _eval(env: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    let arg = (&args[0]).clone();
+    let expr: SExpr = arg.into();
+
+    expr.eval(env)
+}
+
+/// `eval-in : A -> B`
+///
+/// Evaluates the specified quoted expression in a freshly initialized
+/// environment, such that its definitions do not affect the calling
+/// environment. Any fuel limit of the calling environment is shared with it,
+/// as are its output and error output.
+pub fn _eval_in(env: Env, args: Args) -> EvalResult {
+    eval_fresh(env, args, Environment::init_intrinsics)
+}
+
+/// `eval-in : A -> B`
+///
+/// Evaluates the specified quoted expression as with `eval-in`, where the
+/// fresh environment is itself sandboxed.
+pub fn _eval_in_sandboxed(env: Env, args: Args) -> EvalResult {
+    eval_fresh(env, args, Environment::init_intrinsics_sandboxed)
+}
+
+/// Evaluates the sole argument in a fresh environment, initialized by the
+/// specified function, which consumes the fuel of the specified environment
+/// and writes to its output and error output.
+fn eval_fresh(env: Env, args: Args, init: fn(&mut Environment)) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    let mut fresh = Environment::default();
+    fresh.set_output(env.output());
+    fresh.set_error_output(env.error_output());
+    init(&mut fresh);
+    if let Some(fuel) = env.fuel() {
+        fresh.set_fuel(fuel);
+    }
+
+    let expr: SExpr = args[0].clone().into();
+    let res = expr.eval(&mut fresh);
+    if let Some(fuel) = fresh.fuel() {
+        env.set_fuel(fuel);
+    }
+    res
+}
+
+/// Produces an error if the number of arguments found doesn't match the
+/// number of arguments expected.
+fn check_arity(expected: usize, found: usize) -> Result<()> {
+    if found != expected {
+        Err(arity_exact(expected, found))
+    } else {
+        Ok(())
+    }
+}
+
+/// Compares the two specified values. If they are numbers, their difference
+/// is returned. Otherwise, `None` is returned.
+fn cmp(a: &Value, b: &Value) -> Option<f64> {
+    match (a, b) {
+        (&Num(a), &Num(b)) => Some(a - b),
+        _ => None,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum StrSection<'a> {
+    Str(&'a str),
+    Expr(&'a str),
+}
+
+fn split_str(s: &str) -> Result<Vec<StrSection<'_>>> {
+    use self::StrSection::*;
+    // An expression may be opened by either `#{` or `${`
+    const INTERPOLATION_CHARS: &[char] = &['#', '$'];
+    let mut strs = Vec::new();
+    let mut in_expr = false;
+    let mut last = 0;
+    let mut last_ch = '\0';
+    let mut open_at = 0;
+
+    // Indices are byte offsets, so slicing stays on char boundaries. A `}`
+    // outside of an expression is kept literally.
+    for (offset, (i, ch)) in s.char_indices().enumerate() {
+        match ch {
+            '{' if INTERPOLATION_CHARS.contains(&last_ch) && !in_expr => {
+                open_at = offset - 1;
+                // The interpolation char is a single byte
+                if last < i - 1 {
+                    strs.push(Str(&s[last..i - 1]));
+                }
+                in_expr = true;
+                last = i + 1; // Begin expression after opening brace
+            }
+            '}' if in_expr => {
+                strs.push(Expr(&s[last..i])); // Push section from expression
+                in_expr = false;
+                last = i + 1; // Begin next section after ending brace
+            }
+            _ => (),
+        }
+        last_ch = ch;
+    }
+    if last < s.len() {
+        strs.push(Str(&s[last..]));
+    }
+
+    if in_expr {
+        Err(format!(
+            "Unclosed expression at offset {} while interpolating string.",
+            open_at
+        ).into())
+    } else {
+        Ok(strs)
+    }
+}
+
+fn format_str(env: Env, sections: &[StrSection]) -> EvalResult {
+    use self::StrSection::*;
+
+    let mut buf = String::new();
+
+    for section in sections {
+        match section {
+            Str(s) => buf.push_str(s),
+            Expr(s) => {
+                let mut parser = Parser::for_str(s);
+
+                // Get contents
+                let expr = parser.parse()?;
+                env.enter_scope(expr.clone());
+                let res = expr.eval(env)?;
+                env.exit_scope();
+                buf.push_str(&plain_str(&res));
+            }
+        }
+    }
+
+    ok(buf)
+}
+
+pub fn _format(env: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match &args[0] {
+        Str(s) => {
+            let sections = split_str(s)?;
+            let formatted = format_str(env, &sections)?;
+
+            ok(formatted)
+        }
+        format => err(format!("{} is not a str.", format)),
+    }
+}
+
+/// Converts the specified num into a count, producing an error if it is not
+/// a non-negative integer.
+fn to_count(num: f64) -> Result<usize> {
+    let count = num as usize;
+    if num != count as f64 {
+        Err(format!("{} is not a non-negative integer.", num).into())
+    } else {
+        Ok(count)
+    }
+}
+
+/// `format-number : num num -> str`
+///
+/// `format-number : num num num -> str`
+///
+/// Produces a string containing the specified num, rounded to the specified
+/// number of decimal places. If a width is specified, the string is padded
+/// on the left with spaces to be at least that wide.
+pub fn _format_number(_: Env, args: Args) -> EvalResult {
+    let len = args.len();
+    if len < 2 {
+        return Err(arity_at_least(2, len));
+    } else if len > 3 {
+        return Err(arity_at_most(3, len));
+    }
+
+    let mut nums = Vec::with_capacity(len);
+    for arg in args {
+        match arg {
+            Num(n) => nums.push(*n),
+            arg => return Err(not_a_number(arg)),
+        }
+    }
+
+    let precision = to_count(nums[1])?;
+    let width = match nums.get(2) {
+        Some(width) => to_count(*width)?,
+        None => 0,
+    };
+
+    ok(format!("{:>width$.precision$}", nums[0], width = width, precision = precision))
+}
+
+pub fn _read_line(_: Env, args: Args) -> EvalResult {
+    check_arity(0, args.len())?;
+
+    let mut buf = String::new();
+    ::std::io::stdin()
+        .read_line(&mut buf)
+        .expect("Failed to read input");
+    let buf = buf.trim().to_string();
+
+    ok(buf)
+}
+
+/// Reads a single expression from the specified reader, producing it as quoted
+/// data. At the end of input, nil is produced instead.
+fn read_from<R: Read>(reader: BufReader<R>) -> EvalResult {
+    let mut parser = Parser::new(reader);
+
+    match parser.parse() {
+        Ok(expr) => ok(Value::from(expr)),
+        Err(ParseError::Eof) => ok(nil()),
+        Err(why) => err(why),
+    }
+}
+
+/// `read : -> A`
+///
+/// Reads a single expression from the standard input, producing it as quoted
+/// data. At the end of input, nil is produced instead.
+pub fn _read(_: Env, args: Args) -> EvalResult {
+    check_arity(0, args.len())?;
+
+    // Read one byte at a time, so that no input past the expression is
+    // consumed from the standard input.
+    read_from(BufReader::with_capacity(1, ::std::io::stdin()))
+}
+
+/// `parse : str -> A`
+///
+/// Parses the single expression contained in the specified string, producing
+/// it as quoted data. Produces an error if the string contains anything other
+/// than whitespace after the expression.
+pub fn _parse(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match &args[0] {
+        Str(s) => {
+            let mut parser = Parser::for_str(s);
+
+            let expr = match parser.parse() {
+                Ok(expr) => expr,
+                Err(ParseError::Eof) => return err("Expected an expression."),
+                Err(why) => return err(why),
+            };
+
+            match parser.parse() {
+                Err(ParseError::Eof) => ok(Value::from(expr)),
+                _ => err(format!("Unexpected input after {}.", expr)),
+            }
+        }
+
+        input => err(format!("{} is not a str.", input)),
+    }
+}
+
+/// `parse-all : str -> [A]`
+///
+/// Parses all expressions contained in the specified string, producing a list
+/// of them as quoted data.
+pub fn _parse_all(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match &args[0] {
+        Str(s) => {
+            let exprs = Parser::parse_from_str(s)?;
+            ok(exprs.into_iter().map(Value::from).collect::<Vec<_>>())
+        }
+
+        input => err(format!("{} is not a str.", input)),
+    }
+}
+
+fn fib(n: u64) -> u64 {
+    match n {
+        0 | 1 => n,
+        n => fib(n - 1) + fib(n - 2),
+    }
+}
+
+pub fn _fib_rust(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match &args[0] {
+        Num(n) => {
+            let n = *n as u64;
+            let res = fib(n);
+            let res = res as f64;
+            ok(res)
+        }
+        n => err(not_a_number(n)),
+    }
+}
+
+use std::fs::{self, File};
+use std::env;
+use std::path::Path;
+
+/// `run-file : str... -> A`
+/// Opens and runs the specified file.
+pub fn _import(env: Env, args: Args) -> EvalResult {
+    let mut vals = Vec::<Value>::new();
+
+    for arg in args {
+        match arg {
+            Str(file_name) => {
+                let file = File::open(file_name)?;
+                let reader = BufReader::new(file);
+                let mut parser = Parser::new(reader);
+                let exprs = parser.parse_all()?;
+
+                let mut list = vec![SExpr::Ident("begin".to_string(), false)];
+                list.extend(exprs);
+                let expr = SExpr::List(list);
+                let res = expr.eval(env)?;
+                vals.push(res);
+            }
+            arg => return err(format!("{} is not a str.", arg)),
+        }
+    }
+
+    Ok(nil())
+}
+
+pub fn _read_file(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match &args[0] {
+        Str(path) => {
+            // Open file
+            let mut file = File::open(path)?;
+
+            // Read file to buffer
+            let mut contents = String::from("(begin ");
+            file.read_to_string(&mut contents)?;
+            contents.push(')');
+
+            ok(contents)
+        }
+        arg => err(format!("{} is not a str.", arg)),
+    }
+}
+
+/// `write-file : str str -> bool`
+///
+/// Attempts to write the specified string to the file with the specified path.
+pub fn _write_file(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    match (&args[0], &args[1]) {
+        (Str(path), data) => {
+            let mut file = File::create(path)?;
+            let data_str = data.to_string();
+            let data_bytes = data_str.as_bytes();
+            file.write(data_bytes)?;
+            Ok(nil())
+        }
+        (file, data) => err(format!(
+            "Contract not satisfied. Expected str str, found {} {}.",
+            file, data
+        )),
+    }
+}
+
+/// `read-lines : str -> [str]`
+///
+/// Produces the contents of the file with the specified path as a list of
+/// its lines, without their line endings.
+pub fn _read_lines(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    let path = match &args[0] {
+        Str(path) => path,
+        arg => return err(format!("{} is not a str.", arg)),
+    };
+    let contents = fs::read_to_string(path)
+        .map_err(|why| format!("Cannot read the file {}: {}.", path, why))?;
+    ok(contents.lines().map(Value::from).collect::<Vec<_>>())
+}
+
+/// `write-lines : str [str] -> nil`
+///
+/// Writes each of the strs in the specified list to the file with the
+/// specified path, each followed by a line ending.
+pub fn _write_lines(_: Env, args: Args) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    let (path, lines) = match (&args[0], &args[1]) {
+        (Str(path), List(lines)) => (path, lines),
+        (path, lines) => {
+            return err(format!("Expected (str [str]), found ({} {}).", path, lines))
+        }
+    };
+    let mut contents = String::new();
+    for line in lines.iter() {
+        match line {
+            Str(line) => contents.push_str(line),
+            line => return err(format!("{} is not a str.", line)),
+        }
+        contents.push('\n');
+    }
+    fs::write(path, contents).map_err(|why| format!("Cannot write the file {}: {}.", path, why))?;
+    Ok(nil())
+}
+
+/// Produces the path specified as the sole argument to a file intrinsic.
+fn path_arg(args: Args<'_>) -> Result<&str> {
+    check_arity(1, args.len())?;
+
+    match &args[0] {
+        Str(path) => Ok(path),
+        arg => Err(format!("{} is not a str.", arg).into()),
+    }
+}
+
+/// `file-exists? : str -> bool`
+///
+/// Determines whether or not a file or directory exists at the specified path.
+pub fn _file_exists(_: Env, args: Args) -> EvalResult {
+    let path = path_arg(args)?;
+    ok(Path::new(path).exists())
+}
+
+/// `delete-file : str -> nil`
+///
+/// Deletes the file with the specified path. It is an error for the file not
+/// to exist.
+pub fn _delete_file(_: Env, args: Args) -> EvalResult {
+    let path = path_arg(args)?;
+    fs::remove_file(path).map_err(|why| format!("Cannot delete the file {}: {}.", path, why))?;
+    Ok(nil())
+}
+
+/// `make-directory : str -> nil`
+///
+/// Creates a directory at the specified path, along with any missing parent
+/// directories. It is not an error for the directory to already exist.
+pub fn _make_directory(_: Env, args: Args) -> EvalResult {
+    let path = path_arg(args)?;
+    fs::create_dir_all(path)
+        .map_err(|why| format!("Cannot create the directory {}: {}.", path, why))?;
+    Ok(nil())
+}
+
+/// `current-directory : -> str`
+///
+/// Produces the path of the current working directory, against which relative
+/// paths, including those passed to `import`, are resolved.
+pub fn _current_directory(_: Env, args: Args) -> EvalResult {
+    check_arity(0, args.len())?;
+
+    let dir = env::current_dir()
+        .map_err(|why| format!("Cannot access the current directory: {}.", why))?;
+    ok(dir.to_string_lossy().into_owned())
+}
+
+/// `change-directory : str -> nil`
+///
+/// Changes the current working directory to the specified path. Subsequent
+/// relative paths, including those passed to `import`, are resolved against
+/// the new directory. It is an error for the directory not to exist.
+pub fn _change_directory(_: Env, args: Args) -> EvalResult {
+    let path = path_arg(args)?;
+    env::set_current_dir(path)
+        .map_err(|why| format!("Cannot change to the directory {}: {}.", path, why))?;
+    Ok(nil())
+}
+
+/// `system : str -> str`
+///
+/// Runs the specified command with the system shell, producing everything it
+/// writes to standard output. If the command exits unsuccessfully, its exit
+/// code and standard error are reported in the resulting error instead.
+pub fn _system(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    let command = match &args[0] {
+        Str(command) => command,
+        arg => return err(format!("{} is not a str.", arg)),
+    };
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .map_err(|why| format!("Cannot run the command {}: {}.", command, why))?;
+
+    if !output.status.success() {
+        let code = match output.status.code() {
+            Some(code) => code.to_string(),
+            None => "none".to_string(),
+        };
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return err(format!(
+            "The command {} failed with exit code {}: {}",
+            command,
+            code,
+            stderr.trim_end()
+        ));
+    }
+    ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn symbol<S: Into<String>>(s: S) -> Value {
+    let s = s.into();
+    Value::Symbol(s, false)
+}
+
+/// `string->symbol : str -> symbol`
+///
+/// Produces a symbol whose name is the specified string. Constructed symbols
+/// are never variadic, so `(string->symbol "a...")` is not equal to `'a...`.
+pub fn _string_to_symbol(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match &args[0] {
+        Str(s) if s.is_empty() => err("Cannot create a symbol with an empty name."),
+        Str(s) => ok(symbol(s.as_str())),
+        val => err(format!("{} is not a str.", val)),
+    }
+}
+
+/// `symbol->string : symbol -> str`
+///
+/// Produces the name of the specified symbol, including the trailing `...`
+/// of a variadic symbol.
+pub fn _symbol_to_string(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match &args[0] {
+        sym @ Symbol(..) => ok(sym.to_string()),
+        val => err(format!("{} is not a symbol.", val)),
+    }
+}
+
+/// `symbol-append : (str | symbol)... -> symbol`
+///
+/// Produces a symbol whose name is the concatenation of the specified strs
+/// and symbol names, such as the names of the constructor and accessors that
+/// `define-struct` generates. As with `string->symbol`, the symbol is never
+/// variadic.
+pub fn _symbol_append(_: Env, args: Args) -> EvalResult {
+    let mut name = String::new();
+    for arg in args {
+        match arg {
+            Str(s) => name.push_str(s),
+            sym @ Symbol(..) => name.push_str(&sym.to_string()),
+            val => return err(format!("{} is neither a str nor a symbol.", val)),
+        }
+    }
+
+    if name.is_empty() {
+        err("Cannot create a symbol with an empty name.")
+    } else {
+        ok(symbol(name))
+    }
+}
+
+/// `values : A... -> (values A...)`
+///
+/// Produces all of the specified values at once, to be destructured by
+/// `let-values`. A single value is produced as is.
+pub fn _values(_: Env, args: Args) -> EvalResult {
+    match args.len() {
+        1 => ok(args[0].clone()),
+        _ => ok(Values(args.to_vec())),
+    }
+}
+
+/// The names of the built-in types, as produced by `type-of` and accepted by
+/// `typecase`.
+pub const TYPE_NAMES: &[&str] = &[
+    "num", "bool", "str", "symbol", "list", "vector", "function", "macro", "struct", "values",
+    "iterator", "iter-end", "mpair",
+];
+
+/// Produces the name of the type of the specified value, one of `TYPE_NAMES`.
+pub fn type_of(val: &Value) -> &'static str {
+    match val {
+        Num(_) => "num",
+        Bool(_) => "bool",
+        Str(_) => "str",
+        Symbol(_, _) => "symbol",
+        List(_) => "list",
+        Vector(_) => "vector",
+        Func(_) => "function",
+        Intrinsic(_) => "function",
+        Memoized(_) => "function",
+        Macro(_) => "macro",
+        Struct(_, _) => "struct",
+        Values(_) => "values",
+        Iter(_) => "iterator",
+        IterEnd => "iter-end",
+        MPair(_) => "mpair",
+    }
+}
+
+/// `doc : (str | symbol) -> str`
+///
+/// Produces the documentation of the function bound to the specified name.
+/// For an intrinsic, it consists of its signatures followed by a description.
+/// For a function defined in lisp, it is its parameter list.
+pub fn _doc(env: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    let name = match &args[0] {
+        Str(s) | Symbol(s, _) => s,
+        val => return err(format!("{} is neither a str nor a symbol.", val)),
+    };
+
+    // An intrinsic is only described where its name is not shadowed
+    let doc = match env.get(name) {
+        Some(Func(ref lambda)) => return ok(format!("{} : {}", name, lambda.params_expr())),
+        Some(ref val @ Intrinsic(_)) if env.get_global(name).as_ref() == Some(val) => {
+            env.doc(name)
+        }
+        _ => None,
+    };
+    match doc {
+        Some(doc) => ok(doc.to_string()),
+        None => err(format!("There is no documentation for {}.", name)),
+    }
+}
+
+/// `apropos : (str | symbol) -> [symbol]`
+///
+/// Produces the names of all bindings visible from the current scope which
+/// contain the specified str, including intrinsics, special forms, and user
+/// definitions, in alphabetical order.
+pub fn _apropos(env: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    let part = match &args[0] {
+        Str(s) | Symbol(s, _) => s,
+        val => return err(format!("{} is neither a str nor a symbol.", val)),
+    };
+
+    let names: Vec<Value> = env
+        .names()
+        .into_iter()
+        .filter(|name| name.contains(part.as_str()))
+        .map(symbol)
+        .collect();
+    ok(names)
+}
+
+/// `type-of : A -> symbol`
+///
+/// Produces a symbol representing the type of the specified value, one of
+/// `num`, `bool`, `str`, `symbol`, `list`, `vector`, `function`, `macro`,
+/// `struct`, `values`, `iterator`, `iter-end`, or `mpair`.
+pub fn _type_of(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    args.first()
+        .map(type_of)
+        .map(symbol)
+        .ok_or_else(|| "Unknown type.".into())
+}
+
+/// A Fowler-Noll-Vo (FNV-1a) hasher. Unlike that of `DefaultHasher`, its
+/// output does not change between runs or Rust versions.
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> FnvHasher {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Produces an error if the specified value is, or contains, a value that
+/// cannot be hashed. Functions are never equal to one another, so they
+/// cannot be hashed.
+fn check_hashable(val: &Value) -> Result<()> {
+    match val {
+        List(vals) => vals.iter().try_for_each(check_hashable),
+        Vector(vals) | Values(vals) | Struct(_, vals) => {
+            vals.iter().try_for_each(check_hashable)
+        }
+        Func(_) | Intrinsic(_) | Memoized(_) | Macro(_) => {
+            err(format!("{} is not hashable.", val))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `hash : A -> num`
+///
+/// Produces an integer hash of the specified value, which is equal for equal
+/// values and the same across runs. It is an error to hash a function, or a
+/// list, vector, or struct containing one.
+pub fn _hash(_: Env, args: Args) -> EvalResult {
+    check_arity(1, args.len())?;
+    check_hashable(&args[0])?;
+
+    let mut hasher = FnvHasher::default();
+    args[0].hash(&mut hasher);
+
+    // Only integers of up to 53 bits are exactly representable as nums
+    ok((hasher.finish() & ((1 << 53) - 1)) as f64)
+}
+
+// String functions
+
+/// `substring : string num num -> string`
+pub fn _substring(_: Env, args: Args) -> EvalResult {
+    check_arity(3, args.len())?;
+
+    match (&args[0], &args[1], &args[2]) {
+        (Str(string), Num(start), Num(end)) => ok(string
+            .chars()
+            .skip(*start as usize)
             .take((end - start - 1.0) as usize)
             .collect::<String>()),
-        _ => err(format!("Contract not satisfied.")),
+        _ => err("Contract not satisfied."),
+    }
+}
+
+/// Applies the specified predicate to two str arguments.
+fn str_predicate(args: Args, f: impl Fn(&str, &str) -> bool) -> EvalResult {
+    check_arity(2, args.len())?;
+
+    match (&args[0], &args[1]) {
+        (Str(a), Str(b)) => ok(f(a, b)),
+        (a, b) => err(format!("Expected (str str), found ({} {}).", a, b)),
+    }
+}
+
+/// `string-contains? : str str -> bool`
+///
+/// Determines whether or not the first str contains the second str.
+pub fn _string_contains(_: Env, args: Args) -> EvalResult {
+    str_predicate(args, |s, sub| s.contains(sub))
+}
+
+/// `string-starts-with? : str str -> bool`
+///
+/// Determines whether or not the first str begins with the second str.
+pub fn _string_starts_with(_: Env, args: Args) -> EvalResult {
+    str_predicate(args, |s, prefix| s.starts_with(prefix))
+}
+
+/// `string-ends-with? : str str -> bool`
+///
+/// Determines whether or not the first str ends with the second str.
+pub fn _string_ends_with(_: Env, args: Args) -> EvalResult {
+    str_predicate(args, |s, suffix| s.ends_with(suffix))
+}
+
+/// `string-replace : str str str -> str`
+///
+/// Produces a copy of the first str in which all non-overlapping occurrences
+/// of the second str are replaced with the third str.
+pub fn _string_replace(_: Env, args: Args) -> EvalResult {
+    check_arity(3, args.len())?;
+
+    match (&args[0], &args[1], &args[2]) {
+        (Str(_), Str(needle), Str(_)) if needle.is_empty() => {
+            err("Cannot replace an empty str.")
+        }
+        (Str(haystack), Str(needle), Str(replacement)) => {
+            ok(haystack.replace(needle.as_str(), replacement))
+        }
+        (a, b, c) => err(format!("Expected (str str str), found ({} {} {}).", a, b, c)),
+    }
+}
+
+/// Applies the specified transformation to a single str argument.
+fn str_fn(args: Args, f: impl Fn(&str) -> &str) -> EvalResult {
+    check_arity(1, args.len())?;
+
+    match &args[0] {
+        Str(s) => ok(f(s).to_string()),
+        arg => err(format!("{} is not a str.", arg)),
+    }
+}
+
+/// `string-trim : str -> str`
+///
+/// Produces the specified str without leading or trailing whitespace.
+pub fn _string_trim(_: Env, args: Args) -> EvalResult {
+    str_fn(args, str::trim)
+}
+
+/// `string-trim-left : str -> str`
+///
+/// Produces the specified str without leading whitespace.
+pub fn _string_trim_left(_: Env, args: Args) -> EvalResult {
+    str_fn(args, str::trim_start)
+}
+
+/// `string-trim-right : str -> str`
+///
+/// Produces the specified str without trailing whitespace.
+pub fn _string_trim_right(_: Env, args: Args) -> EvalResult {
+    str_fn(args, str::trim_end)
+}
+
+/// Produces the str to pad, the number of fill chars to add to reach the
+/// specified width, and the fill char, which defaults to a space.
+fn pad_args(args: Args<'_>) -> Result<(&str, usize, char)> {
+    let len = args.len();
+    if len < 2 {
+        return Err(arity_at_least(2, len));
+    } else if len > 3 {
+        return Err(arity_at_most(3, len));
+    }
+
+    let (s, width) = match (&args[0], &args[1]) {
+        (Str(s), Num(width)) => (s, to_count(*width)?),
+        (s, width) => return Err(format!("Expected (str num), found ({} {}).", s, width).into()),
+    };
+    let fill = match args.get(2) {
+        Some(Str(fill)) if fill.chars().count() == 1 => fill.chars().next().unwrap(),
+        Some(fill) => return Err(format!("{} is not a single char str.", fill).into()),
+        None => ' ',
+    };
+
+    let count = width.saturating_sub(s.chars().count());
+    Ok((s, count, fill))
+}
+
+/// `string-pad-left : str num -> str`
+///
+/// `string-pad-left : str num str -> str`
+///
+/// Produces the specified str, preceded by enough fill chars to reach the
+/// specified width. The fill char defaults to a space. Strs which are already
+/// at least as wide are produced as is.
+pub fn _string_pad_left(_: Env, args: Args) -> EvalResult {
+    let (s, count, fill) = pad_args(args)?;
+    let mut buf: String = (0..count).map(|_| fill).collect();
+    buf.push_str(s);
+    ok(buf)
+}
+
+/// `string-pad-right : str num -> str`
+///
+/// `string-pad-right : str num str -> str`
+///
+/// Produces the specified str, followed by enough fill chars to reach the
+/// specified width. The fill char defaults to a space. Strs which are already
+/// at least as wide are produced as is.
+pub fn _string_pad_right(_: Env, args: Args) -> EvalResult {
+    let (s, count, fill) = pad_args(args)?;
+    let mut buf = s.to_string();
+    buf.extend((0..count).map(|_| fill));
+    ok(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use intrinsics::test::*;
+
+    fn nums(ns: &[f64]) -> Vec<Value> {
+        ns.iter().map(|n| Num(*n)).collect()
+    }
+
+    #[test]
+    fn vector_literal() {
+        let res = eval("#(1 2 (+ 1 2))").unwrap();
+        assert!(res == Vector(nums(&[1.0, 2.0, 3.0])));
+        assert_eq!(res.to_string(), Vector(nums(&[1.0, 2.0, 3.0])).to_string());
+        assert!(eval("(vector 1 2)").unwrap() == Vector(nums(&[1.0, 2.0])));
+    }
+
+    #[test]
+    fn vector_ref() {
+        assert!(eval("(vector-ref #(4 5 6) 1)").unwrap() == Num(5.0));
+        assert!(eval("(vector-length #(4 5 6))").unwrap() == Num(3.0));
+    }
+
+    #[test]
+    fn vector_ref_out_of_bounds() {
+        let why = eval_err("(vector-ref #(4 5 6) 3)");
+        assert!(why.contains("Index 3 is out of bounds for length 3."));
+    }
+
+    #[test]
+    fn vector_set_is_functional() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define v #(1 2 3))").unwrap();
+        let res = eval_in(&mut env, "(vector-set v 0 9)").unwrap();
+        assert!(res == Vector(nums(&[9.0, 2.0, 3.0])));
+        assert!(eval_in(&mut env, "v").unwrap() == Vector(nums(&[1.0, 2.0, 3.0])));
+    }
+
+    #[test]
+    fn list_vector_round_trip() {
+        let res = eval("(list->vector '(1 2 3))").unwrap();
+        assert!(res == Vector(nums(&[1.0, 2.0, 3.0])));
+
+        let res = eval("(vector->list (list->vector '(1 2 3)))").unwrap();
+        assert!(res == List(nums(&[1.0, 2.0, 3.0]).into()));
+
+        let res = eval("(list->vector (vector->list #(3 2 1)))").unwrap();
+        assert!(res == Vector(nums(&[3.0, 2.0, 1.0])));
+    }
+
+    #[test]
+    fn string_list_round_trip() {
+        let chars = |cs: &[&str]| -> Value {
+            cs.iter().map(|c| Str(c.to_string())).collect::<Vec<_>>().into()
+        };
+        assert!(eval("(string->list \"abc\")").unwrap() == chars(&["a", "b", "c"]));
+        assert!(eval("(list->string (string->list \"abc\"))").unwrap() == Str("abc".into()));
+
+        let res = eval("(string->list \"h\u{e9}\u{1f600}\")").unwrap();
+        assert!(res == chars(&["h", "\u{e9}", "\u{1f600}"]));
+        let res = eval("(list->string (string->list \"h\u{e9}\u{1f600}\"))").unwrap();
+        assert!(res == Str("h\u{e9}\u{1f600}".into()));
+        assert!(eval("(len (string->list \"h\u{e9}\u{1f600}\"))").unwrap() == Num(3.0));
+
+        assert!(eval("(string->list \"\")").unwrap() == chars(&[]));
+        assert!(eval_err("(list->string (vector->list #(\"ab\")))").contains("single char"));
+    }
+
+    #[test]
+    fn updated_vector_leaves_list_unchanged() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define lst '(1 2 3))").unwrap();
+        let res = eval_in(&mut env, "(vector-set (list->vector lst) 1 5)").unwrap();
+        assert!(res == Vector(nums(&[1.0, 5.0, 3.0])));
+        assert!(eval_in(&mut env, "lst").unwrap() == List(nums(&[1.0, 2.0, 3.0]).into()));
+    }
+
+    #[test]
+    fn sum_large_list_with_cdr() {
+        let mut env = new_env();
+        let n = 100_000;
+        let mut list = List((0..n).map(|i| Num(i as f64)).collect());
+
+        let mut sum = 0.0;
+        while list != nil() {
+            match _car(&mut env, &[list.clone()]).unwrap() {
+                Num(x) => sum += x,
+                _ => panic!("Expected a num."),
+            }
+            list = _cdr(&mut env, &[list]).unwrap();
+        }
+
+        let n = n as f64;
+        assert_eq!(sum, n * (n - 1.0) / 2.0);
+    }
+
+    #[test]
+    fn sum_list_with_recursive_cdr() {
+        let mut env = new_env();
+        eval_in(
+            &mut env,
+            "(define (sum lst) (if (eq? (len lst) 0) 0 (+ (car lst) (sum (cdr lst)))))",
+        ).unwrap();
+        let res = eval_in(&mut env, "(sum '(1 2 3 4 5 6 7 8 9 10))").unwrap();
+        assert!(res == Num(55.0));
+    }
+
+    #[test]
+    fn format_number_rounding() {
+        assert!(eval("(format-number 3.14159 2)").unwrap() == Str("3.14".to_string()));
+        assert!(eval("(format-number 2.71828 3)").unwrap() == Str("2.718".to_string()));
+        assert!(eval("(format-number (+ 0.1 0.2) 2)").unwrap() == Str("0.30".to_string()));
+        assert!(eval("(format-number 7 0)").unwrap() == Str("7".to_string()));
+    }
+
+    #[test]
+    fn format_number_padding() {
+        assert!(eval("(format-number 3.14159 2 8)").unwrap() == Str("    3.14".to_string()));
+        assert!(eval("(format-number 123.456 1 3)").unwrap() == Str("123.5".to_string()));
+    }
+
+    #[test]
+    fn format_number_negative() {
+        assert!(eval("(format-number -2.345 1)").unwrap() == Str("-2.3".to_string()));
+        assert!(eval("(format-number -2.345 2 7)").unwrap() == Str("  -2.35".to_string()));
+        assert!(eval_err("(format-number 1 -1)").contains("-1 is not a non-negative integer."));
+    }
+
+    #[test]
+    fn parse_single_expr() {
+        let res = eval("(parse \"(+ 1 2)\")").unwrap();
+        let expected = List(vec![symbol("+"), Num(1.0), Num(2.0)].into());
+        assert!(res == expected);
+
+        assert!(eval("(parse \"  5  \")").unwrap() == Num(5.0));
+    }
+
+    #[test]
+    fn parse_trailing_input() {
+        let why = eval_err("(parse \"(a b) c\")");
+        assert!(why.contains("Unexpected input after (a b)."));
+
+        let why = eval_err("(parse \"\")");
+        assert!(why.contains("Expected an expression."));
+    }
+
+    #[test]
+    fn read_expr() {
+        let input: &[u8] = b"  (a \"b\" 1)\n(c)";
+        let res = read_from(BufReader::new(input)).unwrap();
+        let expected = List(vec![symbol("a"), Str("b".to_string()), Num(1.0)].into());
+        assert!(res == expected);
+
+        let input: &[u8] = b"  ; nothing left\n";
+        assert!(read_from(BufReader::new(input)).unwrap() == nil());
+    }
+
+    #[test]
+    fn parse_all_exprs() {
+        let res = eval("(parse-all \"1 (a) \\\"b\\\"\")").unwrap();
+        let expected = List(vec![Num(1.0), List(vec![symbol("a")].into()), Str("b".to_string())].into());
+        assert!(res == expected);
+
+        assert!(eval("(parse-all \"\")").unwrap() == nil());
+    }
+
+    #[test]
+    fn split_str_leading_expr() {
+        use self::StrSection::{Expr, Str};
+        assert_eq!(split_str("${x} y").unwrap(), vec![Expr("x"), Str(" y")]);
+        assert_eq!(split_str("${x}${y}").unwrap(), vec![Expr("x"), Expr("y")]);
+        assert_eq!(split_str("#{x} y").unwrap(), vec![Expr("x"), Str(" y")]);
+    }
+
+    #[test]
+    fn split_str_trailing_dollar() {
+        use self::StrSection::{Expr, Str};
+        assert_eq!(split_str("a$").unwrap(), vec![Str("a$")]);
+        assert_eq!(split_str("${x}$").unwrap(), vec![Expr("x"), Str("$")]);
+        assert_eq!(split_str("$").unwrap(), vec![Str("$")]);
+    }
+
+    #[test]
+    fn split_str_stray_brace() {
+        use self::StrSection::{Expr, Str};
+        assert_eq!(split_str("a}b").unwrap(), vec![Str("a}b")]);
+        assert_eq!(split_str("${x}}").unwrap(), vec![Expr("x"), Str("}")]);
+    }
+
+    #[test]
+    fn split_str_multibyte() {
+        use self::StrSection::{Expr, Str};
+        assert_eq!(split_str("é ${x} ü").unwrap(), vec![Str("é "), Expr("x"), Str(" ü")]);
+    }
+
+    #[test]
+    fn split_str_unclosed_offset() {
+        let why = split_str("abc ${x").unwrap_err().to_string();
+        assert!(why.contains("Unclosed expression at offset 4"));
+
+        let why = split_str("${a} é ${b").unwrap_err().to_string();
+        assert!(why.contains("Unclosed expression at offset 7"));
+    }
+
+    #[test]
+    fn symbol_round_trip() {
+        assert!(eval("(string->symbol \"abc\")").unwrap() == symbol("abc"));
+        assert!(eval("(eq? (symbol \"abc\") 'abc)").unwrap() == Bool(true));
+        assert!(eval("(symbol->string 'abc)").unwrap() == Str("abc".to_string()));
+        let res = eval("(string->symbol (symbol->string 'a-b))").unwrap();
+        assert!(res == symbol("a-b"));
+        assert!(eval("(symbol->string 'a...)").unwrap() == Str("a...".to_string()));
+    }
+
+    #[test]
+    fn symbol_contracts() {
+        assert!(eval_err("(symbol \"\")").contains("empty name"));
+        assert!(eval_err("(symbol->string \"a\")").contains("is not a symbol."));
+        assert!(eval_err("(string->symbol 'a)").contains("is not a str."));
+    }
+
+    #[test]
+    fn apply_contracts() {
+        assert!(eval("(apply + '(1 2))").unwrap() == Num(3.0));
+
+        let why = eval_err("(apply 1 '(1 2))");
+        assert!(why.contains("First argument to apply,") && why.contains("is not a function."));
+
+        let why = eval_err("(apply + 1)");
+        assert!(why.contains("Second argument to apply,") && why.contains("is not a list."));
+
+        let why = eval_err("(apply if '(#t 1 2))");
+        assert!(why.contains("Macros cannot be used as first-class functions."));
+    }
+
+    #[test]
+    fn reduce_lists() {
+        assert!(eval("(reduce + '(1 2 3 4))").unwrap() == Num(10.0));
+        assert!(eval("(reduce - '(10 2 3))").unwrap() == Num(5.0));
+        assert!(eval("(reduce (lambda (a b) (* a b)) '(2 3 4))").unwrap() == Num(24.0));
+        assert!(eval("(reduce + '(7))").unwrap() == Num(7.0));
+        assert!(eval_err("(reduce + '())").contains("Cannot reduce an empty list."));
+        assert!(eval_err("(reduce if '(1 2))").contains("first-class functions"));
+    }
+
+    #[test]
+    fn take_and_drop_while() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define (small? n) (< n 3))").unwrap();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(take-while small? '(1 2 3 1))"), "(1 2)");
+        assert_eq!(call("(drop-while small? '(1 2 3 1))"), "(3 1)");
+
+        // The predicate fails on the first element
+        assert_eq!(call("(take-while small? '(5 1 2))"), "()");
+        assert_eq!(call("(drop-while small? '(5 1 2))"), "(5 1 2)");
+
+        // The predicate holds for the whole list
+        assert_eq!(call("(take-while small? '(0 1 2))"), "(0 1 2)");
+        assert_eq!(call("(drop-while small? '(0 1 2))"), "()");
+
+        let why = eval_err("(take-while (lambda (n) n) '(1 2))");
+        assert!(why.contains("is not a bool."));
+    }
+
+    #[test]
+    fn zip_and_unzip() {
+        let mut env = new_env();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(zip '(1 2 3) '(4 5))"), "((1 4) (2 5))");
+        assert_eq!(call("(zip '(1 2) '(4 5 6) '(#t #f))"), "((1 4 true) (2 5 false))");
+        assert_eq!(call("(zip '(1 2) '())"), "()");
+        assert_eq!(call("(unzip '((1 4) (2 5)))"), "((1 2) (4 5))");
+        assert_eq!(call("(unzip (zip '(1 2 3) '(4 5 6)))"), "((1 2 3) (4 5 6))");
+        assert_eq!(call("(apply zip (unzip '((1 4) (2 5))))"), "((1 4) (2 5))");
+        assert_eq!(call("(unzip '())"), "()");
+
+        assert!(eval_err("(unzip '((1 4) (2)))").contains("Expected a tuple of length 2"));
+        assert!(eval_err("(zip '(1 2) 3)").contains("is not a list."));
+    }
+
+    #[test]
+    fn partition_and_group_by() {
+        let mut env = new_env();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(partition even? '(1 2 3 4 5))"), "((2 4) (1 3 5))");
+        assert_eq!(call("(partition even? '())"), "(() ())");
+
+        let res = call(r#"(group-by (lambda (s) (car (string->list s))) '("apple" "bee" "avo"))"#);
+        assert_eq!(res, r#"(("a" ("apple" "avo")) ("b" ("bee")))"#);
+        assert_eq!(call("(group-by even? '(1 2 3))"), "((false (1 3)) (true (2)))");
+
+        assert!(eval_err("(partition (lambda (n) n) '(1))").contains("is not a bool."));
+    }
+
+    #[test]
+    fn count_any_all() {
+        let mut env = new_env();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(count even? '())"), "0");
+        assert_eq!(call("(any? even? '())"), "false");
+        assert_eq!(call("(all? even? '())"), "true");
+
+        assert_eq!(call("(count even? '(1 2 3 4))"), "2");
+        assert_eq!(call("(any? even? '(1 2 3))"), "true");
+        assert_eq!(call("(any? even? '(1 3))"), "false");
+        assert_eq!(call("(all? even? '(2 4))"), "true");
+        assert_eq!(call("(all? even? '(2 3))"), "false");
+
+        // The remaining elements are not tested once the result is known
+        assert_eq!(call("(any? even? '(2 #t))"), "true");
+        assert_eq!(call("(all? even? '(1 #t))"), "false");
+        assert!(eval_err("(count (lambda (n) n) '(1))").contains("is not a bool."));
+    }
+
+    #[test]
+    fn make_list() {
+        assert!(eval("(make-list 0 'a)").unwrap() == List(vec![].into()));
+        assert!(eval("(make-list 3 1)").unwrap() == List(nums(&[1.0, 1.0, 1.0]).into()));
+        assert!(eval("(repeat 2 7)").unwrap() == List(nums(&[7.0, 7.0]).into()));
+        assert!(eval_err("(make-list -1 'a)").contains("-1 is not a non-negative integer."));
+        assert!(eval_err("(make-list 1.5 'a)").contains("is not a non-negative integer."));
+        assert!(eval("(make-list 16777216 0)").is_ok());
+        let why = eval_err("(make-list 1e15 0)");
+        assert!(why.contains("Cannot make a list of 1000000000000000 elements."));
+    }
+
+    #[test]
+    fn read_and_write_lines() {
+        let name = format!("rlisp-lines-{}", ::std::process::id());
+        let path = ::std::env::temp_dir().join(name);
+        let path = path.to_str().unwrap();
+        let mut env = new_env();
+        let write = format!(r#"(write-lines "{}" (vector->list #("a b" "" "c")))"#, path);
+        eval_in(&mut env, &write).unwrap();
+
+        let read = eval_in(&mut env, &format!(r#"(read-lines "{}")"#, path));
+        fs::remove_file(path).unwrap();
+        let lines: Vec<Value> = vec!["a b".into(), "".into(), "c".into()];
+        assert!(read.unwrap() == lines.into());
+
+        let why = eval_err(&format!(r#"(read-lines "{}")"#, path));
+        assert!(why.contains("Cannot read the file") && why.contains(path));
+    }
+
+    #[test]
+    fn file_management() {
+        let name = format!("rlisp-files-{}", ::std::process::id());
+        let dir = ::std::env::temp_dir().join(name);
+        let dir = dir.to_str().unwrap();
+        let file = format!("{}/nested/file", dir);
+        let mut env = new_env();
+        let mut call = |s: String| format!("{:?}", eval_in(&mut env, &s).unwrap());
+
+        assert_eq!(call(format!(r#"(file-exists? "{}")"#, dir)), "false");
+        call(format!(r#"(make-directory "{}/nested")"#, dir));
+        call(format!(r#"(make-directory "{}/nested")"#, dir));
+        call(format!(r#"(write-lines "{}" (vector->list #("a")))"#, file));
+        assert_eq!(call(format!(r#"(file-exists? "{}")"#, file)), "true");
+        call(format!(r#"(delete-file "{}")"#, file));
+        assert_eq!(call(format!(r#"(file-exists? "{}")"#, file)), "false");
+
+        let why = eval_err(&format!(r#"(delete-file "{}")"#, file));
+        fs::remove_dir_all(dir).unwrap();
+        assert!(why.contains("Cannot delete the file") && why.contains(&file));
+    }
+
+    #[test]
+    fn change_directory() {
+        let prev = env::current_dir().unwrap();
+        let dir = env::temp_dir().canonicalize().unwrap();
+        let dir = dir.to_str().unwrap();
+        let mut env = new_env();
+        eval_in(&mut env, &format!(r#"(change-directory "{}")"#, dir)).unwrap();
+        let res = eval_in(&mut env, "(current-directory)");
+        env::set_current_dir(prev).unwrap();
+        assert!(res.unwrap() == Str(dir.to_string()));
+
+        let why = eval_err(r#"(change-directory "/no/such/rlisp/dir")"#);
+        assert!(why.contains("Cannot change to the directory /no/such/rlisp/dir"));
+    }
+
+    #[test]
+    fn system_commands() {
+        assert!(eval(r#"(system "echo hello")"#).unwrap() == Str("hello\n".to_string()));
+
+        let why = eval_err(r#"(system "echo oops >&2; exit 3")"#);
+        assert!(why.contains("failed with exit code 3: oops"));
+    }
+
+    #[test]
+    fn eval_in_fresh_environment() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define x 1)").unwrap();
+        let res = eval_in(&mut env, "(eval-in '(begin (define x 2) (define y 3) (+ x y)))");
+        assert!(res.unwrap() == Num(5.0));
+        assert!(eval_in(&mut env, "x").unwrap() == Num(1.0));
+        assert!(eval_in(&mut env, "y").is_err());
+        assert!(eval_err("(begin (define z 1) (eval-in 'z))").contains("is unbound."));
+
+        // Fuel is shared with the fresh environment
+        env.set_fuel(1_000);
+        let why: String = eval_in(&mut env, "(eval-in '(do () (#f)))").err().unwrap().into();
+        assert!(why.contains("Fuel exhausted."));
+
+        // So are its output and error output
+        let mut env = new_env();
+        let (out, err) = (Rc::new(RefCell::new(vec![])), Rc::new(RefCell::new(vec![])));
+        env.set_output(out.clone());
+        env.set_error_output(err.clone());
+        eval_in(&mut env, "(eval-in '(begin (print 1) (set-trace! #t) ((lambda (x) x) 2)))")
+            .unwrap();
+        assert_eq!(String::from_utf8(out.borrow().clone()).unwrap(), "1");
+        assert!(!err.borrow().is_empty());
+        let res = eval_in(&mut env, "(with-output-to-string (eval-in '(print \"a\")))");
+        assert!(res.unwrap() == Str("a".to_string()));
+    }
+
+    #[test]
+    fn interpolated_string_literals() {
+        let res = eval(r#"(let ([x 2] [name "y"]) ~"${name} is ${(+ x 1)}, \"quoted\"")"#);
+        assert!(res.unwrap() == Str(r#"y is 3, "quoted""#.to_string()));
+        let res = eval(r#"(let ([x 2]) ~"x is #{x}")"#);
+        assert!(res.unwrap() == Str("x is 2".to_string()));
+    }
+
+    #[test]
+    fn cons_error_names_cdr() {
+        let why = eval_err("(cons \"car\" 2)");
+        let expected = format!("Second argument to cons, {}, is not a list.", Num(2.0));
+        assert_eq!(why.lines().last(), Some(&expected[..]));
+    }
+
+    #[test]
+    fn cons_error_omits_car() {
+        let why: String = _cons(&mut new_env(), &[Num(1.0), Num(2.0)])
+            .err()
+            .unwrap()
+            .into();
+        assert!(why.contains(&Num(2.0).to_string()));
+        assert!(!why.contains(&Num(1.0).to_string()));
+    }
+
+    #[test]
+    fn neq() {
+        assert!(eval("(neq? 1 2)").unwrap() == Bool(true));
+        assert!(eval("(neq? '(1) '(1))").unwrap() == Bool(false));
+    }
+
+    #[test]
+    fn sign_predicates() {
+        let check = |pred: &str, expected: [bool; 4]| {
+            for (n, expected) in ["3", "-3", "0", "0.5"].iter().zip(expected.iter()) {
+                let res = eval(&format!("({} {})", pred, n)).unwrap();
+                assert!(res == Bool(*expected), "({} {})", pred, n);
+            }
+        };
+        check("zero?", [false, false, true, false]);
+        check("positive?", [true, false, false, true]);
+        check("negative?", [false, true, false, false]);
+    }
+
+    #[test]
+    fn parity_predicates() {
+        for &(n, even) in &[("4", true), ("3", false), ("-4", true), ("-3", false), ("0", true)] {
+            assert!(eval(&format!("(even? {})", n)).unwrap() == Bool(even));
+            assert!(eval(&format!("(odd? {})", n)).unwrap() == Bool(!even));
+        }
+        assert!(eval_err("(even? 0.5)").contains("is not an integer."));
+        assert!(eval_err("(odd? -1.5)").contains("is not an integer."));
+        assert!(eval_err("(odd? \"1\")").contains("is not a number."));
+    }
+
+    #[test]
+    fn nan_and_signed_zero_equality() {
+        assert!(eval("(eq? math/nan math/nan)").unwrap() == Bool(true));
+        assert!(eval("(neq? math/nan math/nan)").unwrap() == Bool(false));
+        assert!(eval("(eq? (vector math/nan) (vector (sqrt -1)))").unwrap() == Bool(true));
+        assert!(eval("(eq? math/nan 1)").unwrap() == Bool(false));
+        assert!(eval("(= math/nan math/nan)").unwrap() == Bool(false));
+        assert!(eval("(= math/nan 1)").unwrap() == Bool(false));
+
+        assert!(eval("(eq? 0 -0)").unwrap() == Bool(true));
+        assert!(eval("(= 0 -0)").unwrap() == Bool(true));
+        assert!(eval("(= 1 1.0)").unwrap() == Bool(true));
+        assert!(eval_err("(= 1 \"1\")").contains("Expected (num num)"));
+    }
+
+    #[test]
+    fn modulo_and_mod() {
+        let cases = [
+            ("7 3", 1.0, 1.0),
+            ("-7 3", -1.0, 2.0),
+            ("7 -3", 1.0, -2.0),
+            ("-7 -3", -1.0, -1.0),
+            ("6 3", 0.0, 0.0),
+            ("-5.5 2", -1.5, 0.5),
+        ];
+        for &(args, truncated, floored) in &cases {
+            assert!(eval(&format!("(modulo {})", args)).unwrap() == Num(truncated));
+            assert!(eval(&format!("(mod {})", args)).unwrap() == Num(floored));
+        }
+    }
+
+    #[test]
+    fn exact_integer_powers() {
+        assert!(eval("(expt 2 62)").unwrap() == Num(4_611_686_018_427_387_904.0));
+        assert!(eval("(expt 3 33)").unwrap() == Num(5_559_060_566_555_523.0));
+        assert!(eval_err("(expt 3 39)").contains("is not exact as a num."));
+        assert!(eval("(expt -2 63)").unwrap() == Num(-9_223_372_036_854_775_808.0));
+        assert!(eval_err("(expt 2 64)").contains("overflows."));
+        assert!(eval_err("(expt 10 10000000000)").contains("overflows."));
+        assert!(eval("(expt 2 -1)").unwrap() == Num(0.5));
+        assert!(eval("(expt 4 0.5)").unwrap() == Num(2.0));
+        assert!(eval("(expt 1 10000000000)").unwrap() == Num(1.0));
+        assert!(eval("(expt 0 0)").unwrap() == Num(1.0));
+    }
+
+    #[test]
+    fn integer_square_roots() {
+        assert!(eval("(isqrt 0)").unwrap() == Num(0.0));
+        assert!(eval("(isqrt 15)").unwrap() == Num(3.0));
+        assert!(eval("(isqrt 16)").unwrap() == Num(4.0));
+
+        // Float rounding makes (sqrt n) overshoot to an integer just below a square
+        let n = "4503599761588224";
+        assert!(eval(&format!("(sqrt {})", n)).unwrap() == Num(67_108_865.0));
+        assert!(eval(&format!("(isqrt {})", n)).unwrap() == Num(67_108_864.0));
+        assert!(eval("(isqrt 4503599761588225)").unwrap() == Num(67_108_865.0));
+        assert!(eval("(isqrt 9007199136250224)").unwrap() == Num(94_906_264.0));
+
+        // The largest num below 2^64 is the largest accepted
+        assert!(eval("(isqrt 18446744073709549568)").unwrap() == Num(4_294_967_295.0));
+        assert!(eval_err("(isqrt 18446744073709551616)").contains("Expected a non-negative"));
+        assert!(eval_err("(isqrt -1)").contains("Expected a non-negative integer"));
+        assert!(eval_err("(isqrt 2.5)").contains("Expected a non-negative integer"));
+    }
+
+    #[test]
+    fn float_classification() {
+        let cases = [
+            ("(sqrt -1)", true, false),
+            ("math/nan", true, false),
+            ("(/ 1 0)", false, true),
+            ("(/ -1 0)", false, true),
+            ("math/infinity", false, true),
+            ("1.5", false, false),
+            ("0", false, false),
+        ];
+        for &(expr, nan, infinite) in &cases {
+            assert!(eval(&format!("(nan? {})", expr)).unwrap() == Bool(nan));
+            assert!(eval(&format!("(infinite? {})", expr)).unwrap() == Bool(infinite));
+            let finite = !nan && !infinite;
+            assert!(eval(&format!("(finite? {})", expr)).unwrap() == Bool(finite));
+        }
+        assert!(eval_err("(nan? #t)").contains("is not a number."));
+    }
+
+    #[test]
+    fn string_predicates() {
+        assert!(eval("(string-contains? \"hello\" \"ell\")").unwrap() == Bool(true));
+        assert!(eval("(string-contains? \"hello\" \"elo\")").unwrap() == Bool(false));
+        assert!(eval("(string-starts-with? \"hello\" \"he\")").unwrap() == Bool(true));
+        assert!(eval("(string-starts-with? \"hello\" \"lo\")").unwrap() == Bool(false));
+        assert!(eval("(string-ends-with? \"hello\" \"lo\")").unwrap() == Bool(true));
+        assert!(eval("(string-ends-with? \"hello\" \"he\")").unwrap() == Bool(false));
+        assert!(eval_err("(string-contains? \"a\" 1)").contains("Expected (str str)"));
+    }
+
+    #[test]
+    fn string_predicates_empty() {
+        for pred in &["string-contains?", "string-starts-with?", "string-ends-with?"] {
+            let res = eval(&format!("({} \"abc\" \"\")", pred)).unwrap();
+            assert!(res == Bool(true));
+        }
+    }
+
+    #[test]
+    fn string_predicates_unicode() {
+        let mut env = new_env();
+        let args = [Str("naïve café".to_string()), Str("ïve".to_string())];
+        assert!(_string_contains(&mut env, &args).unwrap() == Bool(true));
+        let args = [Str("naïve café".to_string()), Str("café".to_string())];
+        assert!(_string_ends_with(&mut env, &args).unwrap() == Bool(true));
+    }
+
+    #[test]
+    fn string_replace() {
+        let res = eval("(string-replace \"a-b-c\" \"-\" \"+\")").unwrap();
+        assert!(res == Str("a+b+c".to_string()));
+        let res = eval("(string-replace \"aaa\" \"aa\" \"b\")").unwrap();
+        assert!(res == Str("ba".to_string()));
+        let res = eval("(string-replace \"abc\" \"x\" \"y\")").unwrap();
+        assert!(res == Str("abc".to_string()));
+        assert!(eval_err("(string-replace \"abc\" \"\" \"y\")").contains("empty str"));
+    }
+
+    #[test]
+    fn string_trim() {
+        let s = "\" \\t a b \\n\"";
+        let trim = |f| eval(&format!("({} {})", f, s)).unwrap();
+        assert!(trim("string-trim") == Str("a b".to_string()));
+        assert!(trim("string-trim-left") == Str("a b \n".to_string()));
+        assert!(trim("string-trim-right") == Str(" \t a b".to_string()));
+    }
+
+    #[test]
+    fn string_pad() {
+        let res = eval("(string-pad-left \"7\" 3 \"0\")").unwrap();
+        assert!(res == Str("007".to_string()));
+        let res = eval("(string-pad-right \"ab\" 4 \".\")").unwrap();
+        assert!(res == Str("ab..".to_string()));
+        let res = eval("(string-pad-left \"ab\" 3)").unwrap();
+        assert!(res == Str(" ab".to_string()));
+        let res = eval("(string-pad-right \"abcd\" 2 \".\")").unwrap();
+        assert!(res == Str("abcd".to_string()));
+        assert!(eval_err("(string-pad-left \"a\" 3 \"ab\")").contains("is not a single char str."));
+    }
+
+    #[test]
+    fn trace_recursive_calls() {
+        let mut env = new_env();
+        let out = Rc::new(RefCell::new(Vec::<u8>::new()));
+        eval_in(&mut env, "(define (fact n) (if (eq? n 0) 1 (* n (fact (- n 1)))))").unwrap();
+        env.set_trace(Some(out.clone() as Sink));
+        assert!(eval_in(&mut env, "(fact 2)").unwrap() == Num(2.0));
+
+        let trace = String::from_utf8(out.borrow().clone()).unwrap();
+        let expected = "\
+> (fact 2)
+  > (fact 1)
+    > (fact 0)
+    < 1
+  < 1
+< 2
+";
+        assert_eq!(trace, expected);
+
+        assert!(eval_in(&mut env, "(set-trace! #f)").is_ok());
+        assert!(!env.is_tracing());
+
+        // Tracing to the error output follows it when it is redirected
+        eval_in(&mut env, "(set-trace! #t)").unwrap();
+        let err = Rc::new(RefCell::new(Vec::<u8>::new()));
+        env.set_error_output(err.clone());
+        eval_in(&mut env, "(fact 0)").unwrap();
+        assert_eq!(String::from_utf8(err.borrow().clone()).unwrap(), "> (fact 0)\n< 1\n");
+    }
+
+    #[test]
+    fn error_backtrace() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define (inner x) (car x))").unwrap();
+        eval_in(&mut env, "(define (outer x) (+ 1 (inner x)))").unwrap();
+        let why = eval_in(&mut env, "(outer 5)").err().unwrap();
+        assert_eq!(why.backtrace, vec!["(car x)", "(inner x)", "(outer 5)"]);
+        let out = why.to_string();
+        assert!(out.ends_with("\nBacktrace:\n  (car x)\n  (inner x)\n  (outer 5)"));
+    }
+
+    #[test]
+    fn scope_callers() {
+        fn caller(env: Env, _: Args) -> EvalResult {
+            ok(Value::from(env.cur_scope().caller.clone()))
+        }
+
+        let mut env = new_env();
+        env.define_intrinsic("caller", caller, "caller : -> A");
+        eval_in(&mut env, "(define (f x) (caller))").unwrap();
+        let res = eval_in(&mut env, "(f 1)").unwrap();
+        assert_eq!(format!("{:?}", res), "('f 1)");
+        let res = eval_in(&mut env, "(apply f '(2))").unwrap();
+        assert_eq!(format!("{:?}", res), "('apply ('lambda ('x) ('caller)) '(2))");
+        let res = eval_in(&mut env, "(let ([a 1]) (caller))").unwrap();
+        assert_eq!(format!("{:?}", res), "('let (('a 1)) ('caller))");
+    }
+
+    #[test]
+    fn identity_and_const() {
+        let mut env = new_env();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        call("(define (map f lst) (for/list ([x lst]) (f x)))");
+        assert_eq!(call("(map identity '(1 2 3))"), "(1 2 3)");
+        assert_eq!(call("(identity identity)"), "<function>");
+        assert_eq!(call("((const 5))"), "5");
+        assert_eq!(call("((always 'a) 1 2 3)"), "'a");
+        assert_eq!(call("(map (const 0) '(1 2 3))"), "(0 0 0)");
+        call("(define (lookup key alist #:opt default (const #f)) (default key))");
+        assert_eq!(call("(lookup 'a '())"), "false");
+        assert_eq!(call("(lookup 'a '() (const 'none))"), "'none");
+        assert!(eval_err("(identity 1 2)").contains("Expected 1"));
+    }
+
+    #[test]
+    fn not_truthiness() {
+        let mut env = new_env();
+        assert_eq!(format!("{:?}", eval_in(&mut env, "(not #t)").unwrap()), "false");
+        assert!(eval_in(&mut env, "(not 0)").is_err());
+        assert!(eval_in(&mut env, "(not '())").is_err());
+        assert!(eval_in(&mut env, "(if 0 1 2)").is_err());
+
+        env.set_truthiness(Truthiness::Lenient);
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(not #t)"), "false");
+        assert_eq!(call("(not #f)"), "true");
+        assert_eq!(call("(not 0)"), "false");
+        assert_eq!(call("(not '())"), "true");
+        assert_eq!(call("(if 0 1 2)"), "1");
+        assert_eq!(call("(or #f 0)"), "true");
+        assert_eq!(call("(and 1 #f '())"), "false");
+        assert_eq!(call("(and 1 '())"), "false");
+        assert_eq!(call("(and 1 '(2))"), "true");
+    }
+
+    #[test]
+    fn ensure_guards() {
+        let mut env = new_env();
+        let withdraw = "(define (withdraw n) (begin (ensure (> n 0) \"Bad amount.\") n))";
+        eval_in(&mut env, withdraw).unwrap();
+        assert!(eval_in(&mut env, "(ensure #t \"unused\")").unwrap() == nil());
+        assert!(eval_in(&mut env, "(withdraw 5)").unwrap() == Num(5.0));
+
+        let why = eval_in(&mut env, "(withdraw -5)").err().unwrap();
+        assert_eq!(why.description, "Bad amount.");
+        assert_eq!(why.backtrace.last().unwrap(), "(withdraw -5)");
+        assert!(eval_err("(ensure 1 \"message\")").contains("is not a bool."));
+        assert!(eval_err("(ensure #f 'message)").contains("is not a str."));
+    }
+
+    #[test]
+    fn memoize_fib() {
+        let mut env = new_env();
+        let fib = "(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))";
+        eval_in(&mut env, fib).unwrap();
+        let expected = eval_in(&mut env, "(fib 15)").unwrap();
+
+        // Recursive calls refer to the memoized function by name
+        eval_in(&mut env, "(define fib (memoize fib))").unwrap();
+        assert!(eval_in(&mut env, "(fib 15)").unwrap() == expected);
+        assert_eq!(format!("{:?}", eval_in(&mut env, "fib").unwrap()), "(memoize #<function fib>)");
+
+        // Without memoization, this would take billions of steps
+        env.set_fuel(100_000);
+        assert!(eval_in(&mut env, "(fib 70)").unwrap() == Num(190392490709135.0));
+        env.set_unlimited_fuel();
+
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("((memoize +) 1 2)"), "3");
+        assert_eq!(call("(lambda? (memoize fib))"), "true");
+        assert_eq!(call("(apply fib '(10))"), "55");
+        assert!(eval_err("(memoize 1)").contains("is not a function."));
+        assert!(eval_err("(memoize if)").contains("is a macro."));
+
+        // Functions passed as arguments hit the cache
+        eval_in(&mut env, "(define calls 0)").unwrap();
+        let call_once = "(define call-once
+            (memoize (lambda (f x) (begin (set! calls (+ calls 1)) (f x)))))";
+        eval_in(&mut env, call_once).unwrap();
+        eval_in(&mut env, "(define (sq x) (* x x))").unwrap();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(call-once sq 3)"), "9");
+        assert_eq!(call("(call-once sq 3)"), "9");
+        assert_eq!(call("(call-once - 3)"), "-3");
+        assert_eq!(call("(call-once - 3)"), "-3");
+        assert_eq!(call("calls"), "2");
+    }
+
+    #[test]
+    fn structural_hashing() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define-struct point [x y])").unwrap();
+        let mut hash = |s: &str| {
+            let res = eval_in(&mut env, &format!("(hash {})", s)).unwrap();
+            format!("{:?}", res)
+        };
+        assert_eq!(hash("'(1 (2 \"a\") b)"), hash("(cons 1 '((2 \"a\") b))"));
+        assert_eq!(hash("(make-point 1 2)"), hash("(make-point 1 2)"));
+        assert_eq!(hash("0"), hash("-0"));
+        assert_ne!(hash("'(1 2)"), hash("'(2 1)"));
+        assert_ne!(hash("'(1 2)"), hash("#(1 2)"));
+        assert_ne!(hash("\"a\""), hash("'a"));
+
+        // Hashes do not vary between runs
+        assert_eq!(hash("'(1 (2 \"a\") #t)"), "6078068424411181");
+
+        assert!(eval_err("(hash +)").contains("is not hashable."));
+        assert!(eval_err("(hash (vector 1 (lambda (x) x)))").contains("is not hashable."));
+    }
+
+    #[test]
+    fn lazy_iterators() {
+        let mut env = new_env();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+
+        // The range is never stored, so it may be arbitrarily long
+        call("(define squares (iter-map (lambda (x) (* x x)) (iter-range 1e15)))");
+        assert_eq!(call("(iter-take 4 squares)"), "(0 1 4 9)");
+        assert_eq!(call("(iter-next squares)"), "16");
+        assert_eq!(call("(iter-take 3 (iter-filter even? (iter-range 1 1e15)))"), "(2 4 6)");
+        assert_eq!(call("(iter->list (iter-range 10 0 -3))"), "(10 7 4 1)");
+
+        call("(define it (iter '(1 2)))");
+        assert_eq!(call("(iter-has-next? it)"), "true");
+        assert_eq!(call("(iter-next it)"), "1");
+        assert_eq!(call("(iter-next it)"), "2");
+        assert_eq!(call("(iter-has-next? it)"), "false");
+        assert_eq!(call("(iter-end? (iter-next it))"), "true");
+        assert_eq!(call("(iter-end? 'end)"), "false");
+        assert_eq!(call("(iter-end? (string->symbol \"#end\"))"), "false");
+        assert_eq!(call("(iter-next it)"), "iter-end");
+        assert_eq!(call("(type-of iter-end)"), "'iter-end");
+
+        // Each value is computed from the start, without accumulating error
+        assert_eq!(call("(len (iter->list (iter-range 0 1 0.1)))"), "10");
+
+        call("(define count-up (let ([n 0]) (lambda () (begin (set! n (+ n 1)) n))))");
+        call("(define (up-to-3) (let ([n (count-up)]) (if (> n 3) iter-end n)))");
+        assert_eq!(call("(iter->list (iter-generate up-to-3))"), "(1 2 3)");
+        let printed = "(with-output-to-string (iter-for-each print (iter #(1 2 3))))";
+        assert_eq!(call(printed), "\"123\"");
+        assert_eq!(call("(type-of (iter '()))"), "'iterator");
+
+        assert!(eval_err("(iter-range 0 10 0)").contains("not a valid step"));
+        assert!(eval_err("(iter 1)").contains("is not iterable."));
+        assert!(eval_err("(iter-map 1 '())").contains("is not a function."));
+        assert!(eval_err("(iter->list (iter-map if '(1)))").contains("is a macro"));
+    }
+
+    #[test]
+    fn generators() {
+        let mut env = new_env();
+        let count_from = "(define (count-from n) (begin (yield n) (count-from (+ n 1))))";
+        eval_in(&mut env, count_from).unwrap();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(iter->list (generator 10 (count-from 0)))"), "(0 1 2 3 4 5 6 7 8 9)");
+        assert_eq!(call("(iter-take 3 (generator 10 (count-from 5)))"), "(5 6 7)");
+        assert_eq!(call("(iter->list (generator 10 (yield 'a) (yield 'b)))"), "('a 'b)");
+        assert_eq!(call("(iter->list (generator 0 (count-from 0)))"), "()");
+
+        let nested = "(generator 3 (yield (iter->list (generator 2 (count-from 0)))) (yield 'c))";
+        assert_eq!(call(&format!("(iter->list {})", nested)), "((0 1) 'c)");
+
+        let mut call_err = |s: &str| -> String { eval_in(&mut env, s).err().unwrap().into() };
+        assert_eq!(call_err("(yield 1)"), "yield may only be called within a generator.");
+        assert!(call_err("(generator 5 (yield 1) (car '()))").contains("car"));
+        assert!(call_err("(generator -1 (yield 1))").contains("not a non-negative integer"));
+
+        // The generator is exited even if its body fails
+        assert_eq!(call_err("(yield 1)"), "yield may only be called within a generator.");
+    }
+
+    #[test]
+    fn mutable_lists() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define a (mlist 1 2 3))").unwrap();
+        eval_in(&mut env, "(define b (cdr a))").unwrap();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("a"), "{1 2 3}");
+        assert_eq!(call("b"), "{2 3}");
+
+        // Mutating the shared tail is observed through both references
+        call("(set-car! b 20)");
+        assert_eq!(call("a"), "{1 20 3}");
+        call("(set-cdr! b (mlist 30 40))");
+        assert_eq!(call("a"), "{1 20 30 40}");
+        assert_eq!(call("(mlist->list a)"), "(1 20 30 40)");
+        assert_eq!(call("(car (cdr (cdr a)))"), "30");
+        assert_eq!(call("(mcons 1 2)"), "{1 . 2}");
+        assert_eq!(call("(mpair? a)"), "true");
+        assert_eq!(call("(mpair? '(1))"), "false");
+
+        // Immutable lists are unaffected
+        call("(define c '(1 2))");
+        assert!(eval_in(&mut env, "(set-car! c 5)").is_err());
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("c"), "(1 2)");
+
+        call("(define cycle (mlist 1 2))");
+        call("(set-cdr! (cdr cycle) cycle)");
+        assert_eq!(call("cycle"), "{1 2 ...}");
+        assert!(eval_in(&mut env, "(mlist->list cycle)").is_err());
+        assert!(eval_err("(mlist->list (mcons 1 2))").contains("not a proper mutable list"));
+
+        // A list within itself is not written forever
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        call("(define inner (mlist 1 2))");
+        call("(set-car! inner inner)");
+        assert_eq!(call("inner"), "{{...} 2}");
+        assert_eq!(call("(mlist->list inner)"), "({{...} 2} 2)");
+        call("(set-car! cycle (vector cycle))");
+        assert_eq!(call("cycle"), "{#({...}) 2 ...}");
+        call("(define shared (mlist 1))");
+        assert_eq!(call("(mlist shared shared)"), "{{1} {1}}");
+
+        // Dropping a long list does not overflow the stack
+        call("(define long (apply mlist (make-list 200000 0)))");
+        call("(define long 0)");
+    }
+
+    #[test]
+    fn symbol_append() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define-struct point [x y])").unwrap();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(symbol-append \"make-\" 'point)"), "'make-point");
+        assert_eq!(call("(symbol-append 'point \"-\" 'x)"), "'point-x");
+        let point = call("(eval (cons (symbol-append \"make-\" 'point) '(1 2)))");
+        assert_eq!(point, "(make-point 1 2)");
+        let accessor = "(symbol-append 'point '-y)";
+        let y = call(&format!("(eval (vector->list (vector {} {})))", accessor, point));
+        assert_eq!(y, "2");
+
+        assert!(eval_err("(symbol-append)").contains("empty name"));
+        assert!(eval_err("(symbol-append 'a 1)").contains("neither a str nor a symbol"));
+    }
+
+    #[test]
+    fn bool_coercion() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define-struct point [x y])").unwrap();
+        let mut to_bool = |s: &str| {
+            let res = eval_in(&mut env, &format!("(->bool {})", s)).unwrap();
+            format!("{:?}", res)
+        };
+        assert_eq!(to_bool("#f"), "false");
+        assert_eq!(to_bool("'()"), "false");
+        assert_eq!(to_bool("#t"), "true");
+        assert_eq!(to_bool("0"), "true");
+        assert_eq!(to_bool("\"\""), "true");
+        assert_eq!(to_bool("'sym"), "true");
+        assert_eq!(to_bool("'(#f)"), "true");
+        assert_eq!(to_bool("#()"), "true");
+        assert_eq!(to_bool("(lambda () #f)"), "true");
+        assert_eq!(to_bool("not"), "true");
+        assert_eq!(to_bool("if"), "true");
+        assert_eq!(to_bool("(make-point 0 0)"), "true");
+
+        // Lenient conditions follow the same rule
+        env.set_truthiness(Truthiness::Lenient);
+        for s in &["#f", "'()", "0", "\"\"", "'(#f)", "#()"] {
+            let cond = eval_in(&mut env, &format!("(if {} #t #f)", s)).unwrap();
+            let to_bool = eval_in(&mut env, &format!("(->bool {})", s)).unwrap();
+            assert!(cond == to_bool, "{}", s);
+        }
+
+        assert_eq!(format!("{:?}", eval("(bool-> #f)").unwrap()), "false");
+        assert_eq!(format!("{:?}", eval("(bool-> (->bool 1))").unwrap()), "true");
+        assert!(eval_err("(bool-> 1)").contains("not a bool"));
+    }
+
+    #[test]
+    fn intrinsic_docs() {
+        let doc = |s: &str| match eval(s).unwrap() {
+            Str(doc) => doc,
+            val => panic!("Expected a str, found {}.", val),
+        };
+        assert!(doc("(doc \"car\")").starts_with("car : [A] -> A\nProduces"));
+        let range = doc("(doc 'iter-range)");
+        assert_eq!(range.lines().filter(|line| line.starts_with("iter-range : ")).count(), 3);
+
+        let mut env = new_env();
+        eval_in(&mut env, "(define (area w #:opt h 1) (* w h))").unwrap();
+        let doc = eval_in(&mut env, "(doc 'area)").unwrap();
+        assert_eq!(format!("{:?}", doc), "\"area : (w #:opt h 1)\"");
+
+        // Shadowed intrinsics are not described
+        let doc = eval_in(&mut env, "(let ([car (lambda (l) l)]) (doc 'car))").unwrap();
+        assert_eq!(format!("{:?}", doc), "\"car : (l)\"");
+        assert!(eval_in(&mut env, "(let ([car cdr]) (doc 'car))").is_err());
+        assert!(eval_in(&mut env, "(let ([car 1]) (doc 'car))").is_err());
+
+        assert!(eval_err("(doc \"no-such-function\")").contains("no documentation"));
+        assert!(eval_err("(doc 1)").contains("neither a str nor a symbol"));
+    }
+
+    #[test]
+    fn apropos_search() {
+        let found = format!("{:?}", eval("(apropos \"str\")").unwrap());
+        for name in &["str?", "string-trim", "string->symbol", "substring"] {
+            assert!(found.contains(name));
+        }
+        assert!(!found.contains("car"));
+
+        let mut env = new_env();
+        eval_in(&mut env, "(define my-string-helper 1)").unwrap();
+        let found = eval_in(&mut env, "(apropos 'string-h)").unwrap();
+        assert_eq!(format!("{:?}", found), "('my-string-helper)");
+        assert_eq!(format!("{:?}", eval("(apropos \"no-such-name\")").unwrap()), "()");
+    }
+
+    #[test]
+    fn type_tags() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define-struct point [x y])").unwrap();
+        eval_in(&mut env, "(define (list vals...) vals)").unwrap();
+        let tags = eval_in(
+            &mut env,
+            "(for/list ([val (list 1 #t \"s\" 'sym '(1) #(1) car (lambda () 1)
+                                   (make-point 1 2) (iter '()) (mcons 1 2))])
+               (type-of val))",
+        ).unwrap();
+        assert_eq!(
+            format!("{:?}", tags),
+            "('num 'bool 'str 'symbol 'list 'vector 'function 'function 'struct \
+             'iterator 'mpair)"
+        );
+        assert_eq!(format!("{:?}", eval("(type-of (memoize car))").unwrap()), "'function");
+        assert_eq!(format!("{:?}", eval("(type-of if)").unwrap()), "'macro");
+    }
+
+    #[test]
+    fn set_equality() {
+        let is_set_equal = |s: &str| format!("{:?}", eval(s).unwrap());
+        assert_eq!(is_set_equal("(set-equal? '(1 2 3) '(3 1 2))"), "true");
+        assert_eq!(is_set_equal("(set-equal? '((1 2) \"a\") '(\"a\" (1 2)))"), "true");
+        assert_eq!(is_set_equal("(set-equal? '() '())"), "true");
+        assert_eq!(is_set_equal("(set-equal? '(1 2 3) '(1 2))"), "false");
+        assert_eq!(is_set_equal("(set-equal? '((1 2)) '((2 1)))"), "false");
+
+        // Multiplicity is ignored
+        assert_eq!(is_set_equal("(set-equal? '(1 1 2) '(2 1))"), "true");
+        assert_eq!(is_set_equal("(set-equal? '(1 1 1) '(1))"), "true");
+        assert_eq!(is_set_equal("(set-equal? '(1 1 2) '(1 2 3))"), "false");
+
+        assert!(eval_err("(set-equal? '(1) 1)").contains("is not a list."));
+
+        // Functions are equal to themselves
+        let mut env = new_env();
+        eval_in(&mut env, "(define (list vals...) vals)").unwrap();
+        eval_in(&mut env, "(define (sq x) (* x x))").unwrap();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(set-equal? (list car sq) (list sq car))"), "true");
+        assert_eq!(call("(set-equal? (list car) (list cdr))"), "false");
+        assert_eq!(call("(eq? (memoize sq) (memoize sq))"), "false");
+    }
+
+    #[test]
+    fn set_float_format() {
+        let mut env = new_env();
+        let mut show = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(show("(/ 1 3)"), "0.3333333333333333");
+        show("(set-float-format! 'fixed 3)");
+        assert_eq!(show("(/ 1 3)"), "0.333");
+        show("(set-float-format! 'fixed)");
+        assert_eq!(show("(/ 1 3)"), "0.333333");
+        show("(set-float-format! 'scientific)");
+        assert_eq!(show("(* 1000 1.5)"), "1.5e3");
+        show("(set-float-format! 'scientific 1)");
+        assert_eq!(show("(* 1000 1.5)"), "1.5e3");
+        assert_eq!(show("(concat 0.25)"), "\"2.5e-1\"");
+        show("(set-float-format! 'shortest)");
+        assert_eq!(show("(/ 1 4)"), "0.25");
+
+        assert!(eval_err("(set-float-format! 'short)").contains("is not a float format."));
+        assert!(eval_err("(set-float-format! 'shortest 2)").contains("is not a float format."));
+        assert!(eval_err("(set-float-format! 'fixed 1.5)").contains("not a non-negative integer"));
+        let why = eval_err("(set-float-format! 'fixed 70000)");
+        assert!(why.contains("70000 is more than 17 decimal places."));
+        assert!(eval_err("(set-float-format! 'scientific 18)").contains("more than 17"));
+    }
+
+    #[test]
+    fn unbound_suggestions() {
+        assert!(eval_err("(lenn '(1 2))").contains("Did you mean len?"));
+        assert!(eval_err("(let ([length 10]) (+ lenght 1))").contains("Did you mean length?"));
+        assert!(eval_err("((lambda (width) (* wdth 2)) 1)").contains("Did you mean width?"));
+        assert!(!eval_err("((lambda ((a b)) arg0) '(1 2))").contains("Did you mean"));
+
+        let msg = eval_err("(+ xyzzy 1)");
+        assert!(msg.contains("is unbound."));
+        assert!(!msg.contains("Did you mean"));
     }
 }
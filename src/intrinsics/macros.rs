@@ -1,9 +1,11 @@
 // Imports
 use super::*;
 use SExpr::*;
+use std::cell::RefCell;
+use std::rc::Rc;
 
-/// Represents the output of a function.
-type Output = Result<Value>;
+/// Represents the output of a macro.
+type Output = Result<Tail>;
 
 /// Represents a mutable reference to an environment.
 type Env<'a> = &'a mut Environment;
@@ -14,18 +16,36 @@ type Exprs<'a> = &'a [SExpr];
 /// `(define ident value)`
 ///
 /// `(define (func-name param1 ...) body)`
+///
+/// Shadowing an intrinsic function is subject to the environment's
+/// `ShadowPolicy`.
 pub fn _define(env: Env, exprs: Exprs) -> Output {
     let len = exprs.len();
     if len > 2 {
         let (ident, val) = (&exprs[1], &exprs[2]);
         match ident {
             // Define variable
-            Ident(s, _) => {
-                if len == 3 {
-                    if RESERVED_WORDS.contains(&s.as_str()) {
+            Ident(s, variadic) => {
+                if *variadic {
+                    err(misplaced_variadic(s))
+                } else if len == 3 {
+                    if is_reserved_word(s) {
                         Err(reserved_word(s))
                     } else {
-                        let val = val.eval(env)?;
+                        if let Some(Value::Intrinsic(_)) = env.get(s) {
+                            match env.shadow_policy() {
+                                ShadowPolicy::Allow => (),
+                                ShadowPolicy::Warn => {
+                                    env.warn(format!("{} shadows an intrinsic function.", s))
+                                }
+                                ShadowPolicy::Protect => return Err(protected_name(s)),
+                            }
+                        }
+
+                        let mut val = val.eval(env)?;
+                        if let Value::Func(Lambda { name: ref mut name @ None, .. }) = val {
+                            *name = Some(s.clone());
+                        }
                         env.define(s.clone(), val);
                         ok(nil())
                     }
@@ -43,16 +63,16 @@ pub fn _define(env: Env, exprs: Exprs) -> Output {
             List(vals) => {
                 let vals_len = vals.len();
                 if vals_len == 0 {
-                    err(format!("Cannot redefine empty list."))
+                    err("Cannot redefine empty list.")
                 } else {
-                    let ident = (&vals[0]).clone();
-                    let params: Vec<_> = (&vals[1..]).iter().map(|expr| expr.clone()).collect();
+                    let ident = vals[0].clone();
+                    let params: Vec<_> = vals[1..].to_vec();
 
                     let body = if len > 3 {
                         let mut vec = Vec::<SExpr>::with_capacity(vals_len - 1);
                         vec.push(Ident("begin".to_string(), false));
 
-                        let statements = (&exprs[2..]).iter().map(|expr| expr.clone());
+                        let statements = exprs[2..].iter().cloned();
                         vec.extend(statements);
                         List(vec)
                     } else {
@@ -65,7 +85,7 @@ pub fn _define(env: Env, exprs: Exprs) -> Output {
                         List(vec![Ident("lambda".to_string(), false), List(params), body]),
                     ]);
 
-                    define.eval(env)
+                    define.eval(env).map(Tail::Value)
                 }
             }
             _ => err(not_an_identifier(ident)),
@@ -75,106 +95,450 @@ pub fn _define(env: Env, exprs: Exprs) -> Output {
     }
 }
 
-/// `(lambda [param1 ...] body)
-pub fn _lambda(_: Env, exprs: Exprs) -> Output {
+/// `(set! ident value)`
+///
+/// Assigns the specified value to the nearest existing binding of the
+/// specified identifier, including a binding captured by a function. Unlike
+/// `define`, it is an error for the identifier to be unbound. Assigning to an
+/// intrinsic function is subject to the environment's `ShadowPolicy`.
+pub fn _set(env: Env, exprs: Exprs) -> Output {
     let len = exprs.len();
     if len != 3 {
         return err(arity_exact(2, len - 1));
     }
 
-    let (params, body) = (&exprs[1], &exprs[2]);
-    match params {
-        List(params) => {
-            let len = params.len();
-            let mut names = Vec::<String>::with_capacity(len);
-            for (i, param) in params.iter().enumerate() {
-                match param {
-                    &SExpr::Ident(ref s, variadic) => {
-                        if variadic && i != len - 1 {
-                            return err("Only the final parameter of a function may be variadic.");
-                        }
-                        names.push(s.to_string())
+    let ident = match &exprs[1] {
+        Ident(s, true) => return err(misplaced_variadic(s)),
+        Ident(s, false) if is_reserved_word(s) => return Err(reserved_word(s)),
+        Ident(s, false) => s,
+        other => return err(not_an_identifier(other)),
+    };
+
+    match env.get(ident) {
+        Some(Value::Intrinsic(_)) => match env.shadow_policy() {
+            ShadowPolicy::Allow => (),
+            ShadowPolicy::Warn => env.warn(format!("{} shadows an intrinsic function.", ident)),
+            ShadowPolicy::Protect => return Err(protected_name(ident)),
+        },
+        Some(_) => (),
+        None => return Err(unbound(ident)),
+    }
+
+    let val = exprs[2].eval(env)?;
+    env.set(ident, val);
+    ok(nil())
+}
+
+/// `(lambda [param1 ...] body ...)
+///
+/// A parameter may also be a list pattern, such as `(x y)`, in which case the
+/// corresponding argument is destructured as in `let`. Optional parameters
+/// follow the required parameters in the form `#:opt name default`, such as
+/// `#:opt b 10` or `#:opt b (+ a 1)`. Keyword parameters are declared in the form
+/// `#:key name default`, and may follow a variadic parameter. They are passed
+/// by callers in the form `#:name value`. Every parameter must have a distinct
+/// name.
+/// If an optional or keyword argument is not passed, its default expression is
+/// evaluated when the function is called. The function captures the scopes in
+/// which it is created, such as those of an enclosing `let`, so that its body
+/// may continue to refer to and `set!` their bindings after they are exited.
+/// If the body has more than one expression, they are evaluated in order as in
+/// `begin`.
+pub fn _lambda(env: Env, exprs: Exprs) -> Output {
+    let len = exprs.len();
+    if len < 3 {
+        return err(arity_at_least(2, len - 1));
+    }
+
+    let params = &exprs[1];
+    let body = if len > 3 {
+        let mut vec = vec![Ident("begin".to_string(), false)];
+        vec.extend(exprs[2..].iter().cloned());
+        List(vec)
+    } else {
+        exprs[2].clone()
+    };
+    let params = match params {
+        List(params) => params,
+        _ => return err(not_a_list(params)),
+    };
+
+    let len = params.len();
+    let mut names = Vec::<String>::with_capacity(len);
+    let mut optional = Vec::<(String, SExpr)>::new();
+    let mut keywords = Vec::<(String, SExpr)>::new();
+    let mut patterns = Vec::<SExpr>::new();
+    let mut variadic = false;
+    let mut iter = params.iter().enumerate();
+    while let Some((i, param)) = iter.next() {
+        match param {
+            // Optional or keyword parameter, in the form: #:opt name default,
+            // or #:key name default
+            _ if is_keyword(param, "opt") || is_keyword(param, "key") => {
+                let name = match iter.next() {
+                    Some((_, name @ Ident(_, false))) if keyword_name(name).is_some() => {
+                        return err(not_an_identifier(name))
                     }
-                    _ => return err(not_an_identifier(param)),
+                    Some((_, Ident(name, false))) => name,
+                    Some((_, name)) => return err(not_an_identifier(name)),
+                    None => return err(format!("Expected a name after {}.", param)),
+                };
+                let default = match iter.next() {
+                    Some((_, default)) => default.clone(),
+                    None => return err(format!("Expected a default value for #:{}.", name)),
+                };
+                if is_keyword(param, "key") {
+                    keywords.push((name.clone(), default));
+                } else if variadic {
+                    return err("Optional parameters may not follow a variadic parameter.");
+                } else {
+                    optional.push((name.clone(), default));
                 }
             }
-            let variadic = if len > 0 {
-                if let &SExpr::Ident(_, v) = &params[len - 1] {
-                    v
-                } else {
-                    false
+            _ if keyword_name(param).is_some() => {
+                return err(format!("Unknown keyword {} in parameter list.", param));
+            }
+
+            // Only keyword parameters may follow the variadic parameter
+            Ident(..) | List(_) if variadic => {
+                return err("Only the final parameter of a function may be variadic.");
+            }
+
+            &SExpr::Ident(ref s, is_variadic) => {
+                if !is_variadic && !optional.is_empty() {
+                    return err("Required parameters may not follow optional parameters.");
                 }
-            } else {
-                false
-            };
-            Ok(Value::Func(names, body.clone(), variadic))
+                variadic = is_variadic;
+                names.push(s.to_string())
+            }
+
+            // Bind the argument to a hidden parameter, to be
+            // destructured by a let around the body
+            List(_) if !optional.is_empty() => {
+                return err("Required parameters may not follow optional parameters.");
+            }
+            List(_) => {
+                let name = format!("{}{}", HIDDEN_PARAM, i);
+                patterns.push(List(vec![param.clone(), Ident(name.clone(), false)]));
+                names.push(name);
+            }
+            _ => return err(not_an_identifier(param)),
         }
-        _ => err(not_a_list(params)),
     }
+
+    // Each parameter must have a distinct name, as keyword arguments are
+    // bound alongside positional ones
+    let mut all_names = names
+        .iter()
+        .chain(optional.iter().map(|(name, _)| name))
+        .chain(keywords.iter().map(|(name, _)| name))
+        .collect::<Vec<_>>();
+    all_names.sort();
+    if let Some(pair) = all_names.windows(2).find(|pair| pair[0] == pair[1]) {
+        return err(format!("The parameter {} is declared more than once.", pair[0]));
+    }
+
+    let body = if patterns.is_empty() {
+        body
+    } else {
+        List(vec![Ident("let".to_string(), false), List(patterns), body])
+    };
+    ok(Value::Func(Lambda {
+        params: names,
+        variadic,
+        optional,
+        keywords,
+        body,
+        name: None,
+        captures: env.capture(),
+    }))
+}
+
+/// `(quote expr)`
+///
+/// Produces the specified expression as data, without evaluating it. This is
+/// equivalent to `'expr`.
+pub fn _quote(_: Env, exprs: Exprs) -> Output {
+    let len = exprs.len() - 1;
+    if len != 1 {
+        return Err(arity_exact(1, len));
+    }
+    ok(Value::from(exprs[1].clone()))
 }
 
 /// `(if bool value1 value2)`
 ///
+/// `(if bool value1)`
+///
 /// If the specified bool is true, the first value is returned. Otherwise,
-/// the second value is returned.
+/// the second value is returned, or nil if there is no second value. Under
+/// lenient truthiness, the condition may be any value.
 pub fn _if(env: Env, exprs: Exprs) -> Output {
     let len = exprs.len();
-    if len != 4 {
-        return err(arity_exact(3, len - 1));
+    if len < 3 {
+        return err(arity_at_least(2, len - 1));
+    } else if len > 4 {
+        return err(arity_at_most(3, len - 1));
     }
 
-    let (cond, then, other) = (&exprs[1], &exprs[2], &exprs[3]);
-    let cond = match cond.eval(env)? {
-        Value::Bool(cond) => cond,
-        _ => return err(not_a_bool(&cond)),
+    let (cond, then) = (&exprs[1], &exprs[2]);
+    let val = cond.eval(env)?;
+    let cond = match env.truth_of(&val) {
+        Some(cond) => cond,
+        None => return err(not_a_bool(cond)),
     };
 
     if cond {
-        then.eval(env)
+        Ok(Tail::Expr(then.clone()))
+    } else if let Some(other) = exprs.get(3) {
+        Ok(Tail::Expr(other.clone()))
     } else {
-        other.eval(env)
+        ok(nil())
     }
 }
 
-/// `(cond [cond1 value1] ...)`
+/// `(begin expr ...)`
+///
+/// Evaluates each of the specified expressions in order, producing the value
+/// of the final expression, or nil if there are none.
+pub fn _begin(env: Env, exprs: Exprs) -> Output {
+    match exprs[1..].split_last() {
+        Some((last, exprs)) => {
+            for expr in exprs {
+                expr.eval(env)?;
+            }
+            Ok(Tail::Expr(last.clone()))
+        }
+        None => ok(nil()),
+    }
+}
+
+/// `(begin0 expr1 expr ...)`
+///
+/// Evaluates each of the specified expressions in order, producing the value
+/// of the first expression.
+pub fn _begin0(env: Env, exprs: Exprs) -> Output {
+    let len = exprs.len();
+    if len < 2 {
+        return err(arity_at_least(1, len - 1));
+    }
+
+    let first = exprs[1].eval(env)?;
+    for expr in &exprs[2..] {
+        expr.eval(env)?;
+    }
+    ok(first)
+}
+
+/// `(with-output-to-string expr ...)`
+///
+/// Evaluates each of the specified expressions in order, producing everything
+/// they print as a str rather than writing it to the environment's output.
+pub fn _with_output_to_string(env: Env, exprs: Exprs) -> Output {
+    let buf = Rc::new(RefCell::new(Vec::<u8>::new()));
+    let prev = env.set_output(buf.clone());
+    let res = exprs[1..].iter().try_for_each(|expr| expr.eval(env).map(|_| ()));
+    env.set_output(prev);
+    res?;
+
+    let out = String::from_utf8_lossy(&buf.borrow()).into_owned();
+    ok(out)
+}
+
+/// `(generator limit body ...)`
+///
+/// Evaluates the specified body, collecting each value it passes to `yield`,
+/// and produces an iterator over the collected values. Evaluation cannot be
+/// suspended, so the body is evaluated immediately rather than resumed by
+/// each `iter-next`, and it is stopped if it attempts to yield more than the
+/// specified limit of values. An infinite generator thus produces its first
+/// `limit` values.
+pub fn _generator(env: Env, exprs: Exprs) -> Output {
+    let len = exprs.len();
+    if len < 3 {
+        return err(arity_at_least(2, len - 1));
+    }
+
+    let limit = match exprs[1].eval(env)? {
+        Value::Num(n) if n >= 0.0 && n.fract() == 0.0 => n as usize,
+        val => return err(format!("{} is not a non-negative integer.", val)),
+    };
+
+    env.enter_generator(limit);
+    let res = exprs[2..].iter().try_for_each(|expr| expr.eval(env).map(|_| ()));
+    let yields = env.exit_generator();
+    match res {
+        // Reaching the limit is not an error
+        Err(_) if yields.stopped => (),
+        res => res?,
+    }
+
+    let values = IterSource::List(yields.values.into());
+    ok(Value::Iter(SharedIter::new(values)))
+}
+
+/// `(-> expr step ...)`
+///
+/// Threads the specified expression through each of the specified steps, by
+/// inserting it as the first argument of the step, such that
+/// `(-> x (f a) (g b))` is equivalent to `(g (f x a) b)`. A step which is not
+/// a list is called with the expression as its only argument.
+pub fn _thread_first(_: Env, exprs: Exprs) -> Output {
+    thread(exprs, false).map(Tail::Expr)
+}
+
+/// `(->> expr step ...)`
+///
+/// Threads the specified expression through each of the specified steps, by
+/// inserting it as the last argument of the step, such that
+/// `(->> x (f a) (g b))` is equivalent to `(g b (f a x))`.
+pub fn _thread_last(_: Env, exprs: Exprs) -> Output {
+    thread(exprs, true).map(Tail::Expr)
+}
+
+/// Expands the specified threading macro, inserting the threaded expression
+/// at the end of each step if specified, or otherwise as the first argument.
+fn thread(exprs: Exprs, last: bool) -> Result<SExpr> {
+    let (init, steps) = match exprs.get(1..) {
+        Some([init, steps @ ..]) => (init, steps),
+        _ => return Err(arity_at_least(1, 0)),
+    };
+
+    let expanded = steps.iter().fold(init.clone(), |threaded, step| match step {
+        List(vals) if !vals.is_empty() => {
+            let mut call = vals.clone();
+            if last {
+                call.push(threaded);
+            } else {
+                call.insert(1, threaded);
+            }
+            List(call)
+        }
+        step => List(vec![step.clone(), threaded]),
+    });
+    Ok(expanded)
+}
+
+/// Determines whether or not the specified expression is the `else` keyword.
+fn is_else(expr: &SExpr) -> bool {
+    match expr {
+        Ident(s, false) => s == "else",
+        _ => false,
+    }
+}
+
+/// Determines whether or not the specified expression is the `=>` keyword.
+fn is_arrow(expr: &SExpr) -> bool {
+    match expr {
+        Ident(s, false) => s == "=>",
+        _ => false,
+    }
+}
+
+/// `(cond [cond1 value1] ... [else value])`
+///
+/// `(cond [test => func] ...)`
 ///
 /// Steps through the condition expressions. If one of the conditions
 /// evaluates to true, its value is returned. Otherwise, the next
-/// next expression is checked, etc.
+/// next expression is checked, etc. The `else` keyword is a condition that
+/// is always true. Under lenient truthiness, a condition may be any value. In
+/// a `=>` clause, if the test is true, the function is applied to the value of
+/// the test, and its output is returned; the test is a condition like any other.
 pub fn _cond(env: Env, exprs: Exprs) -> Output {
     let conditions = &exprs[1..];
-    env.enter_scope(SExpr::List(exprs.to_vec()));
-    env.define("else", Value::Bool(true));
     for condition in conditions.iter() {
         match condition {
             List(vals) => {
                 let len = vals.len();
                 match len {
                     2 => {
+                        if is_else(&vals[0]) {
+                            return Ok(Tail::Expr(vals[1].clone()));
+                        }
+
                         let condition = vals[0].eval(env)?;
-                        if let Value::Bool(b) = condition {
-                            if b {
-                                env.exit_scope();
-                                return vals[1].eval(env);
-                            }
-                        } else {
-                            env.exit_scope();
-                            return err(format!("{} is not a bool.", condition));
+                        match env.truth_of(&condition) {
+                            Some(true) => return Ok(Tail::Expr(vals[1].clone())),
+                            Some(false) => (),
+                            None => return err(format!("{} is not a bool.", condition)),
                         }
                     }
-                    n => {
-                        env.exit_scope();
-                        return err(arity_exact(2, n));
+                    3 if is_arrow(&vals[1]) => {
+                        let test = vals[0].eval(env)?;
+                        match env.truth_of(&test) {
+                            Some(true) => {
+                                let func = vals[2].eval(env)?;
+                                return call_fn(condition, &func, &[test], env)
+                                    .map(Tail::Value);
+                            }
+                            Some(false) => (),
+                            None => return err(format!("{} is not a bool.", test)),
+                        }
                     }
+                    n => return err(arity_exact(2, n)),
                 }
             }
-            _ => {
-                env.exit_scope();
-                return err(not_a_list(condition));
-            }
+            _ => return err(not_a_list(condition)),
+        }
+    }
+    ok(nil())
+}
+
+/// Determines whether or not the specified value is of the type with the
+/// specified name. Names other than the built-in types refer to structs.
+fn has_type(env: Env, val: &Value, ty: &str) -> Result<bool> {
+    match ty {
+        // Functions may also be matched as `lambda`
+        "lambda" => Ok(functions::type_of(val) == "function"),
+        ty if functions::TYPE_NAMES.contains(&ty) => Ok(functions::type_of(val) == ty),
+        name => match env.get_struct(name) {
+            Some(_) => match val {
+                Value::Struct(struct_name, _) => Ok(struct_name == name),
+                _ => Ok(false),
+            },
+            None => Err(format!("Unknown type {}.", name).into()),
+        },
+    }
+}
+
+/// `(typecase expr [type1 value1] ... [else value])`
+///
+/// Evaluates the expression once, then returns the value of the first
+/// clause whose type matches it. The types are those produced by `type-of`,
+/// such as `num`, `str`, `function`, and `struct`, along with `lambda` for
+/// any function and the names of structs. The `else` keyword matches any
+/// value.
+pub fn _typecase(env: Env, exprs: Exprs) -> Output {
+    let len = exprs.len();
+    if len < 2 {
+        return err(arity_at_least(1, len - 1));
+    }
+
+    let val = exprs[1].eval(env)?;
+    for clause in &exprs[2..] {
+        match clause {
+            List(vals) => match vals.len() {
+                2 => {
+                    let (ty, body) = (&vals[0], &vals[1]);
+                    if is_else(ty) {
+                        return Ok(Tail::Expr(body.clone()));
+                    }
+
+                    match ty {
+                        Ident(name, false) => {
+                            if has_type(env, &val, name)? {
+                                return Ok(Tail::Expr(body.clone()));
+                            }
+                        }
+                        _ => return err(not_an_identifier(ty)),
+                    }
+                }
+                n => return err(arity_exact(2, n)),
+            },
+            _ => return err(not_a_list(clause)),
         }
     }
-    env.exit_scope();
     ok(nil())
 }
 
@@ -183,6 +547,9 @@ pub fn _cond(env: Env, exprs: Exprs) -> Output {
 ///       ...)
 ///     expr)
 /// `
+///
+/// An identifier may also be a list pattern, such as `(a (b c))`, which binds
+/// each of its identifiers to the corresponding element of the value.
 pub fn _let(env: Env, exprs: Exprs) -> Output {
     let len = exprs.len() - 1;
     if len != 2 {
@@ -203,9 +570,9 @@ pub fn _let(env: Env, exprs: Exprs) -> Output {
 
                         let binding = (&binding[0], &binding[1]);
                         match binding {
-                            (&Ident(ref s, _), expr) => {
+                            (pattern @ Ident(..), expr) | (pattern @ List(_), expr) => {
                                 let res = expr.eval(env)?;
-                                env.define(s.clone(), res);
+                                bind_pattern(env, pattern, res)?;
                             }
                             _ => {
                                 env.exit_scope();
@@ -220,14 +587,479 @@ pub fn _let(env: Env, exprs: Exprs) -> Output {
                 }
             }
 
-            let res = body.eval(env);
-            env.exit_scope();
-            res
+            // The scope is exited once the body has been evaluated
+            Ok(Tail::Expr(body.clone()))
         }
         _ => Err(not_a_list(args.0)),
     }
 }
 
+/// Binds the specified value to the specified pattern in the current scope.
+/// An identifier binds the whole value, while a list pattern binds each of
+/// its elements against the corresponding element of a list value of the
+/// same length. A variadic identifier at the end of a list pattern binds the
+/// remaining elements.
+fn bind_pattern(env: Env, pattern: &SExpr, val: Value) -> Result<()> {
+    let patterns = match pattern {
+        Ident(s, true) => return Err(misplaced_variadic(s)),
+        Ident(s, false) => {
+            env.define(s.clone(), val);
+            return Ok(());
+        }
+        List(patterns) => patterns,
+        _ => return Err(not_an_identifier(pattern)),
+    };
+
+    let vals = match &val {
+        Value::List(vals) => vals,
+        _ => return Err(format!("Cannot bind {} to the pattern {}.", val, pattern).into()),
+    };
+
+    let variadic = match patterns.last() {
+        Some(&Ident(_, variadic)) => variadic,
+        _ => false,
+    };
+    let fixed = if variadic { patterns.len() - 1 } else { patterns.len() };
+    if vals.len() < fixed || !variadic && vals.len() != fixed {
+        return Err(format!("Cannot bind {} to the pattern {}.", val, pattern).into());
+    }
+
+    for (i, pattern) in patterns[..fixed].iter().enumerate() {
+        bind_pattern(env, pattern, vals[i].clone())?;
+    }
+    if let Some(Ident(s, true)) = patterns.get(fixed) {
+        env.define(s.clone(), Value::List(vals[fixed..].iter().cloned().collect()));
+    }
+    Ok(())
+}
+
+/// `(match expr [pattern body] ...)`
+///
+/// Evaluates the specified expression and matches its value against the
+/// pattern of each clause in order, evaluating the body of the first clause
+/// whose pattern matches, with the pattern's bindings in scope. A pattern may
+/// be any of:
+/// * `_`: Matches any value, without binding it
+/// * An identifier: Matches any value, binding it to the identifier
+/// * A num, str, bool, or quoted literal: Matches an equal value
+/// * `(pattern ...)`: Matches a list of the same length, element by element
+/// * `(pattern ... . rest)` or `(pattern ... rest...)`: Matches a list of at
+///   least as many elements, where the rest of the list is matched by `rest`
+/// * `(struct-name pattern ...)`: Matches a struct of the named type, field by
+///   field, if `struct-name` is a defined struct
+///
+/// It is an error for no clause to match.
+pub fn _match(env: Env, exprs: Exprs) -> Output {
+    let (expr, clauses) = match exprs.get(1..) {
+        Some([expr, clauses @ ..]) => (expr, clauses),
+        _ => return Err(arity_at_least(1, 0)),
+    };
+    let val = expr.eval(env)?;
+
+    for clause in clauses {
+        let (pattern, body) = match clause {
+            List(clause) if clause.len() == 2 => (&clause[0], &clause[1]),
+            List(clause) => return Err(arity_exact(2, clause.len())),
+            clause => return Err(not_a_list(clause)),
+        };
+
+        let mut bindings = vec![];
+        if match_pattern(env, pattern, &val, &mut bindings)? {
+            env.enter_scope(SExpr::List(exprs.to_vec()));
+            for (ident, val) in bindings {
+                env.define(ident, val);
+            }
+
+            // The scope is exited once the body has been evaluated
+            return Ok(Tail::Expr(body.clone()));
+        }
+    }
+
+    err(format!("No clause matched {}.", val))
+}
+
+/// Determines whether or not the specified value matches the specified
+/// `match` pattern, collecting the bindings it produces.
+fn match_pattern(
+    env: Env,
+    pattern: &SExpr,
+    val: &Value,
+    bindings: &mut Vec<(String, Value)>,
+) -> Result<bool> {
+    let patterns = match pattern {
+        Ident(s, false) if s == "_" => return Ok(true),
+        Ident(s, true) => return Err(misplaced_variadic(s)),
+        Ident(s, false) => {
+            bindings.push((s.clone(), val.clone()));
+            return Ok(true);
+        }
+        Num(_) | Str(_) | Bool(_) => return Ok(Value::from(pattern.clone()) == *val),
+        Quote(quoted) => return Ok(Value::from((**quoted).clone()) == *val),
+        List(patterns) => patterns,
+        _ => return Err(format!("{} is not a valid pattern.", pattern).into()),
+    };
+
+    // Struct pattern
+    if let Some(Ident(name, false)) = patterns.first() {
+        if let Some(fields) = env.get_struct(name.as_str()) {
+            let arity = fields.len();
+            if patterns.len() - 1 != arity {
+                return Err(arity_exact(arity, patterns.len() - 1));
+            }
+            return match val {
+                Value::Struct(ref val_name, ref vals) if val_name == name => {
+                    match_all(env, &patterns[1..], vals, bindings)
+                }
+                _ => Ok(false),
+            };
+        }
+    }
+
+    // List pattern, with an optional rest pattern
+    let (fixed, rest) = match patterns.split_last() {
+        Some((&Ident(ref s, true), fixed)) => (fixed, Some(Ident(s.clone(), false))),
+        _ => match patterns.len().checked_sub(2).map(|i| patterns.split_at(i)) {
+            Some((fixed, [Ident(dot, false), rest])) if dot == "." => (fixed, Some(rest.clone())),
+            _ => (&patterns[..], None),
+        },
+    };
+    let vals = match val {
+        Value::List(vals) => vals,
+        _ => return Ok(false),
+    };
+    match rest {
+        None if vals.len() != fixed.len() => Ok(false),
+        Some(_) if vals.len() < fixed.len() => Ok(false),
+        None => match_all(env, fixed, vals, bindings),
+        Some(rest) => {
+            let rest_vals = Value::List(vals[fixed.len()..].iter().cloned().collect());
+            Ok(match_all(env, fixed, &vals[..fixed.len()], bindings)?
+                && match_pattern(env, &rest, &rest_vals, bindings)?)
+        }
+    }
+}
+
+/// Determines whether or not each of the specified values matches the
+/// corresponding pattern, collecting the bindings they produce.
+fn match_all(
+    env: Env,
+    patterns: &[SExpr],
+    vals: &[Value],
+    bindings: &mut Vec<(String, Value)>,
+) -> Result<bool> {
+    for (pattern, val) in patterns.iter().zip(vals) {
+        if !match_pattern(env, pattern, val, bindings)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// `(let-values ([(ident1 ...) values1] ...) body)`
+///
+/// Evaluates each values expression and binds each of the values it produces
+/// to the corresponding identifier, then evaluates the body with those
+/// bindings. Each expression must produce exactly as many values as it has
+/// identifiers.
+pub fn _let_values(env: Env, exprs: Exprs) -> Output {
+    let len = exprs.len() - 1;
+    if len != 2 {
+        return Err(arity_exact(2, len));
+    }
+
+    let (bindings, body) = match (&exprs[1], &exprs[2]) {
+        (List(bindings), body) => (bindings, body),
+        (bindings, _) => return Err(not_a_list(bindings)),
+    };
+
+    let depth = env.depth();
+    env.enter_scope(SExpr::List(exprs.to_vec()));
+    if let Err(why) = bind_values(env, bindings) {
+        env.truncate_scopes(depth);
+        return Err(why);
+    }
+
+    // The scope is exited once the body has been evaluated
+    Ok(Tail::Expr(body.clone()))
+}
+
+/// Binds the values produced by each `[(ident ...) values]` binding in the
+/// current scope.
+fn bind_values(env: Env, bindings: &[SExpr]) -> Result<()> {
+    for binding in bindings {
+        let binding = match binding {
+            List(binding) if binding.len() == 2 => binding,
+            List(binding) => return Err(arity_exact(2, binding.len())),
+            _ => return Err(not_a_list(binding)),
+        };
+
+        let idents = match &binding[0] {
+            List(idents) => idents,
+            idents => return Err(not_a_list(idents)),
+        };
+
+        let vals = match binding[1].eval(env)? {
+            Value::Values(vals) => vals,
+            val => vec![val],
+        };
+        if idents.len() != vals.len() {
+            return Err(values_exact(idents.len(), vals.len()));
+        }
+
+        for (ident, val) in idents.iter().zip(vals) {
+            match ident {
+                Ident(s, false) => env.define(s.clone(), val),
+                Ident(s, true) => return Err(misplaced_variadic(s)),
+                _ => return Err(not_an_identifier(ident)),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `
+/// (do ([ident1 init1 step1]
+///      ...)
+///     (test result ...)
+///     body ...)
+/// `
+///
+/// Binds each identifier to its initial value, then repeatedly evaluates the
+/// body until the test evaluates to true, at which point the final result is
+/// returned. After each iteration, each identifier is rebound to the value of
+/// its step expression, which sees the values of the previous iteration. An
+/// identifier without a step expression keeps its value. Under lenient
+/// truthiness, the test may produce any value.
+pub fn _do(env: Env, exprs: Exprs) -> Output {
+    let len = exprs.len() - 1;
+    if len < 2 {
+        return Err(arity_at_least(2, len));
+    }
+
+    // Read loop variables
+    let mut vars: Vec<DoVar> = vec![];
+    let mut values: Vec<Value> = vec![];
+    match &exprs[1] {
+        List(bindings) => for expr in bindings.iter() {
+            match expr {
+                List(binding) if binding.len() == 2 || binding.len() == 3 => match &binding[0] {
+                    Ident(s, _) => {
+                        vars.push(DoVar {
+                            ident: s.clone(),
+                            step: binding.get(2),
+                        });
+                        values.push(binding[1].eval(env)?);
+                    }
+                    ident => return Err(not_an_identifier(ident)),
+                },
+                List(binding) => return Err(arity_at_least(2, binding.len())),
+                _ => return Err(not_a_list(expr)),
+            }
+        },
+        bindings => return Err(not_a_list(bindings)),
+    }
+
+    // Read test and result
+    let (test, results) = match &exprs[2] {
+        List(clause) if !clause.is_empty() => (&clause[0], &clause[1..]),
+        List(_) => return Err(arity_at_least(1, 0)),
+        clause => return Err(not_a_list(clause)),
+    };
+    let body = &exprs[3..];
+
+    loop {
+        env.enter_scope(SExpr::List(exprs.to_vec()));
+        let res = do_iteration(env, &vars, &mut values, test, results, body);
+        env.exit_scope();
+        if let Some(res) = res? {
+            return ok(res);
+        }
+    }
+}
+
+/// A loop variable of a `do` loop.
+struct DoVar<'a> {
+    ident: String,
+    step: Option<&'a SExpr>,
+}
+
+/// Performs a single iteration of a `do` loop. If the loop's test is true,
+/// the loop's result is produced. Otherwise, the loop variables' values are
+/// replaced with those for the next iteration.
+fn do_iteration(
+    env: Env,
+    vars: &[DoVar],
+    values: &mut [Value],
+    test: &SExpr,
+    results: Exprs,
+    body: Exprs,
+) -> Result<Option<Value>> {
+    for (var, value) in vars.iter().zip(values.iter()) {
+        env.define(var.ident.clone(), value.clone());
+    }
+
+    let val = test.eval(env)?;
+    match env.truth_of(&val) {
+        Some(true) => {
+            let mut res = nil();
+            for expr in results.iter() {
+                res = expr.eval(env)?;
+            }
+            return Ok(Some(res));
+        }
+        Some(false) => (),
+        None => return Err(not_a_bool(test)),
+    }
+
+    for expr in body.iter() {
+        expr.eval(env)?;
+    }
+
+    for (var, value) in vars.iter().zip(values.iter_mut()) {
+        if let Some(step) = var.step {
+            *value = step.eval(env)?;
+        }
+    }
+    Ok(None)
+}
+
+/// Determines whether or not the specified expression is the keyword with the
+/// specified name.
+fn is_keyword(expr: &SExpr, name: &str) -> bool {
+    keyword_name(expr) == Some(name)
+}
+
+/// `
+/// (for/list ([ident1 list1]
+///            ...
+///            #:when test)
+///     body)
+/// `
+///
+/// Binds each identifier to the successive elements of its list in parallel,
+/// stopping at the end of the shortest list, and produces a list of the
+/// body's values. If any `#:when` tests are specified, elements for which a
+/// test is false are skipped. Under lenient truthiness, a test may produce
+/// any value.
+pub fn _for_list(env: Env, exprs: Exprs) -> Output {
+    let len = exprs.len() - 1;
+    if len != 2 {
+        return Err(arity_exact(2, len));
+    }
+
+    // Read generators and filters
+    let mut idents: Vec<&str> = vec![];
+    let mut seqs: Vec<Vec<Value>> = vec![];
+    let mut tests: Vec<&SExpr> = vec![];
+    let clauses = match &exprs[1] {
+        List(clauses) => clauses,
+        clauses => return Err(not_a_list(clauses)),
+    };
+    let mut clauses = clauses.iter();
+    while let Some(clause) = clauses.next() {
+        match clause {
+            clause if is_keyword(clause, "when") => match clauses.next() {
+                Some(test) => tests.push(test),
+                None => return err("Expected an expression after #:when."),
+            },
+            List(generator) if generator.len() == 2 => match &generator[0] {
+                Ident(s, _) => {
+                    idents.push(s);
+                    let seq = match generator[1].eval(env)? {
+                        Value::List(vals) => vals.to_vec(),
+                        Value::Vector(vals) => vals,
+                        val => return err(format!("{} is not a list.", val)),
+                    };
+                    seqs.push(seq);
+                }
+                ident => return Err(not_an_identifier(ident)),
+            },
+            List(generator) => return Err(arity_exact(2, generator.len())),
+            _ => return Err(not_a_list(clause)),
+        }
+    }
+
+    let body = &exprs[2];
+    let count = seqs.iter().map(|seq| seq.len()).min().unwrap_or(0);
+    let mut results = Vec::with_capacity(count);
+    for i in 0..count {
+        env.enter_scope(SExpr::List(exprs.to_vec()));
+        let res = for_iteration(env, &idents, &seqs, i, &tests, body);
+        env.exit_scope();
+        if let Some(res) = res? {
+            results.push(res);
+        }
+    }
+    ok(results)
+}
+
+/// Performs a single iteration of a `for/list` comprehension, producing the
+/// value of the body unless one of the tests is false.
+fn for_iteration(
+    env: Env,
+    idents: &[&str],
+    seqs: &[Vec<Value>],
+    i: usize,
+    tests: &[&SExpr],
+    body: &SExpr,
+) -> Result<Option<Value>> {
+    for (ident, seq) in idents.iter().zip(seqs.iter()) {
+        env.define(*ident, seq[i].clone());
+    }
+
+    for test in tests.iter() {
+        let val = test.eval(env)?;
+        match env.truth_of(&val) {
+            Some(true) => (),
+            Some(false) => return Ok(None),
+            None => return Err(not_a_bool(test)),
+        }
+    }
+
+    body.eval(env).map(Some)
+}
+
+/// `(assert expr)`
+///
+/// Produces an error containing the specified expression if it does not
+/// evaluate to true, or to a truthy value under lenient truthiness.
+/// Otherwise, nil is produced.
+pub fn _assert(env: Env, exprs: Exprs) -> Output {
+    let len = exprs.len() - 1;
+    if len != 1 {
+        return Err(arity_exact(1, len));
+    }
+
+    let expr = &exprs[1];
+    let val = expr.eval(env)?;
+    match env.truth_of(&val) {
+        Some(true) => ok(nil()),
+        _ => err(format!("Assertion failed: {}.", expr)),
+    }
+}
+
+/// `(assert-eq expected actual)`
+///
+/// Produces an error containing both expressions and their values if the
+/// two values are not equal to one another. Otherwise, nil is produced.
+pub fn _assert_eq(env: Env, exprs: Exprs) -> Output {
+    let len = exprs.len() - 1;
+    if len != 2 {
+        return Err(arity_exact(2, len));
+    }
+
+    let (left, right) = (&exprs[1], &exprs[2]);
+    let (left_val, right_val) = (left.eval(env)?, right.eval(env)?);
+    if left_val == right_val {
+        ok(nil())
+    } else {
+        err(format!(
+            "Assertion failed: {} is not equal to {}.\n- {}\n+ {}",
+            left, right, left_val, right_val
+        ))
+    }
+}
+
 /// `(define-struct (struct-name field1 ...)`
 pub fn _define_struct(env: Env, exprs: Exprs) -> Output {
     let len = exprs.len() - 1;
@@ -358,3 +1190,614 @@ pub fn _define_struct(env: Env, exprs: Exprs) -> Output {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use intrinsics::test::*;
+    use parser::Parser;
+
+    #[test]
+    fn if_with_else() {
+        assert!(eval("(if #t 1 2)").unwrap() == Value::Num(1.0));
+        assert!(eval("(if #f 1 2)").unwrap() == Value::Num(2.0));
+    }
+
+    #[test]
+    fn if_without_else() {
+        assert!(eval("(if #t 1)").unwrap() == Value::Num(1.0));
+        assert!(eval("(if #f 1)").unwrap() == nil());
+    }
+
+    #[test]
+    fn if_arity() {
+        assert!(eval_err("(if #t)").contains("Expected at least 2 arg(s), found 1."));
+        assert!(eval_err("(if #t 1 2 3)").contains("Expected at most 3 arg(s), found 4."));
+    }
+
+    #[test]
+    fn cond_else() {
+        assert!(eval("(cond [#f 1] [else 2])").unwrap() == Value::Num(2.0));
+        assert!(eval("(cond [#t 1] [else 2])").unwrap() == Value::Num(1.0));
+        assert!(eval("(cond [#f 1])").unwrap() == nil());
+    }
+
+    #[test]
+    fn cond_user_else_variable() {
+        let res = eval("(let ([else 5]) (cond [#f 1] [else else]))").unwrap();
+        assert!(res == Value::Num(5.0));
+
+        let res = eval("(let ([else 5]) (cond [(eq? else 5) else]))").unwrap();
+        assert!(res == Value::Num(5.0));
+    }
+
+    #[test]
+    fn do_summation() {
+        let res = eval("(do ([i 0 (+ i 1)] [sum 0 (+ sum i)]) ((eq? i 5) sum))").unwrap();
+        assert!(res == Value::Num(10.0));
+    }
+
+    #[test]
+    fn do_vector_fill() {
+        let res = eval(
+            "(do ([v (vector 0 0 0) (vector-set v i (* i i))]
+                  [i 0 (+ i 1)])
+                 ((eq? i 3) v))",
+        ).unwrap();
+        let expected = Value::Vector(vec![Value::Num(0.0), Value::Num(1.0), Value::Num(4.0)]);
+        assert!(res == expected);
+    }
+
+    #[test]
+    fn do_body_and_fixed_variable() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define total 0)").unwrap();
+        let res = eval_in(
+            &mut env,
+            "(do ([i 0 (+ i 1)] [n 2]) ((eq? i 3) (define total (* n 10)) total) (+ i 1))",
+        ).unwrap();
+        assert!(res == Value::Num(20.0));
+    }
+
+    #[test]
+    fn assert_passes() {
+        assert!(eval("(assert (< 1 2))").unwrap() == nil());
+        assert!(eval("(assert-eq (+ 1 2) 3)").unwrap() == nil());
+    }
+
+    #[test]
+    fn assert_fails() {
+        let why = eval_err("(assert (> 1 2))");
+        assert!(why.contains("Assertion failed: (> 1 2)."));
+
+        let why = eval_err("(assert 5)");
+        assert!(why.contains("Assertion failed: 5."));
+    }
+
+    #[test]
+    fn assert_eq_fails() {
+        let why = eval_err("(assert-eq (+ 1 2) 4)");
+        assert!(why.contains("Assertion failed: (+ 1 2) is not equal to 4."));
+        assert!(why.contains(&format!("- {}", Value::Num(3.0))));
+        assert!(why.contains(&format!("+ {}", Value::Num(4.0))));
+    }
+
+    #[test]
+    fn typecase_dispatch() {
+        let expr = |x| {
+            format!(
+                "(typecase {} [num 'num] [str 'str] [list 'list] [else 'other])",
+                x
+            )
+        };
+        let sym = |s: &str| Value::Symbol(s.to_string(), false);
+        assert!(eval(&expr("1")).unwrap() == sym("num"));
+        assert!(eval(&expr("\"a\"")).unwrap() == sym("str"));
+        assert!(eval(&expr("'(1 2)")).unwrap() == sym("list"));
+        assert!(eval(&expr("#t")).unwrap() == sym("other"));
+        assert!(eval("(typecase #t [num 1])").unwrap() == nil());
+
+        // The types produced by type-of are accepted
+        let mut env = new_env();
+        eval_in(&mut env, "(define (f) 1)").unwrap();
+        let types = [
+            ("car", "function"),
+            ("(memoize f)", "function"),
+            ("f", "lambda"),
+            ("if", "macro"),
+            ("(values 1 2)", "values"),
+            ("(iter '())", "iterator"),
+            ("iter-end", "iter-end"),
+            ("(mcons 1 2)", "mpair"),
+        ];
+        for (val, ty) in &types {
+            let expr = format!("(typecase {} [{} #t] [else #f])", val, ty);
+            assert!(eval_in(&mut env, &expr).unwrap() == Value::Bool(true), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn typecase_evaluates_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn tick(_: Env, _: &[Value]) -> Result<Value> {
+            ok(CALLS.fetch_add(1, Ordering::SeqCst) as f64)
+        }
+
+        let mut env = new_env();
+        env.define_intrinsic("tick", tick, "tick : -> num");
+        let res = eval_in(&mut env, "(typecase (tick) [str 1] [bool 2] [num 3])").unwrap();
+        assert!(res == Value::Num(3.0));
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn typecase_struct() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define-struct point [x y])").unwrap();
+        let res = eval_in(&mut env, "(typecase (make-point 1 2) [num 1] [point 2])").unwrap();
+        assert!(res == Value::Num(2.0));
+        let res = eval_in(&mut env, "(typecase (make-point 1 2) [function 1] [struct 2])").unwrap();
+        assert!(res == Value::Num(2.0));
+        assert!(eval_err("(typecase 1 [widget 1])").contains("Unknown type widget."));
+    }
+
+    #[test]
+    fn struct_in_function() {
+        let mut env = new_env();
+        let f = "(define (f x) (begin (define-struct pt (a b)) (pt-a (make-pt x 2))))";
+        eval_in(&mut env, f).unwrap();
+        assert!(eval_in(&mut env, "(f 1)").unwrap() == Value::Num(1.0));
+    }
+
+    #[test]
+    fn struct_suggestions() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define-struct point [x y])").unwrap();
+        let msg = eval_in(&mut env, "(make-pont 1 2)").unwrap_err().to_string();
+        assert!(msg.contains("Did you mean make-point?"));
+        let msg = eval_in(&mut env, "(poimt-x (make-point 1 2))").unwrap_err().to_string();
+        assert!(msg.contains("Did you mean point-x?"));
+        let msg = eval_in(&mut env, "(pont? 1)").unwrap_err().to_string();
+        assert!(msg.contains("Did you mean point?"));
+        let msg = eval_in(&mut env, "(make-rectangle 1 2)").unwrap_err().to_string();
+        assert!(!msg.contains("Did you mean"));
+    }
+
+    #[test]
+    fn macro_as_argument() {
+        let mut env = new_env();
+        let why: String = eval_in(&mut env, "((begin if) #t 1 2)").err().unwrap().into();
+        assert!(why.contains("Macros cannot be used as first-class functions."));
+        let why: String = eval_in(&mut env, "(apply if '(#t 1 2))").err().unwrap().into();
+        assert!(why.contains("Macros cannot be used as first-class functions."));
+
+        // Macros may still be passed around as data
+        eval_in(&mut env, "(define (second a b) b)").unwrap();
+        assert!(eval_in(&mut env, "(second if 1)").unwrap() == Value::Num(1.0));
+        let res = eval_in(&mut env, "((lambda (f) (type-of f)) if)").unwrap();
+        assert_eq!(format!("{:?}", res), "'macro");
+    }
+
+    #[test]
+    fn define_recursive() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define (fact n) (if (eq? n 0) 1 (* n (fact (- n 1)))))").unwrap();
+        assert!(eval_in(&mut env, "(fact 5)").unwrap() == Value::Num(120.0));
+
+        // An internal recursive function remains callable after it escapes
+        eval_in(&mut env, "(define (counter)
+            (define (down n) (if (eq? n 0) 'done (down (- n 1))))
+            down)").unwrap();
+        let res = eval_in(&mut env, "((counter) 5)").unwrap();
+        assert!(res == Value::Symbol("done".to_string(), false));
+        assert!(eval_in(&mut env, "(eq? (counter) (counter))").unwrap() == Value::Bool(false));
+        let res = eval_in(&mut env, "(let ([f (counter)]) (eq? f f))").unwrap();
+        assert!(res == Value::Bool(true));
+    }
+
+    #[test]
+    fn for_list_single() {
+        let res = eval("(for/list ([x '(1 2 3)]) (* x x))").unwrap();
+        let expected = vec![Value::Num(1.0), Value::Num(4.0), Value::Num(9.0)];
+        assert!(res == Value::List(expected.into()));
+        assert!(eval("(for/list ([x '()]) x)").unwrap() == nil());
+    }
+
+    #[test]
+    fn for_list_parallel() {
+        let res = eval("(for/list ([x '(1 2 3)] [y #(10 20)]) (+ x y))").unwrap();
+        assert!(res == Value::List(vec![Value::Num(11.0), Value::Num(22.0)].into()));
+    }
+
+    #[test]
+    fn for_list_when() {
+        let res = eval("(for/list ([x '(1 2 3 4)] #:when (even? x)) (* 10 x))").unwrap();
+        assert!(res == Value::List(vec![Value::Num(20.0), Value::Num(40.0)].into()));
+        assert!(eval_err("(for/list ([x '(1)] #:when) x)").contains("after #:when"));
+    }
+
+    #[test]
+    fn lenient_conditions() {
+        let mut env = new_env();
+        assert!(eval_in(&mut env, "(cond [1 'a])").is_err());
+        assert!(eval_in(&mut env, "(do ([i 0 (+ i 1)]) (i i))").is_err());
+        assert!(eval_in(&mut env, "(for/list ([x '(1)] #:when x) x)").is_err());
+        assert!(eval_in(&mut env, "(assert 1)").is_err());
+
+        env.set_truthiness(Truthiness::Lenient);
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(cond [#f 'a] ['() 'b] ['(1) 'c])"), "'c");
+        assert_eq!(call("(do ([i 3 (- i 1)] [l #f (cons i '())]) (l l))"), "(3)");
+        assert_eq!(call("(for/list ([x '(1 #f 2)] #:when x) x)"), "(1 2)");
+        assert_eq!(call("(assert \"\")"), "()");
+        assert!(eval_in(&mut env, "(assert #f)").is_err());
+    }
+
+    #[test]
+    fn begin_sequence() {
+        assert!(eval("(begin)").unwrap() == nil());
+        assert!(eval("(begin (define a 1) (+ a 1))").unwrap() == Value::Num(2.0));
+    }
+
+    #[test]
+    fn nested_quotes() {
+        let call = |s: &str| format!("{:?}", eval(s).unwrap());
+        assert_eq!(call("''x"), "''x");
+        assert_eq!(call("(quote (quote x))"), "''x");
+        assert_eq!(call("(car ''x)"), "'quote");
+        assert_eq!(call("(len ''x)"), "2");
+        assert_eq!(call("'(a 'b ''c)"), "('a ''b '''c)");
+        assert_eq!(call("(eval ''x)"), "'x");
+        assert_eq!(call("(eval '''(1 2))"), "'(1 2)");
+
+        // Doubly-quoted data survives conversion to an expression and back
+        let parsed = Parser::parse_from_str("''(a 'b)").unwrap().remove(0);
+        let val = Value::from(parsed.clone());
+        let expr: SExpr = val.clone().into();
+        assert_eq!(expr, parsed);
+        assert!(Value::from(expr) == val);
+    }
+
+    #[test]
+    fn begin0_sequence() {
+        let mut env = new_env();
+        let res = eval_in(&mut env, "(begin (define x 1) (begin0 x (set! x 2) (set! x 3)))");
+        assert!(res.unwrap() == Value::Num(1.0));
+        assert!(eval_in(&mut env, "x").unwrap() == Value::Num(3.0));
+
+        let res = eval("(with-output-to-string (print (begin0 1 (print \"a\") (print \"b\"))))");
+        assert!(res.unwrap() == Value::Str("ab1".to_string()));
+        assert!(eval_err("(begin0)").contains("Expected at least 1 arg(s), found 0."));
+    }
+
+    #[test]
+    fn if_tail_loop() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define (count n acc) (if (eq? n 0) acc (count (- n 1) (+ acc 1))))")
+            .unwrap();
+        let res = eval_in(&mut env, "(count 1000000 0)").unwrap();
+        assert!(res == Value::Num(1_000_000.0));
+        assert_eq!(env.depth(), 1);
+    }
+
+    #[test]
+    fn cond_let_begin_tail_loop() {
+        let mut env = new_env();
+        let loop_fn = "(define (count n)
+                         (cond [(eq? n 0) 'done]
+                               [else (let ([m (- n 1)]) (begin m (count m)))]))";
+        eval_in(&mut env, loop_fn).unwrap();
+        let res = eval_in(&mut env, "(count 100000)").unwrap();
+        assert!(res == Value::Symbol("done".to_string(), false));
+    }
+
+    #[test]
+    fn mutual_tail_loop() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define (ev? n) (if (eq? n 0) #t (od? (- n 1))))").unwrap();
+        eval_in(&mut env, "(define (od? n) (if (eq? n 0) #f (ev? (- n 1))))").unwrap();
+        assert!(eval_in(&mut env, "(ev? 100000)").unwrap() == Value::Bool(true));
+        assert!(eval_in(&mut env, "(od? 100001)").unwrap() == Value::Bool(true));
+        assert_eq!(env.depth(), 1);
+    }
+
+    #[test]
+    fn scopes_exited_on_error() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define (f n) (let ([m n]) (car m)))").unwrap();
+        assert!(eval_in(&mut env, "(f 1)").is_err());
+        assert_eq!(env.depth(), 1);
+    }
+
+    #[test]
+    fn let_values_divmod() {
+        let res = eval("(let-values ([(q r) (divmod 7 2)]) (vector q r))").unwrap();
+        assert_eq!(format!("{:?}", res), "#(3 1)");
+        let res = eval("(let-values ([(q r) (divmod -7 2)] [(x) (values 5)]) (vector q r x))");
+        assert_eq!(format!("{:?}", res.unwrap()), "#(-4 1 5)");
+        let why = eval_err("(let-values ([(q) (divmod 7 2)]) q)");
+        assert!(why.contains("Expected 1 value(s), found 2."));
+        assert!(eval_err("(+ 1 (divmod 7 2))").contains("Expected 1 value(s), found 2."));
+        assert!(eval_err("(divmod 1 0)").contains("Cannot divide by zero."));
+    }
+
+    #[test]
+    fn destructuring_let() {
+        assert!(eval("(let ([(a b) '(1 2)]) (+ a b))").unwrap() == Value::Num(3.0));
+        assert!(eval("(let ([(a (b c)) '(1 (2 3))]) (+ a (* b c)))").unwrap() == Value::Num(7.0));
+        let rest = Value::list(vec![Value::Num(2.0), Value::Num(3.0)]);
+        assert!(eval("(let ([(a b...) '(1 2 3)]) b)").unwrap() == rest);
+        let why = eval_err("(let ([(a b) '(1 2 3)]) a)");
+        assert!(why.contains("to the pattern (a b)."));
+        assert!(eval_err("(let ([(a (b c)) '(1 2)]) a)").contains("Cannot bind"));
+    }
+
+    #[test]
+    fn destructuring_lambda() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define (dot (x1 y1) (x2 y2)) (+ (* x1 x2) (* y1 y2)))").unwrap();
+        assert!(eval_in(&mut env, "(dot '(1 2) '(3 4))").unwrap() == Value::Num(11.0));
+        let res = eval_in(&mut env, "((lambda (n (x (y))) (+ n x y)) 1 '(2 (3)))").unwrap();
+        assert!(res == Value::Num(6.0));
+        assert!(eval_in(&mut env, "(dot '(1 2) 3)").is_err());
+
+        // The patterns are shown in place of the hidden parameters
+        let why = eval_in(&mut env, "(dot '(1 2))").err().unwrap().description;
+        assert!(why.contains("dot expects ((x1 y1) (x2 y2)), got 1 argument."));
+        let res = eval_in(&mut env, "(lambda (n (x y...)) (+ n x))").unwrap();
+        assert_eq!(format!("{:?}", res), "(lambda (n (x y...)) (+ n x))");
+    }
+
+    #[test]
+    fn cond_arrow() {
+        let mut env = new_env();
+        let res = eval_in(&mut env, "(cond [(eq? 1 1) => not] [else 0])").unwrap();
+        assert!(res == Value::Bool(false));
+        let res = eval_in(&mut env, "(cond [#f => not] [else 0])").unwrap();
+        assert!(res == Value::Num(0.0));
+        let why = eval_in(&mut env, "(cond [5 => (lambda (n) n)])").err().unwrap().description;
+        assert!(why.contains("is not a bool."));
+
+        env.set_truthiness(Truthiness::Lenient);
+        eval_in(&mut env, "(define alist '((a 1) (b 2)))").unwrap();
+        eval_in(&mut env, "(define (assoc k l)
+            (cond [(eq? 0 (len l)) #f]
+                  [(eq? k (car (car l))) (car l)]
+                  [else (assoc k (cdr l))]))").unwrap();
+        let res = eval_in(&mut env, "(cond [(assoc 'b alist) => cdr] [else 0])").unwrap();
+        assert!(res == Value::list(vec![Value::Num(2.0)]));
+        let res = eval_in(&mut env, "(cond [(assoc 'c alist) => cdr] [else 0])").unwrap();
+        assert!(res == Value::Num(0.0));
+        let res = eval_in(&mut env, "(cond [5 => (lambda (n) (* n n))])").unwrap();
+        assert!(res == Value::Num(25.0));
+        assert!(eval_in(&mut env, "(cond [1 => 2])").is_err());
+        let why = eval_in(&mut env, "(cond [1 => if])").err().unwrap().description;
+        assert!(why.contains("is a macro"));
+    }
+
+    #[test]
+    fn variadic_placement() {
+        // Valid at the end of a parameter list or list pattern
+        assert!(eval("((lambda (a b...) b) 1 2 3)").unwrap() == Value::list(vec![
+            Value::Num(2.0),
+            Value::Num(3.0),
+        ]));
+        assert!(eval("(let ([(a b...) '(1)]) b)").unwrap() == nil());
+        assert!(eval("(begin (define (f xs...) xs) (f))").unwrap() == nil());
+
+        // Quoted, it remains a variadic symbol
+        let res = eval("'a...").unwrap();
+        assert!(res == Value::Symbol("a".to_string(), true));
+
+        // Anywhere else, it is an error
+        let why = eval_err("(begin (define a 1) a...)");
+        assert!(why.contains("a... is variadic."));
+        assert!(eval_err("(define a... 1)").contains("a... is variadic."));
+        assert!(eval_err("(let ([a... 1]) a)").contains("a... is variadic."));
+        assert!(eval_err("(let ([(a... b) '(1 2)]) a)").contains("a... is variadic."));
+        assert!(eval_err("(lambda (a... b) a)").contains("Only the final parameter"));
+        assert!(eval_err("(lambda (a... (b c)) a)").contains("Only the final parameter"));
+
+        // Keyword parameters may follow it, as they are displayed
+        let lambda = "(lambda (a b... #:key c 1) (vector a b c))";
+        assert_eq!(format!("{:?}", eval(lambda).unwrap()), lambda);
+        let res = eval(&format!("({} 1 2 #:c 3 4)", lambda)).unwrap();
+        assert_eq!(format!("{:?}", res), "#(1 (2 4) 3)");
+    }
+
+    #[test]
+    fn shadow_intrinsic_warns() {
+        let mut env = new_env();
+        env.set_shadow_policy(ShadowPolicy::Warn);
+        eval_in(&mut env, "(define (car l) 0)").unwrap();
+        assert!(eval_in(&mut env, "(car '(1 2))").unwrap() == Value::Num(0.0));
+        assert_eq!(env.take_warnings(), vec!["car shadows an intrinsic function."]);
+
+        // Redefining a user function is not a warning
+        eval_in(&mut env, "(define (f) 1) (define (f) 2)").unwrap();
+        assert!(env.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn shadow_intrinsic_protected() {
+        let mut env = new_env();
+        env.set_shadow_policy(ShadowPolicy::Protect);
+        let why: String = eval_in(&mut env, "(define car 5)").err().unwrap().into();
+        assert!(why.contains("car is an intrinsic function, and may not be redefined."));
+        assert!(eval_in(&mut env, "(car '(1 2))").unwrap() == Value::Num(1.0));
+
+        env.set_shadow_policy(ShadowPolicy::Allow);
+        eval_in(&mut env, "(define car 5)").unwrap();
+        assert!(eval_in(&mut env, "car").unwrap() == Value::Num(5.0));
+        assert!(env.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn keyword_arguments() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define (f a #:key b 10 #:key c (+ a 1)) (vector a b c))").unwrap();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(f 1)"), "#(1 10 2)");
+        assert_eq!(call("(f 1 #:b 2)"), "#(1 2 2)");
+        assert_eq!(call("(f #:c 3 5 #:b (* 2 2))"), "#(5 4 3)");
+        let lambda = "(lambda (a #:key b 10 #:key c (+ a 1)) (vector a b c))";
+        assert_eq!(call(lambda), lambda);
+
+        let mut call_err = |s: &str| -> String { eval_in(&mut env, s).err().unwrap().into() };
+        assert!(call_err("(f 1 #:d 2)").contains("Unknown keyword argument #:d."));
+        assert!(call_err("(f 1 #:b 2 #:b 3)").contains("#:b was passed twice."));
+        assert!(call_err("(f 1 #:b)").contains("Expected a value after #:b."));
+        assert!(call_err("(f #:b 2)").contains("got 0 arguments."));
+        assert!(call_err("(lambda (#:key b) b)").contains("Expected a default value for #:b."));
+        assert!(call_err("(lambda (#:foo b 1) b)").contains("Unknown keyword #:foo"));
+        let duplicate = "The parameter a is declared more than once.";
+        assert!(call_err("(lambda (a #:key a 1) a)").contains(duplicate));
+        assert!(call_err("(lambda (a a) a)").contains(duplicate));
+        assert!(call_err("(lambda (b #:key a 1 #:key a 2) b)").contains(duplicate));
+    }
+
+    #[test]
+    fn capture_output() {
+        let mut env = new_env();
+        let res = eval_in(&mut env, "(with-output-to-string (print 1 \"a\") (println \"b\") 5)");
+        assert!(res.unwrap() == Value::Str("1ab\n".to_string()));
+
+        // Nested captures are independent, and the output is restored
+        let res = eval_in(
+            &mut env,
+            "(with-output-to-string (print \"x\") (with-output-to-string (print \"y\")))",
+        );
+        assert!(res.unwrap() == Value::Str("x".to_string()));
+        assert!(eval_in(&mut env, "(with-output-to-string)").unwrap() == Value::Str("".into()));
+
+        // The output is restored even if evaluation fails
+        let out = Rc::new(RefCell::new(Vec::<u8>::new()));
+        env.set_output(out.clone());
+        assert!(eval_in(&mut env, "(with-output-to-string (print 1) (car 1))").is_err());
+        eval_in(&mut env, "(print 2)").unwrap();
+        assert_eq!(out.borrow().as_slice(), b"2");
+    }
+
+    #[test]
+    fn threading_macros() {
+        let parse = |s: &str| ::parser::Parser::parse_from_str(s).unwrap().remove(0);
+        let expand = |s: &str, last: bool| match parse(s) {
+            List(exprs) => thread(&exprs, last).unwrap(),
+            _ => unreachable!(),
+        };
+        assert_eq!(expand("(-> x (f a) (g b) h)", false), parse("(h (g (f x a) b))"));
+        assert_eq!(expand("(->> x (f a) (g b) h)", true), parse("(h (g b (f a x)))"));
+
+        assert!(eval("(-> 10 (- 3) (vector 1))").unwrap() == eval("#(7 1)").unwrap());
+        assert!(eval("(->> 10 (- 3) (vector 1))").unwrap() == eval("#(1 -7)").unwrap());
+        assert!(eval("(-> 5)").unwrap() == Value::Num(5.0));
+        assert!(eval_err("(->)").contains("Expected at least 1 arg(s), found 0."));
+    }
+
+    #[test]
+    fn pattern_matching() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define-struct point [x y])").unwrap();
+        eval_in(
+            &mut env,
+            "(define (describe v)
+                (match v
+                    [0 \"zero\"]
+                    [\"hi\" \"greeting\"]
+                    ['sym \"symbol\"]
+                    [(point 0 y) (concat \"on the y axis at \" y)]
+                    [(point x _) (concat \"point at x \" x)]
+                    [() \"empty\"]
+                    [(a b) (concat \"pair \" a \" \" b)]
+                    [(a . rest) (concat \"head \" a \" of \" (len rest) \" more\")]
+                    [n (concat \"other \" n)]))",
+        )
+        .unwrap();
+        let mut call = |s: &str| match eval_in(&mut env, s).unwrap() {
+            Value::Str(s) => s,
+            val => panic!("Expected a str, found {:?}.", val),
+        };
+        assert_eq!(call("(describe 0)"), "zero");
+        assert_eq!(call("(describe \"hi\")"), "greeting");
+        assert_eq!(call("(describe 'sym)"), "symbol");
+        assert_eq!(call("(describe (make-point 0 5))"), "on the y axis at 5");
+        assert_eq!(call("(describe (make-point 2 5))"), "point at x 2");
+        assert_eq!(call("(describe (vector->list #()))"), "empty");
+        assert_eq!(call("(describe (vector->list #(1 2)))"), "pair 1 2");
+        assert_eq!(call("(describe (vector->list #(1 2 3)))"), "head 1 of 2 more");
+        assert_eq!(call("(describe 7)"), "other 7");
+
+        assert!(eval("(match '(1 2 3) [(a rest...) rest])").unwrap() == eval("'(2 3)").unwrap());
+        assert!(eval_err("(match 1 [2 2])").contains("No clause matched"));
+        assert!(eval_err("(match '(1 2) [(a... b) 2])").contains("a... is variadic"));
+    }
+
+    #[test]
+    fn optional_parameters() {
+        let mut env = new_env();
+        eval_in(&mut env, "(define (f a #:opt b 10 #:opt c (+ a 1)) (vector a b c))").unwrap();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(f 1)"), "#(1 10 2)");
+        assert_eq!(call("(f 1 2)"), "#(1 2 2)");
+        assert_eq!(call("(f 1 2 3)"), "#(1 2 3)");
+        let lambda = "(lambda (a #:opt b 10 #:opt c (+ a 1)) (vector a b c))";
+        assert_eq!(call(lambda), lambda);
+        assert_eq!(call("((lambda ((x y) #:opt z 3) (vector x y z)) '(1 2))"), "#(1 2 3)");
+
+        // Defaults which could otherwise be read as patterns
+        assert_eq!(call("((lambda (a #:opt b a) b) 1)"), "1");
+        assert_eq!(call("((lambda (a #:opt b (+ a a)) b) 1)"), "2");
+        assert_eq!(call("((lambda (a #:opt b (vector)) b) 1)"), "#()");
+        assert_eq!(call("((lambda (a [b c]) (vector a b c)) 1 '(2 3))"), "#(1 2 3)");
+
+        let mut call_err = |s: &str| -> String { eval_in(&mut env, s).err().unwrap().into() };
+        assert!(call_err("(f)").contains("got 0 arguments"));
+        assert!(call_err("(f 1 2 3 4)").contains("got 4 arguments"));
+        assert!(call_err("(lambda (#:opt a 1 b) b)").contains("may not follow optional"));
+        assert!(call_err("(lambda (a... #:opt b 1) b)").contains("may not follow a variadic"));
+        assert!(call_err("(lambda (#:opt b) b)").contains("Expected a default value for #:b."));
+    }
+
+    #[test]
+    fn closures_capture_let() {
+        let mut env = new_env();
+        let counter = "(let ([count 0]) (lambda () (set! count (+ count 1)) count))";
+        eval_in(&mut env, &format!("(define next {})", counter)).unwrap();
+        eval_in(&mut env, &format!("(define other {})", counter)).unwrap();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("(next)"), "1");
+        assert_eq!(call("(next)"), "2");
+        assert_eq!(call("(other)"), "1");
+        assert_eq!(call("(next)"), "3");
+        assert_eq!(call("((let ([x 1]) (lambda (x) x)) 5)"), "5");
+        assert_eq!(call("((lambda ((a b)) (set! a (+ a b)) a) '(1 2))"), "3");
+
+        let mut call_err = |s: &str| -> String { eval_in(&mut env, s).err().unwrap().into() };
+        assert!(call_err("(set! undefined 1)").contains("undefined"));
+        assert!(call_err("(set! if 1)").contains("reserved"));
+        assert!(call_err("(begin (let ([y 1]) (lambda () y)) y)").contains("unbound"));
+    }
+
+    #[test]
+    fn function_names() {
+        let mut env = new_env();
+        let factorial = "(define (factorial n) (if (= n 0) 1 (* n (factorial (- n 1)))))";
+        eval_in(&mut env, factorial).unwrap();
+        eval_in(&mut env, "(define square (lambda (x) (* x x)))").unwrap();
+        eval_in(&mut env, "(define fact factorial)").unwrap();
+        let mut call = |s: &str| format!("{:?}", eval_in(&mut env, s).unwrap());
+        assert_eq!(call("factorial"), "#<function factorial>");
+        assert_eq!(call("square"), "#<function square>");
+        assert_eq!(call("fact"), "#<function factorial>");
+        assert_eq!(call("(lambda (x) x)"), "(lambda (x) x)");
+        assert_eq!(call("(factorial 5)"), "120");
+
+        let mut call_err = |s: &str| -> String { eval_in(&mut env, s).err().unwrap().into() };
+        assert_eq!(call_err("(factorial 1 2)"), "factorial expects (n), got 2 arguments.");
+        assert_eq!(call_err("(square)"), "square expects (x), got 0 arguments.");
+        let anonymous = "The function expects (x y...), got 0 arguments.";
+        assert_eq!(call_err("((lambda (x y...) x))"), anonymous);
+        assert_eq!(call_err("(+ factorial 1)"), "#<function factorial> is not a number.");
+    }
+}
@@ -12,23 +12,47 @@ const NAME: &str = env!("CARGO_PKG_NAME");
 /// The version of the lisp interpreter.
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-/// All reserved words that may not be used as identifiers.
-const RESERVED_WORDS: [&str; 7] = [
-    "define",
-    "define-struct",
-    "begin",
-    "cond",
-    "else",
-    "if",
-    "let",
+/// All special forms, which are defined as macros. Their names are reserved
+/// words, and may not be used as identifiers.
+const SPECIAL_FORMS: &[(&str, Macro)] = &[
+    ("define", macros::_define),
+    ("set!", macros::_set),
+    ("lambda", macros::_lambda),
+    ("begin", macros::_begin),
+    ("begin0", macros::_begin0),
+    ("quote", macros::_quote),
+    ("if", macros::_if),
+    ("cond", macros::_cond),
+    ("typecase", macros::_typecase),
+    ("match", macros::_match),
+    ("let", macros::_let),
+    ("let-values", macros::_let_values),
+    ("do", macros::_do),
+    ("generator", macros::_generator),
+    ("for/list", macros::_for_list),
+    ("define-struct", macros::_define_struct),
+    ("assert", macros::_assert),
+    ("assert-eq", macros::_assert_eq),
+    ("with-output-to-string", macros::_with_output_to_string),
+    ("->", macros::_thread_first),
+    ("->>", macros::_thread_last),
 ];
 
+/// All keywords used within special forms, which are also reserved words.
+const KEYWORDS: &[&str] = &["else", "=>"];
+
+/// Determines whether or not the specified word is reserved, and so may not
+/// be used as an identifier.
+fn is_reserved_word(word: &str) -> bool {
+    KEYWORDS.contains(&word) || SPECIAL_FORMS.iter().any(|&(name, _)| name == word)
+}
+
 fn nil() -> Value {
-    Value::List(vec![])
+    Value::List(SharedList::default())
 }
 
 pub trait Intrinsics {
-    fn define_intrinsic<S>(&mut self, S, Intrinsic)
+    fn define_intrinsic<S>(&mut self, ident: S, f: Intrinsic, doc: &'static str)
     where
         S: Into<String>;
 
@@ -37,13 +61,20 @@ pub trait Intrinsics {
         S: Into<String>;
 
     fn init_intrinsics(&mut self);
+
+    fn init_intrinsics_sandboxed(&mut self);
 }
 
 impl Intrinsics for Environment {
-    fn define_intrinsic<S>(&mut self, ident: S, f: Intrinsic)
+    /// Defines the specified intrinsic function along with its documentation,
+    /// which consists of its signatures, one per line, followed by a
+    /// description of what it produces.
+    fn define_intrinsic<S>(&mut self, ident: S, f: Intrinsic, doc: &'static str)
     where
         S: Into<String>,
     {
+        let ident = ident.into();
+        self.add_doc(ident.clone(), doc);
         self.define(ident, Value::Intrinsic(f));
     }
 
@@ -54,7 +85,131 @@ impl Intrinsics for Environment {
         self.define(ident, Value::Macro(f));
     }
 
+    /// Defines all intrinsics, including those that access the filesystem,
+    /// standard input, or the process.
     fn init_intrinsics(&mut self) {
+        self.init_intrinsics_sandboxed();
+
+        self.define_intrinsic(
+            "exit",
+            functions::_exit,
+            "exit : num -> nil\n\
+             Exits the process with the specified exit code.",
+        );
+        self.define_intrinsic(
+            "read-line",
+            functions::_read_line,
+            "read-line : -> str\n\
+             Reads a line from the standard input, without its surrounding \
+             whitespace.",
+        );
+        self.define_intrinsic(
+            "read",
+            functions::_read,
+            "read : -> A\n\
+             Reads a single expression from the standard input, producing it as \
+             quoted data. At the end of input, nil is produced instead.",
+        );
+
+        self.define_intrinsic(
+            "import",
+            functions::_import,
+            "import : str -> A\n\
+             Opens and runs the specified file, producing the value of its last \
+             expression.",
+        );
+        self.define_intrinsic(
+            "read-file",
+            functions::_read_file,
+            "read-file : str -> str\n\
+             Produces the contents of the file with the specified path, wrapped in \
+             a `begin` expression so that they may be parsed and evaluated as a \
+             whole.",
+        );
+        self.define_intrinsic(
+            "write-file",
+            functions::_write_file,
+            "write-file : str str -> bool\n\
+             Attempts to write the specified string to the file with the specified \
+             path.",
+        );
+        self.define_intrinsic(
+            "read-lines",
+            functions::_read_lines,
+            "read-lines : str -> [str]\n\
+             Produces the contents of the file with the specified path as a list of \
+             its lines, without their line endings.",
+        );
+        self.define_intrinsic(
+            "write-lines",
+            functions::_write_lines,
+            "write-lines : str [str] -> nil\n\
+             Writes each of the strs in the specified list to the file with the \
+             specified path, each followed by a line ending.",
+        );
+        self.define_intrinsic(
+            "file-exists?",
+            functions::_file_exists,
+            "file-exists? : str -> bool\n\
+             Determines whether or not a file or directory exists at the specified \
+             path.",
+        );
+        self.define_intrinsic(
+            "delete-file",
+            functions::_delete_file,
+            "delete-file : str -> nil\n\
+             Deletes the file with the specified path. It is an error for the file \
+             not to exist.",
+        );
+        self.define_intrinsic(
+            "make-directory",
+            functions::_make_directory,
+            "make-directory : str -> nil\n\
+             Creates a directory at the specified path, along with any missing \
+             parent directories. It is not an error for the directory to already \
+             exist.",
+        );
+        self.define_intrinsic(
+            "current-directory",
+            functions::_current_directory,
+            "current-directory : -> str\n\
+             Produces the path of the current working directory, against which \
+             relative paths, including those passed to `import`, are resolved.",
+        );
+        self.define_intrinsic(
+            "change-directory",
+            functions::_change_directory,
+            "change-directory : str -> nil\n\
+             Changes the current working directory to the specified path. \
+             Subsequent relative paths, including those passed to `import`, are \
+             resolved against the new directory. It is an error for the directory \
+             not to exist.",
+        );
+        self.define_intrinsic(
+            "system",
+            functions::_system,
+            "system : str -> str\n\
+             Runs the specified command with the system shell, producing everything \
+             it writes to standard output. If the command exits unsuccessfully, its \
+             exit code and standard error are reported in the resulting error \
+             instead.",
+        );
+        self.define_intrinsic(
+            "eval-in",
+            functions::_eval_in,
+            "eval-in : A -> B\n\
+             Evaluates the specified quoted expression in a freshly initialized \
+             environment, such that its definitions do not affect the calling \
+             environment. Any fuel limit of the calling environment is shared with \
+             it, as are its output and error output. In a sandboxed environment, \
+             the fresh environment is sandboxed as well.",
+        );
+    }
+
+    /// Defines only the intrinsics that are safe to expose to untrusted code.
+    /// Those that access the filesystem, standard input, or the process are
+    /// left unbound.
+    fn init_intrinsics_sandboxed(&mut self) {
         use self::Value::*;
 
         // Constants
@@ -63,6 +218,7 @@ impl Intrinsics for Environment {
         let infinity = ::std::f64::INFINITY;
         self.define("math/infinity", Num(infinity));
         self.define("math/-infinity", Num(-infinity));
+        self.define("math/nan", Num(f64::NAN));
 
         let pi = ::std::f64::consts::PI;
         let e = ::std::f64::consts::E;
@@ -73,68 +229,958 @@ impl Intrinsics for Environment {
         self.define("env/lisp-name", Str(NAME.to_string()));
 
         // Macros
-        self.define_macro("define", macros::_define);
-        self.define_macro("lambda", macros::_lambda);
-        self.define_macro("if", macros::_if);
-        self.define_macro("cond", macros::_cond);
-        self.define_macro("let", macros::_let);
-        self.define_macro("define-struct", macros::_define_struct);
+        for &(name, f) in SPECIAL_FORMS {
+            self.define_macro(name, f);
+        }
 
         // Numeric operations
-        self.define_intrinsic("+", functions::_add);
-        self.define_intrinsic("-", functions::_sub);
-        self.define_intrinsic("*", functions::_mul);
-        self.define_intrinsic("/", functions::_div);
-        self.define_intrinsic("modulo", functions::_modulo);
-        self.define_intrinsic("sqrt", functions::_sqrt);
-        self.define_intrinsic("pow", functions::_pow);
-        self.define_intrinsic("log", functions::_log);
-        self.define_intrinsic("fibonacci", functions::_fib_rust);
+        self.define_intrinsic(
+            "+",
+            functions::_add,
+            "+ : num... -> num\n\
+             Produces the sum of 0 and the specified nums.",
+        );
+        self.define_intrinsic(
+            "-",
+            functions::_sub,
+            "- : num num... -> num\n\
+             Produces the difference between the first num and the sum of the \
+             subsequent nums. If only one num is provided, the num is negated.",
+        );
+        self.define_intrinsic(
+            "*",
+            functions::_mul,
+            "* : num... num\n\
+             Produces the product of 1 and the specified values.",
+        );
+        self.define_intrinsic(
+            "/",
+            functions::_div,
+            "/ : num num... -> num\n\
+             Produces the quotient between the first num and the product of the \
+             subsequent nums. If only one num is provided, the num is inverted.",
+        );
+        self.define_intrinsic(
+            "modulo",
+            functions::_modulo,
+            "modulo : num num -> num\n\
+             Produces the remainder of dividing the first num by the second, where \
+             the quotient is truncated toward zero. The result has the sign of the \
+             dividend, such that `(modulo -7 3)` is -1. See `mod` for floored \
+             semantics.",
+        );
+        self.define_intrinsic(
+            "mod",
+            functions::_mod,
+            "mod : num num -> num\n\
+             Produces the remainder of dividing the first num by the second, where \
+             the quotient is floored. The result has the sign of the divisor, such \
+             that `(mod -7 3)` is 2. See `modulo` for truncated semantics.",
+        );
+        self.define_intrinsic(
+            "divmod",
+            functions::_divmod,
+            "divmod : num num -> (values num num)\n\
+             Produces both the floored quotient of the two specified nums and the \
+             remainder, such that the remainder has the same sign as the divisor.",
+        );
+        self.define_intrinsic(
+            "sqrt",
+            functions::_sqrt,
+            "sqrt : num -> num\n\
+             Produces the square root of the specified num.",
+        );
+        self.define_intrinsic(
+            "isqrt",
+            functions::_isqrt,
+            "isqrt : num -> num\n\
+             Produces the integer square root of the specified non-negative \
+             integer, which is the largest integer whose square does not exceed it. \
+             Unlike `(floor (sqrt n))`, the result is exact for large integers.",
+        );
+        self.define_intrinsic(
+            "pow",
+            functions::_pow,
+            "pow : num num -> num\n\
+             Produces the num equal to the first num raised to the power of the \
+             second num.",
+        );
+        self.define_intrinsic(
+            "expt",
+            functions::_expt,
+            "expt : num num -> num\n\
+             Produces the num equal to the first num raised to the power of the \
+             second num. If the base is an integer and the exponent is a non- \
+             negative integer, the power is computed exactly, and it is an error \
+             for it to exceed the range of a 64-bit integer. Otherwise, it is \
+             computed as with `pow`.",
+        );
+        self.define_intrinsic(
+            "log",
+            functions::_log,
+            "log : num num -> num\n\
+             Produces the logarithm of the first specified num, using the second \
+             specified num as the base.",
+        );
+        self.define_intrinsic(
+            "fibonacci",
+            functions::_fib_rust,
+            "fibonacci : num -> num\n\
+             Produces the nth Fibonacci number.",
+        );
 
         // Type checking functions
         functions::load_checks(self);
 
         // List functions
         // self.define_intrinsic("list", functions::_list);
-        self.define_intrinsic("cons", functions::_cons);
-        self.define_intrinsic("car", functions::_car);
-        self.define_intrinsic("cdr", functions::_cdr);
-        self.define_intrinsic("len", functions::_len);
-        self.define_intrinsic("nth", functions::_nth);
-        self.define_intrinsic("append", functions::_append);
+        self.define_intrinsic(
+            "cons",
+            functions::_cons,
+            "cons : A [A] -> [A]\n\
+             Produces a list equal to the specified list prepended by the specified \
+             value.",
+        );
+        self.define_intrinsic(
+            "car",
+            functions::_car,
+            "car : [A] -> A\n\
+             Produces the first element of the specified list.",
+        );
+        self.define_intrinsic(
+            "cdr",
+            functions::_cdr,
+            "cdr : [A] -> A\n\
+             Produces the rest of the specified list after the first element. The \
+             rest of the list shares its elements with the specified list, rather \
+             than copying them.",
+        );
+        self.define_intrinsic(
+            "len",
+            functions::_len,
+            "len : [A] -> num\n\
+             Determines the length of the specified list.",
+        );
+        self.define_intrinsic(
+            "make-list",
+            functions::_make_list,
+            "make-list : num A -> [A]\n\
+             Produces a list containing the specified number of copies of the \
+             specified value. The count must be a non-negative integer, no \
+             greater than 2^24.",
+        );
+        self.define_intrinsic(
+            "repeat",
+            functions::_make_list,
+            "repeat : num A -> [A]\n\
+             Produces a list containing the specified number of copies of the \
+             specified value. The count must be a non-negative integer, no \
+             greater than 2^24.",
+        );
+        self.define_intrinsic(
+            "nth",
+            functions::_nth,
+            "nth : [A] num -> A\n\
+             Produces the nth value of the specified list.",
+        );
+        self.define_intrinsic(
+            "append",
+            functions::_append,
+            "append : A [A] -> [A]",
+        );
+        self.define_intrinsic(
+            "mcons",
+            functions::_mcons,
+            "mcons : A B -> mpair\n\
+             Produces a mutable cons cell with the specified car and cdr. Unlike a \
+             list, the cell is shared rather than copied, so that mutating it with \
+             `set-car!` or `set-cdr!` is observed through every reference to it. \
+             Cells which refer to one another in a cycle are never freed.",
+        );
+        self.define_intrinsic(
+            "mlist",
+            functions::_mlist,
+            "mlist : A... -> mpair\n\
+             Produces a mutable list of the specified values, made of mutable cons \
+             cells and ending in the empty list.",
+        );
+        self.define_intrinsic(
+            "mpair?",
+            functions::_is_mpair,
+            "mpair? : A -> bool\n\
+             Determines whether or not the specified value is a mutable cons cell.",
+        );
+        self.define_intrinsic(
+            "set-car!",
+            functions::_set_car,
+            "set-car! : mpair A -> ()\n\
+             Replaces the car of the specified mutable cons cell with the specified \
+             value.",
+        );
+        self.define_intrinsic(
+            "set-cdr!",
+            functions::_set_cdr,
+            "set-cdr! : mpair A -> ()\n\
+             Replaces the cdr of the specified mutable cons cell with the specified \
+             value.",
+        );
+        self.define_intrinsic(
+            "mlist->list",
+            functions::_mlist_to_list,
+            "mlist->list : mpair -> [A]\n\
+             Produces an immutable list containing the elements of the specified \
+             mutable list. It is an error for the mutable list to be cyclic, or not \
+             to end in the empty list.",
+        );
+
+        // Vector functions
+        self.define_intrinsic(
+            "vector",
+            functions::_vector,
+            "vector : A... -> #[A]\n\
+             Wraps all specified values in a vector.",
+        );
+        self.define_intrinsic(
+            "vector-ref",
+            functions::_vector_ref,
+            "vector-ref : #[A] num -> A\n\
+             Produces the value at the specified index of the specified vector.",
+        );
+        self.define_intrinsic(
+            "vector-set",
+            functions::_vector_set,
+            "vector-set : #[A] num A -> #[A]\n\
+             Produces a copy of the specified vector, with the value at the \
+             specified index replaced by the specified value. The original vector \
+             is unchanged.",
+        );
+        self.define_intrinsic(
+            "vector-length",
+            functions::_vector_length,
+            "vector-length : #[A] -> num\n\
+             Determines the length of the specified vector.",
+        );
+        self.define_intrinsic(
+            "list->vector",
+            functions::_list_to_vector,
+            "list->vector : [A] -> #[A]\n\
+             Produces a vector containing the elements of the specified list, in \
+             order.",
+        );
+        self.define_intrinsic(
+            "vector->list",
+            functions::_vector_to_list,
+            "vector->list : #[A] -> [A]\n\
+             Produces a list containing the elements of the specified vector, in \
+             order.",
+        );
+        self.define_intrinsic(
+            "string->list",
+            functions::_string_to_list,
+            "string->list : str -> [str]\n\
+             Produces a list containing each char of the specified str, in order, \
+             as a single char str.",
+        );
+        self.define_intrinsic(
+            "list->string",
+            functions::_list_to_string,
+            "list->string : [str] -> str\n\
+             Produces the str consisting of each of the single char strs in the \
+             specified list, in order.",
+        );
+        self.define_intrinsic(
+            "zip",
+            functions::_zip,
+            "zip : [A] [B] ... -> [(A B ...)]\n\
+             Produces a list of tuples, represented as lists, where the nth tuple \
+             contains the nth element of each of the specified lists. The result is \
+             as long as the shortest list.",
+        );
+        self.define_intrinsic(
+            "unzip",
+            functions::_unzip,
+            "unzip : [(A B ...)] -> ([A] [B] ...)\n\
+             Produces a list of lists, where the nth list contains the nth element \
+             of each of the tuples in the specified list. All of the tuples must \
+             have the same length, such that `(apply zip (unzip tuples))` produces \
+             the tuples.",
+        );
+
+        // Iterator functions
+        self.define("iter-end", iter_end());
+        self.define_intrinsic(
+            "iter",
+            functions::_iter,
+            "iter : [A] -> iterator\n\
+             Produces an iterator over the elements of the specified list or \
+             vector. An iterator is produced unchanged.",
+        );
+        self.define_intrinsic(
+            "iter-range",
+            functions::_iter_range,
+            "iter-range : num -> iterator\n\
+             iter-range : num num -> iterator\n\
+             iter-range : num num num -> iterator\n\
+             Produces an iterator counting from the start, or zero, toward the end \
+             by the step, or one, excluding the end. Unlike a list, the range is \
+             never stored, so it may be arbitrarily long.",
+        );
+        self.define_intrinsic(
+            "iter-generate",
+            functions::_iter_generate,
+            "iter-generate : (-> A) -> iterator\n\
+             Produces an iterator which calls the specified function to produce \
+             each of its values, until the function produces `iter-end`.",
+        );
+        self.define_intrinsic(
+            "iter-next",
+            functions::_iter_next,
+            "iter-next : iterator -> A\n\
+             Produces the next value of the specified iterator and advances past \
+             it, or `iter-end` if it has produced all of its values.",
+        );
+        self.define_intrinsic(
+            "iter-has-next?",
+            functions::_iter_has_next,
+            "iter-has-next? : iterator -> bool\n\
+             Determines whether or not the specified iterator has another value, \
+             without advancing past it.",
+        );
+        self.define_intrinsic(
+            "iter-end?",
+            functions::_is_iter_end,
+            "iter-end? : A -> bool\n\
+             Determines whether or not the specified value is `iter-end`, which \
+             marks the end of an iterator.",
+        );
+        self.define_intrinsic(
+            "yield",
+            functions::_yield,
+            "yield : A -> ()\n\
+             Passes the specified value to the innermost generator being evaluated. \
+             If the generator has already yielded as many values as its limit, it \
+             is stopped instead.",
+        );
+        self.define_intrinsic(
+            "iter-map",
+            functions::_iter_map,
+            "iter-map : (A -> B) iterator -> iterator\n\
+             Produces an iterator which lazily applies the specified function to \
+             each value of the specified iterator, list, or vector.",
+        );
+        self.define_intrinsic(
+            "iter-filter",
+            functions::_iter_filter,
+            "iter-filter : (A -> bool) iterator -> iterator\n\
+             Produces an iterator which lazily skips each value of the specified \
+             iterator, list, or vector that does not satisfy the specified \
+             predicate.",
+        );
+        self.define_intrinsic(
+            "iter-for-each",
+            functions::_iter_for_each,
+            "iter-for-each : (A -> B) iterator -> ()\n\
+             Applies the specified function to each remaining value of the \
+             specified iterator, list, or vector in order, discarding its outputs.",
+        );
+        self.define_intrinsic(
+            "iter-take",
+            functions::_iter_take,
+            "iter-take : num iterator -> [A]\n\
+             Produces a list of up to the specified number of values from the \
+             specified iterator, advancing past them.",
+        );
+        self.define_intrinsic(
+            "iter->list",
+            functions::_iter_to_list,
+            "iter->list : iterator -> [A]\n\
+             Produces a list of all remaining values of the specified iterator. It \
+             is an error for the iterator to be infinite.",
+        );
 
         // Comparison operations
-        self.define_intrinsic("<", functions::_is_l);
-        self.define_intrinsic("<=", functions::_is_le);
-        self.define_intrinsic(">", functions::_is_g);
-        self.define_intrinsic(">=", functions::_is_ge);
-        self.define_intrinsic("eq?", functions::_is_eq);
+        self.define_intrinsic(
+            "<",
+            functions::_is_l,
+            "< : num num -> bool\n\
+             Determines whether or not the first argument is less than the second \
+             argument.",
+        );
+        self.define_intrinsic(
+            "<=",
+            functions::_is_le,
+            "<= : num num -> bool\n\
+             Determines whether or not the first argument is less than or equal to \
+             the second argument.",
+        );
+        self.define_intrinsic(
+            ">",
+            functions::_is_g,
+            "> : num num -> bool\n\
+             Determines whether or not the first argument is greater than the \
+             second argument.",
+        );
+        self.define_intrinsic(
+            ">=",
+            functions::_is_ge,
+            ">= : num num -> bool\n\
+             Determines whether or not the first argument is greater than or equal \
+             to the second argument.",
+        );
+        self.define_intrinsic(
+            "=",
+            functions::_num_eq,
+            "= : num num -> bool\n\
+             Determines whether or not the two specified nums are numerically \
+             equal. Unlike `eq?`, this follows IEEE 754, so NaN is not equal to \
+             itself. Zero is equal to negative zero.",
+        );
+        self.define_intrinsic(
+            "eq?",
+            functions::_is_eq,
+            "eq? : A A -> bool\n\
+             Determines whether or not the two specified values are structurally \
+             equal to one another. NaN is equal to itself, and zero is equal to \
+             negative zero.",
+        );
+        self.define_intrinsic(
+            "neq?",
+            functions::_is_neq,
+            "neq? : A A -> bool\n\
+             Determines whether or not the two specified values are not equal to \
+             one another.",
+        );
+        self.define_intrinsic(
+            "set-equal?",
+            functions::_is_set_equal,
+            "set-equal? : [A] [A] -> bool\n\
+             Determines whether or not the two specified lists contain the same \
+             elements, as compared by `eq?`, regardless of their order. Lists are \
+             treated as sets, so the number of times each element occurs is \
+             ignored.",
+        );
+
+        // Numeric predicates
+        self.define_intrinsic(
+            "zero?",
+            functions::_is_zero,
+            "zero? : num -> bool\n\
+             Determines whether or not the specified num is zero.",
+        );
+        self.define_intrinsic(
+            "positive?",
+            functions::_is_positive,
+            "positive? : num -> bool\n\
+             Determines whether or not the specified num is greater than zero.",
+        );
+        self.define_intrinsic(
+            "negative?",
+            functions::_is_negative,
+            "negative? : num -> bool\n\
+             Determines whether or not the specified num is less than zero.",
+        );
+        self.define_intrinsic(
+            "even?",
+            functions::_is_even,
+            "even? : num -> bool\n\
+             Determines whether or not the specified integer is even.",
+        );
+        self.define_intrinsic(
+            "odd?",
+            functions::_is_odd,
+            "odd? : num -> bool\n\
+             Determines whether or not the specified integer is odd.",
+        );
+        self.define_intrinsic(
+            "nan?",
+            functions::_is_nan,
+            "nan? : num -> bool\n\
+             Determines whether or not the specified num is not a number, such as \
+             the square root of a negative num.",
+        );
+        self.define_intrinsic(
+            "infinite?",
+            functions::_is_infinite,
+            "infinite? : num -> bool\n\
+             Determines whether or not the specified num is positive or negative \
+             infinity.",
+        );
+        self.define_intrinsic(
+            "finite?",
+            functions::_is_finite,
+            "finite? : num -> bool\n\
+             Determines whether or not the specified num is neither infinite nor \
+             not a number.",
+        );
 
         // Logical operations
-        self.define_intrinsic("or", functions::_or);
-        self.define_intrinsic("and", functions::_and);
-        self.define_intrinsic("not", functions::_not);
+        self.define_intrinsic(
+            "or",
+            functions::_or,
+            "or : bool... -> bool\n\
+             Produces the logical `or` of all the specified boolean values.",
+        );
+        self.define_intrinsic(
+            "and",
+            functions::_and,
+            "and : bool... -> bool\n\
+             Produces the logical `and` of all the specified boolean values.",
+        );
+        self.define_intrinsic(
+            "not",
+            functions::_not,
+            "not : bool -> bool\n\
+             Inverts the specified boolean value. Under lenient truthiness, any \
+             value is accepted, and its truthiness is inverted instead.",
+        );
+        self.define_intrinsic(
+            "->bool",
+            functions::_to_bool,
+            "->bool : A -> bool\n\
+             Converts the specified value to a bool. `#f` and the empty list are \
+             false, and every other value is true, including `0`, `\"\"`, and \
+             `#()`.",
+        );
+        self.define_intrinsic(
+            "bool->",
+            functions::_from_bool,
+            "bool-> : bool -> bool\n\
+             Produces the specified bool unchanged, as the counterpart of `->bool`.",
+        );
 
         // Other
-        self.define_intrinsic("exit", functions::_exit);
-        self.define_intrinsic("begin", functions::_begin);
-        self.define_intrinsic("print", functions::_print);
-        self.define_intrinsic("println", functions::_println);
-        self.define_intrinsic("apply", functions::_apply);
-        self.define_intrinsic("concat", functions::_concat);
-        self.define_intrinsic("eval", functions::_eval);
+        self.define_intrinsic(
+            "print",
+            functions::_print,
+            "print : A... -> nil\n\
+             Prints the specified values to the environment's output, which is the \
+             standard output unless redirected.",
+        );
+        self.define_intrinsic(
+            "println",
+            functions::_println,
+            "println : A... -> nil\n\
+             Prints the specified values, followed by a newline, to the \
+             environment's output, which is the standard output unless redirected.",
+        );
+        self.define_intrinsic(
+            "set-trace!",
+            functions::_set_trace,
+            "set-trace! : bool -> nil\n\
+             Enables or disables tracing each function call and its return value to \
+             the environment's error output, which is the standard error unless \
+             redirected.",
+        );
+        self.define_intrinsic(
+            "set-float-format!",
+            functions::_set_float_format,
+            "set-float-format! : symbol -> nil\n\
+             set-float-format! : symbol num -> nil\n\
+             Sets the format in which nums are displayed to `shortest`, which uses \
+             the fewest digits that identify each num, `fixed`, which uses the \
+             specified number of decimal places, or six by default, or \
+             `scientific`, which uses an exponent with the specified number of \
+             decimal places, or the fewest digits that identify each num by \
+             default. At most 17 decimal places may be specified. The format is \
+             shared by every environment on the current thread.",
+        );
+        self.define_intrinsic(
+            "ensure",
+            functions::_ensure,
+            "ensure : bool str -> ()\n\
+             Produces an error with the specified message if the specified bool is \
+             false. Otherwise, nil is produced. Unlike `assert`, the message \
+             describes the failure to the caller rather than the failed expression.",
+        );
+        self.define_intrinsic(
+            "identity",
+            functions::_identity,
+            "identity : A -> A\n\
+             Produces the specified value unchanged.",
+        );
+        self.define_intrinsic(
+            "const",
+            functions::_const,
+            "const : A -> (B... -> A)\n\
+             Produces a function which ignores its arguments and always produces \
+             the specified value.",
+        );
+        self.define_intrinsic(
+            "always",
+            functions::_const,
+            "always : A -> (B... -> A)\n\
+             Produces a function which ignores its arguments and always produces \
+             the specified value.",
+        );
+        self.define_intrinsic(
+            "memoize",
+            functions::_memoize,
+            "memoize : (A... -> B) -> (A... -> B)\n\
+             Produces a version of the specified function which caches its outputs, \
+             so that calling it again with equal arguments produces the cached \
+             output instead. A function argument is only equal to the same \
+             function, so a call with an equivalent but separately created \
+             function is not cached.",
+        );
+        self.define_intrinsic(
+            "apply",
+            functions::_apply,
+            "apply : (A... -> B) [A] -> B\n\
+             Expands the specified list of values into a variadic input for the \
+             specified function, producing that function's output. Macros operate \
+             on unevaluated expressions, so they cannot be applied.",
+        );
+        self.define_intrinsic(
+            "reduce",
+            functions::_reduce,
+            "reduce : (A A -> A) [A] -> A\n\
+             Performs a left-associative fold on the specified list, using its \
+             first element as the initial accumulator. Unlike `foldl`, there is no \
+             seed, so it is an error to reduce an empty list.",
+        );
+        self.define_intrinsic(
+            "take-while",
+            functions::_take_while,
+            "take-while : (A -> bool) [A] -> [A]\n\
+             Produces the leading elements of the specified list for which the \
+             specified predicate holds.",
+        );
+        self.define_intrinsic(
+            "drop-while",
+            functions::_drop_while,
+            "drop-while : (A -> bool) [A] -> [A]\n\
+             Produces the specified list without the leading elements for which the \
+             specified predicate holds.",
+        );
+        self.define_intrinsic(
+            "partition",
+            functions::_partition,
+            "partition : (A -> bool) [A] -> ([A] [A])\n\
+             Produces a pair of lists, where the first contains the elements of the \
+             specified list for which the specified predicate holds, and the second \
+             contains the rest. The order of the elements is preserved.",
+        );
+        self.define_intrinsic(
+            "group-by",
+            functions::_group_by,
+            "group-by : (A -> B) [A] -> [(B [A])]\n\
+             Groups the elements of the specified list by the key produced for each \
+             of them by the specified function. The groups are produced as an \
+             association list of `(key elements)` pairs, ordered by the first \
+             occurrence of each key. The order of the elements within each group is \
+             preserved.",
+        );
+        self.define_intrinsic(
+            "count",
+            functions::_count,
+            "count : (A -> bool) [A] -> num\n\
+             Determines the number of elements of the specified list for which the \
+             specified predicate holds.",
+        );
+        self.define_intrinsic(
+            "any?",
+            functions::_any,
+            "any? : (A -> bool) [A] -> bool\n\
+             Determines whether or not the specified predicate holds for any \
+             element of the specified list. The remaining elements are not tested \
+             once one is found.",
+        );
+        self.define_intrinsic(
+            "all?",
+            functions::_all,
+            "all? : (A -> bool) [A] -> bool\n\
+             Determines whether or not the specified predicate holds for every \
+             element of the specified list, which is vacuously true for an empty \
+             list. The remaining elements are not tested once one fails.",
+        );
+        self.define_intrinsic(
+            "values",
+            functions::_values,
+            "values : A... -> (values A...)\n\
+             Produces all of the specified values at once, to be destructured by \
+             `let-values`. A single value is produced as is.",
+        );
+        self.define_intrinsic(
+            "concat",
+            functions::_concat,
+            "concat : A... -> str\n\
+             `A... -> str` Produces a string containing all arguments concatenated \
+             together.",
+        );
+        self.define_intrinsic(
+            "eval",
+            functions::_eval,
+            "eval : A -> B\n\
+             Evaluates the specified quoted expression in the calling environment.",
+        );
+        self.define_intrinsic(
+            "eval-in",
+            functions::_eval_in_sandboxed,
+            "eval-in : A -> B\n\
+             Evaluates the specified quoted expression in a freshly initialized \
+             environment, such that its definitions do not affect the calling \
+             environment. Any fuel limit of the calling environment is shared with \
+             it, as are its output and error output. In a sandboxed environment, \
+             the fresh environment is sandboxed as well.",
+        );
 
-        self.define_intrinsic("format", functions::_format);
-        self.define_intrinsic("read-line", functions::_read_line);
-        self.define_intrinsic("parse", functions::_parse);
+        self.define_intrinsic(
+            "format",
+            functions::_format,
+            "format : str -> str\n\
+             Produces the specified str with each of its interpolated expressions, \
+             written as `#{expr}` or `${expr}`, evaluated and replaced by its \
+             value.",
+        );
+        self.define_intrinsic(
+            "format-number",
+            functions::_format_number,
+            "format-number : num num -> str\n\
+             format-number : num num num -> str\n\
+             Produces a string containing the specified num, rounded to the \
+             specified number of decimal places. If a width is specified, the \
+             string is padded on the left with spaces to be at least that wide.",
+        );
+        self.define_intrinsic(
+            "parse",
+            functions::_parse,
+            "parse : str -> A\n\
+             Parses the single expression contained in the specified string, \
+             producing it as quoted data. Produces an error if the string contains \
+             anything other than whitespace after the expression.",
+        );
+        self.define_intrinsic(
+            "parse-all",
+            functions::_parse_all,
+            "parse-all : str -> [A]\n\
+             Parses all expressions contained in the specified string, producing a \
+             list of them as quoted data.",
+        );
 
-        self.define_intrinsic("import", functions::_import);
-        self.define_intrinsic("read-file", functions::_read_file);
-        self.define_intrinsic("write-file", functions::_write_file);
-
-        self.define_intrinsic("type-of", functions::_type_of);
-        self.define_intrinsic("substring", functions::_substring);
+        self.define_intrinsic(
+            "doc",
+            functions::_doc,
+            "doc : (str | symbol) -> str\n\
+             Produces the documentation of the function bound to the specified \
+             name. For an intrinsic, it consists of its signatures followed by a \
+             description. For a function defined in lisp, it is its parameter \
+             list.",
+        );
+        self.define_intrinsic(
+            "apropos",
+            functions::_apropos,
+            "apropos : (str | symbol) -> [symbol]\n\
+             Produces the names of all bindings visible from the current scope \
+             which contain the specified str, including intrinsics, special forms, \
+             and user definitions, in alphabetical order.",
+        );
+        self.define_intrinsic(
+            "type-of",
+            functions::_type_of,
+            "type-of : A -> symbol\n\
+             Produces a symbol representing the type of the specified value, one \
+             of `num`, `bool`, `str`, `symbol`, `list`, `vector`, `function`, \
+             `macro`, `struct`, `values`, `iterator`, `iter-end`, or `mpair`.",
+        );
+        self.define_intrinsic(
+            "hash",
+            functions::_hash,
+            "hash : A -> num\n\
+             Produces an integer hash of the specified value, which is equal for \
+             equal values and the same across runs. It is an error to hash a \
+             function, or a list, vector, or struct containing one.",
+        );
+        self.define_intrinsic(
+            "symbol",
+            functions::_string_to_symbol,
+            "symbol : str -> symbol\n\
+             Produces a symbol whose name is the specified string. Constructed \
+             symbols are never variadic, so `(string->symbol \"a...\")` is not \
+             equal to `'a...`.",
+        );
+        self.define_intrinsic(
+            "string->symbol",
+            functions::_string_to_symbol,
+            "string->symbol : str -> symbol\n\
+             Produces a symbol whose name is the specified string. Constructed \
+             symbols are never variadic, so `(string->symbol \"a...\")` is not \
+             equal to `'a...`.",
+        );
+        self.define_intrinsic(
+            "symbol->string",
+            functions::_symbol_to_string,
+            "symbol->string : symbol -> str\n\
+             Produces the name of the specified symbol, including the trailing \
+             `...` of a variadic symbol.",
+        );
+        self.define_intrinsic(
+            "symbol-append",
+            functions::_symbol_append,
+            "symbol-append : (str | symbol)... -> symbol\n\
+             Produces a symbol whose name is the concatenation of the specified \
+             strs and symbol names, such as the names of the constructor and \
+             accessors that `define-struct` generates. As with `string->symbol`, \
+             the symbol is never variadic.",
+        );
+        self.define_intrinsic(
+            "substring",
+            functions::_substring,
+            "substring : string num num -> string",
+        );
+        self.define_intrinsic(
+            "string-contains?",
+            functions::_string_contains,
+            "string-contains? : str str -> bool\n\
+             Determines whether or not the first str contains the second str.",
+        );
+        self.define_intrinsic(
+            "string-starts-with?",
+            functions::_string_starts_with,
+            "string-starts-with? : str str -> bool\n\
+             Determines whether or not the first str begins with the second str.",
+        );
+        self.define_intrinsic(
+            "string-ends-with?",
+            functions::_string_ends_with,
+            "string-ends-with? : str str -> bool\n\
+             Determines whether or not the first str ends with the second str.",
+        );
+        self.define_intrinsic(
+            "string-replace",
+            functions::_string_replace,
+            "string-replace : str str str -> str\n\
+             Produces a copy of the first str in which all non-overlapping \
+             occurrences of the second str are replaced with the third str.",
+        );
+        self.define_intrinsic(
+            "string-trim",
+            functions::_string_trim,
+            "string-trim : str -> str\n\
+             Produces the specified str without leading or trailing whitespace.",
+        );
+        self.define_intrinsic(
+            "string-trim-left",
+            functions::_string_trim_left,
+            "string-trim-left : str -> str\n\
+             Produces the specified str without leading whitespace.",
+        );
+        self.define_intrinsic(
+            "string-trim-right",
+            functions::_string_trim_right,
+            "string-trim-right : str -> str\n\
+             Produces the specified str without trailing whitespace.",
+        );
+        self.define_intrinsic(
+            "string-pad-left",
+            functions::_string_pad_left,
+            "string-pad-left : str num -> str\n\
+             string-pad-left : str num str -> str\n\
+             Produces the specified str, preceded by enough fill chars to reach the \
+             specified width. The fill char defaults to a space. Strs which are \
+             already at least as wide are produced as is.",
+        );
+        self.define_intrinsic(
+            "string-pad-right",
+            functions::_string_pad_right,
+            "string-pad-right : str num -> str\n\
+             string-pad-right : str num str -> str\n\
+             Produces the specified str, followed by enough fill chars to reach the \
+             specified width. The fill char defaults to a space. Strs which are \
+             already at least as wide are produced as is.",
+        );
 
         functions::load_trig_fns(self);
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use parser::Parser;
+
+    /// Produces a fresh environment with all intrinsics defined.
+    pub fn new_env() -> Environment {
+        let mut env = Environment::default();
+        env.init_intrinsics();
+        env
+    }
+
+    /// Evaluates all expressions in the specified string in the specified
+    /// environment, producing the value of the final expression.
+    pub fn eval_in(env: &mut Environment, s: &str) -> Result<Value> {
+        let mut res = nil();
+        for expr in Parser::parse_from_str(s)? {
+            res = expr.eval(env)?;
+        }
+        Ok(res)
+    }
+
+    /// Evaluates the specified string in a fresh environment.
+    pub fn eval(s: &str) -> Result<Value> {
+        eval_in(&mut new_env(), s)
+    }
+
+    /// Evaluates the specified string in a fresh environment, producing the
+    /// description of the resulting error.
+    pub fn eval_err(s: &str) -> String {
+        match eval(s) {
+            Ok(val) => panic!("Expected an error, found {}.", val),
+            Err(why) => why.into(),
+        }
+    }
+
+    #[test]
+    fn sandbox_omits_io() {
+        let mut env = Environment::default();
+        env.init_intrinsics_sandboxed();
+        assert!(eval_in(&mut env, "(+ 1 2)").unwrap() == Value::Num(3.0));
+
+        for expr in &["(read-file \"x\")", "(import \"x\")", "(exit 0)", "(system \"ls\")"] {
+            let why: String = eval_in(&mut env, expr).err().unwrap().into();
+            assert!(why.contains("is unbound."));
+        }
+
+        let why: String = eval_in(&mut env, "(eval-in '(exit 0))").err().unwrap().into();
+        assert!(why.contains("is unbound."));
+    }
+
+    #[test]
+    fn fuel_limits_evaluation() {
+        let mut env = new_env();
+        env.set_fuel(10_000);
+        let why: String = eval_in(&mut env, "(do () (#f))").err().unwrap().into();
+        assert!(why.contains("Fuel exhausted."));
+        assert_eq!(env.fuel(), Some(0));
+
+        env.set_fuel(100);
+        assert!(eval_in(&mut env, "(+ 1 2)").unwrap() == Value::Num(3.0));
+        assert_eq!(env.fuel(), Some(96));
+
+        env.set_unlimited_fuel();
+        assert!(eval_in(&mut env, "(+ 1 2)").is_ok());
+        assert_eq!(env.fuel(), None);
+    }
+
+    #[test]
+    fn intrinsics_documented() {
+        let env = new_env();
+        for name in env.names() {
+            if let Some(Value::Intrinsic(_)) = env.get(&name) {
+                let doc = env.doc(&name).unwrap();
+                let signature = format!("{} : ", name);
+                assert!(doc.starts_with(&signature), "{} has the wrong signature.", name);
+            }
+        }
+    }
+
+    #[test]
+    fn special_forms_reserved() {
+        for word in &["lambda", "if", "define", "let-values", "else"] {
+            let why = eval_err(&format!("(define {} 5)", word));
+            assert!(why.contains("is a reserved word."));
+            let why = eval_err(&format!("(define ({} x) x)", word));
+            assert!(why.contains("is a reserved word."));
+        }
+        assert!(eval("((lambda (x) x) 1)").unwrap() == Value::Num(1.0));
+    }
+}
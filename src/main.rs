@@ -6,6 +6,8 @@ extern crate ansi_term;
 extern crate clap;
 use clap::{App, Arg};
 
+extern crate rustyline;
+
 mod color;
 mod err;
 mod errors;
@@ -13,7 +15,6 @@ mod interpreter;
 mod intrinsics;
 mod parser;
 mod repl;
-mod utils;
 
 use err::*;
 use interpreter::*;
@@ -21,6 +22,7 @@ use intrinsics::*;
 use parser::*;
 
 use std::env;
+use std::fmt;
 
 const ENTRY_POINT: &str = "loader.rl";
 
@@ -42,9 +44,9 @@ fn init(lisp_env: &mut Environment, lib: &str) -> Result<(), RLError> {
     Ok(())
 }
 
-fn print_err(msg: impl AsRef<str>) {
-    let err = format!("ERROR:\n{}", msg.as_ref());
-    println!("{}", color::err(err));
+fn print_err(env: &Environment, msg: impl fmt::Display) {
+    let err = format!("ERROR:\n{}", msg);
+    let _ = env.write_error(&format!("{}\n", color::err(err)));
 }
 
 fn match_args<'a>() -> clap::ArgMatches<'a> {
@@ -65,6 +67,12 @@ fn match_args<'a>() -> clap::ArgMatches<'a> {
                 .long("interactive")
                 .help("Toggles interactive mode"),
         )
+        .arg(
+            Arg::with_name("trace")
+                .short("t")
+                .long("trace")
+                .help("Traces each function call and its return value"),
+        )
         .arg(
             Arg::with_name("INPUT")
                 .help("Sets the input file to use")
@@ -88,9 +96,19 @@ fn main() -> Result<(), Box<Error>> {
 
     init(&mut lisp_env, lib)?;
 
+    if matches.is_present("trace") {
+        let args = [Value::Bool(true)];
+        functions::_set_trace(&mut lisp_env, &args)?;
+    }
+
     if let Some(input) = input {
         let args = [Value::Str(input.to_string())];
-        functions::_import(&mut lisp_env, &args)?;
+        let res = functions::_import(&mut lisp_env, &args);
+        repl::print_warnings(&mut lisp_env);
+        if let Err(why) = res {
+            print_err(&lisp_env, why);
+            std::process::exit(1);
+        }
     }
 
     if interactive {
@@ -1,65 +1,142 @@
 pub mod sexpr;
 
+use std::{fmt, str};
 use std::io::{BufReader, Read};
 
 // Export SExpr.
 pub use sexpr::*;
 use SExpr::*;
 
+/// Represents a location in the parser's input, starting from line 1,
+/// column 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Represents an error produced while parsing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The end of the input was reached before an expression began.
+    Eof,
+
+    /// The input is not a valid expression.
+    Syntax(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Eof => write!(f, "Unexpected end of input."),
+            ParseError::Syntax(why) => write!(f, "{}", why),
+        }
+    }
+}
+
+impl From<String> for ParseError {
+    fn from(why: String) -> ParseError {
+        ParseError::Syntax(why)
+    }
+}
+
+impl From<&str> for ParseError {
+    fn from(why: &str) -> ParseError {
+        ParseError::Syntax(why.to_string())
+    }
+}
+
+/// Represents an error produced while parsing a sequence of expressions,
+/// along with the expressions that were parsed before it and the position of
+/// the last char read when it occurred.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseAllError {
+    pub parsed: Vec<SExpr>,
+    pub error: ParseError,
+    pub position: Position,
+}
+
+impl fmt::Display for ParseAllError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
 pub struct Parser<R: Read> {
     stack: Vec<char>,
     reader: BufReader<R>,
+    position: Position,
+    prev_line_end: usize,
 }
 
-type ParseResult = Result<SExpr, String>;
+type ParseResult = Result<SExpr, ParseError>;
+
+impl<'a> Parser<&'a [u8]> {
+    /// Produces a parser reading from the specified string.
+    pub fn for_str(s: &'a str) -> Self {
+        Parser::new(BufReader::new(s.as_bytes()))
+    }
+
+    /// Produces all expressions in the specified string.
+    pub fn parse_from_str(s: &'a str) -> Result<Vec<SExpr>, ParseAllError> {
+        Parser::for_str(s).parse_all()
+    }
+}
 
 impl<R: Read> Parser<R> {
     pub fn new(reader: BufReader<R>) -> Self {
         let mut parser = Parser {
             stack: vec![],
             reader,
+            position: Position { line: 1, column: 0 },
+            prev_line_end: 0,
         };
 
         // Ignore a shebang if present
-        let mut beginning: [u8; 2] = [0, 0];
-        match parser.reader.read(&mut beginning) {
-            Ok(n) if n == 2 => {
-                let (c1, c2) = (beginning[0] as char, beginning[1] as char);
-                if c1 == '#' && c2 == '!' {
-                    // Shebang
-                    parser.skip_to_linebreak();
-                } else {
-                    parser.undo_char(c2 as char);
-                    parser.undo_char(c1 as char);
-                }
+        match (parser.next_char(), parser.next_char()) {
+            (Some('#'), Some('!')) => {
+                // Shebang
+                parser.skip_to_linebreak();
             }
-            Ok(n) if n == 1 => {
-                let c = beginning[0];
-                parser.undo_char(c as char);
+            (Some(c1), Some(c2)) => {
+                parser.undo_char(c2);
+                parser.undo_char(c1);
             }
+            (Some(c), None) => parser.undo_char(c),
             _ => (),
         }
 
         parser
     }
 
-    pub fn parse_all(&mut self) -> Result<Vec<SExpr>, String> {
-        let mut results: Vec<SExpr> = vec![];
+    /// Produces all expressions from the reader until its end is reached. If
+    /// an error occurs, it is produced along with all expressions parsed
+    /// before it.
+    pub fn parse_all(&mut self) -> Result<Vec<SExpr>, ParseAllError> {
+        let mut parsed: Vec<SExpr> = vec![];
 
         loop {
             match self.parse() {
-                Ok(expr) => results.push(expr),
-                Err(why) => {
-                    if why == "EOF" {
-                        break;
-                    } else {
-                        return Err(why);
-                    }
+                Ok(expr) => parsed.push(expr),
+                Err(ParseError::Eof) => break,
+                Err(error) => {
+                    let position = self.position;
+                    return Err(ParseAllError {
+                        parsed,
+                        error,
+                        position,
+                    });
                 }
             }
         }
 
-        Ok(results)
+        Ok(parsed)
     }
 
     /// Skips the parser forward until a linebreak is reached.
@@ -76,20 +153,14 @@ impl<R: Read> Parser<R> {
     /// Produces the next expression from the reader, or an error if one is not
     /// found.
     pub fn parse(&mut self) -> ParseResult {
-        self.skip_whitespace();
+        self.skip_ignored()?;
 
         self.next_char()
-            .ok_or_else(|| "EOF".to_string())
+            .ok_or(ParseError::Eof)
             .and_then(|c| match c {
-                // Comment
-                ';' | '#' => {
-                    self.skip_to_linebreak();
-                    self.parse()
-                }
-
                 // Quote
                 '\'' => {
-                    let quoted = self.parse()?;
+                    let quoted = self.parse_required("Expected an expression after a quote.")?;
                     let quoted = SExpr::Quote(Box::new(quoted));
                     Ok(quoted)
                 }
@@ -100,12 +171,24 @@ impl<R: Read> Parser<R> {
                 // List (brackets)
                 '[' => self.parse_list(']'),
 
+                // Reader syntax
+                '#' => self.parse_reader_macro(),
+
+                // Closing delimiter without a matching opening delimiter
+                ')' | ']' => Err(format!("Unexpected '{}' at {}.", c, self.position).into()),
+
                 // String
                 '"' => self.parse_str(),
 
                 // Formatted string
                 '`' => self.parse_fmt_str(),
 
+                // Interpolated string, e.g. `~"x is #{x}"`
+                '~' => match self.next_char() {
+                    Some('"') => self.parse_str().map(format_call),
+                    _ => Err(format!("Expected '\"' after '~' at {}.", self.position).into()),
+                },
+
                 // Atom
                 _ => {
                     self.undo_char(c);
@@ -114,6 +197,15 @@ impl<R: Read> Parser<R> {
             })
     }
 
+    /// Produces the next expression from the reader. Reaching the end of the
+    /// reader is an error with the specified description.
+    fn parse_required(&mut self, why: &str) -> ParseResult {
+        match self.parse() {
+            Err(ParseError::Eof) => Err(why.into()),
+            res => res,
+        }
+    }
+
     /// Attempts to read the next atom in the `Parser`'s reader into an
     /// `Option<String>`. An atom is defined as being any expression other than
     /// a list.
@@ -142,17 +234,18 @@ impl<R: Read> Parser<R> {
     }
 
     /// Attempts to parse the next atom in the `Parser`'s reader. An atom is
-    /// defined as any expression that is not a list.
+    /// defined as any expression that is not a list. If the end of the reader
+    /// is reached before any char of an atom, `ParseError::Eof` is produced.
     fn parse_atom(&mut self) -> ParseResult {
         let atom = self.read_atom();
         if let Some(s) = atom {
             // Check true
-            if s == "#t" || s == "true" {
+            if s == "true" {
                 return Ok(SExpr::Bool(true));
             }
 
             // Check false
-            if s == "#f" || s == "false" {
+            if s == "false" {
                 return Ok(SExpr::Bool(false));
             }
 
@@ -162,24 +255,52 @@ impl<R: Read> Parser<R> {
             }
 
             // Check if valid identifier
-            if s.chars().all(|c| c.is_valid_ident()) {
-                const ELLIPSIS: &str = "...";
-                let variadic = s.ends_with(ELLIPSIS);
-                let name = if variadic {
-                    s[..s.len() - ELLIPSIS.len()].to_string()
-                } else {
-                    s
-                };
-                if name.is_empty() {
-                    Err("Empty identifier.".to_string())
-                } else {
-                    Ok(SExpr::Ident(name, variadic))
+            let (name, variadic) = split_ident(s)?;
+            Ok(Ident(name, variadic))
+        } else {
+            Err(ParseError::Eof)
+        }
+    }
+
+    /// Attempts to parse the reader syntax following a `#`. The `#` may only
+    /// appear at the beginning of a token, and is followed by one of:
+    /// * `(`: A vector literal, e.g. `#(1 2 3)`
+    /// * `t`, `true`, `f`, `false`: A bool
+    /// * `super:`: An identifier looked up in the enclosing scope
+    /// * `"`: A raw string, e.g. `#"C:\path"#`
+    fn parse_reader_macro(&mut self) -> ParseResult {
+        const SUPER: &str = "super:";
+
+        match self.next_char() {
+            Some('(') => {
+                self.undo_char('(');
+                self.parse_vector()
+            }
+            Some('"') => self.parse_raw_str(),
+            Some(c) => {
+                let position = self.position;
+                self.undo_char(c);
+                match self.read_atom() {
+                    Some(ref s) if s == "t" || s == "true" => Ok(Bool(true)),
+                    Some(ref s) if s == "f" || s == "false" => Ok(Bool(false)),
+                    Some(ref s) if s.starts_with(':') => match split_ident(s[1..].to_string())? {
+                        (name, false) => Ok(Ident(format!("#:{}", name), false)),
+                        _ => {
+                            let why = format!("Keyword '#{}' may not be variadic at {}.", s, position);
+                            Err(why.into())
+                        }
+                    },
+                    Some(ref s) if s.starts_with(SUPER) => {
+                        let (name, variadic) = split_ident(s[SUPER.len()..].to_string())?;
+                        Ok(Ident(format!("#{}{}", SUPER, name), variadic))
+                    }
+                    Some(s) => {
+                        Err(format!("Unknown reader syntax '#{}' at {}.", s, position).into())
+                    }
+                    None => Err(format!("Unknown reader syntax '#{}' at {}.", c, position).into()),
                 }
-            } else {
-                Err(format!("Invalid identifier {}.", s))
             }
-        } else {
-            Err("No atom.".to_string())
+            None => Err("Unexpected EOF after '#'.".into()),
         }
     }
 
@@ -198,17 +319,17 @@ impl<R: Read> Parser<R> {
                             '\"' => '\"',
                             '0' => '\0',
                             '\\' => '\\',
-                            c => return Err(format!("Unknown escape character '\\{}'.", c)),
+                            c => return Err(format!("Unknown escape character '\\{}'.", c).into()),
                         };
                         buf.push(escape);
                     }
                 }
                 Some(c) => buf.push(c),
-                None => return Err("Unexpected EOF before end of string.".to_string()),
+                None => return Err("Unexpected EOF before end of string.".into()),
             }
         }
 
-        Ok(List(vec![Ident("format".to_string(), false), Str(buf)]))
+        Ok(format_call(Str(buf)))
     }
 
     /// Attempts to parse the next string from the `Parser`'s reader.
@@ -232,7 +353,7 @@ impl<R: Read> Parser<R> {
                             '\"' => '\"',
                             '0' => '\0',
                             '\\' => '\\',
-                            c => return Err(format!("Unknown escape character '\\{}'.", c)),
+                            c => return Err(format!("Unknown escape character '\\{}'.", c).into()),
                         };
                         buf.push(escape);
                     }
@@ -242,52 +363,166 @@ impl<R: Read> Parser<R> {
                 Some(c) => buf.push(c),
 
                 // Throw an error if the string is unclosed
-                None => return Err("Unexpected EOF before end of string.".to_string()),
+                None => return Err("Unexpected EOF before end of string.".into()),
             }
         }
 
         Ok(Str(buf))
     }
 
+    /// Attempts to parse the next raw string from the `Parser`'s reader,
+    /// following its opening `#"`. A raw string is closed by `"#`, and may
+    /// contain any other chars, including quotes, backslashes, and newlines,
+    /// without escaping them.
+    fn parse_raw_str(&mut self) -> ParseResult {
+        let mut buf = String::new();
+
+        loop {
+            match self.next_char() {
+                Some('"') => match self.next_char() {
+                    Some('#') => break,
+                    Some(c) => {
+                        self.undo_char(c);
+                        buf.push('"');
+                    }
+                    None => buf.push('"'),
+                },
+                Some(c) => buf.push(c),
+                None => return Err("Unexpected EOF before end of raw string.".into()),
+            }
+        }
+
+        Ok(Str(buf))
+    }
+
+    /// Attempts to parse the next vector from the `Parser`'s reader. A vector
+    /// literal `#(a b c)` is converted into `(vector a b c)`.
+    fn parse_vector(&mut self) -> ParseResult {
+        match self.next_char() {
+            Some('(') => {
+                let vals = self.parse_exprs(')')?;
+                let mut buf = Vec::with_capacity(vals.len() + 1);
+                buf.push(Ident("vector".to_string(), false));
+                buf.extend(vals);
+                Ok(List(buf))
+            }
+            _ => Err(format!("Expected '(' after '#' at {}.", self.position).into()),
+        }
+    }
+
     /// Attempts to parse the next list from the `Parser`'s reader.
     fn parse_list(&mut self, close: char) -> ParseResult {
+        self.parse_exprs(close).map(List)
+    }
+
+    /// Attempts to parse expressions from the `Parser`'s reader up to the
+    /// specified closing delimiter.
+    fn parse_exprs(&mut self, close: char) -> Result<Vec<SExpr>, ParseError> {
         let mut buf: Vec<SExpr> = vec![];
 
         loop {
+            self.skip_ignored()?;
             match self.next_char() {
-                Some(c) if c.is_whitespace() => (),
                 Some(c) if c == close => {
                     break;
                 }
+
+                // Closed by the wrong delimiter, e.g. `(foo]`
+                Some(c) if c == ')' || c == ']' => {
+                    return Err(format!(
+                        "Expected '{}' but found '{}' at {}.",
+                        close, c, self.position
+                    ).into())
+                }
                 Some(c) => {
                     self.undo_char(c);
                     let exp = self.parse()?;
                     buf.push(exp);
                 }
-                None => return Err("Unexpected EOF before end of list.".to_string()),
+                None => return Err("Unexpected EOF before end of list.".into()),
             }
         }
 
-        Ok(List(buf))
+        Ok(buf)
     }
 
     /// Attempts to produce the next `char` in the `Parser`'s reader. If the
     /// reader does not contains another `char`, `None` is returned instead.
     fn next_char(&mut self) -> Option<char> {
-        if self.stack.is_empty() {
-            let mut buf: [u8; 1] = [0];
-            match self.reader.read(&mut buf) {
-                Ok(n) => match n {
-                    1 => (), // Read one char as expected
-                    _ => return None,
+        let ch = if self.stack.is_empty() {
+            self.read_char()?
+        } else {
+            self.stack.pop()?
+        };
+
+        // Track the position of the char just read
+        if ch == '\n' {
+            self.prev_line_end = self.position.column;
+            self.position.line += 1;
+            self.position.column = 0;
+        } else {
+            self.position.column += 1;
+        }
+
+        Some(ch)
+    }
+
+    /// Reads the next UTF-8 encoded `char` from the reader. Invalid sequences
+    /// are replaced by `char::REPLACEMENT_CHARACTER`.
+    fn read_char(&mut self) -> Option<char> {
+        let mut buf: [u8; 4] = [0; 4];
+        self.reader.read_exact(&mut buf[..1]).ok()?;
+
+        // The leading byte determines the length of the encoded char
+        let len = match buf[0].leading_ones() {
+            0 => 1,
+            n @ 2..=4 => n as usize,
+            _ => return Some(char::REPLACEMENT_CHARACTER),
+        };
+        if self.reader.read_exact(&mut buf[1..len]).is_err() {
+            return Some(char::REPLACEMENT_CHARACTER);
+        }
+        let ch = str::from_utf8(&buf[..len])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        Some(ch)
+    }
+
+    /// Skips any whitespace, line comments, and datum comments preceding the
+    /// next expression. A datum comment (`#;`) discards the single expression
+    /// following it.
+    fn skip_ignored(&mut self) -> Result<(), ParseError> {
+        loop {
+            self.skip_whitespace();
+            match self.next_char() {
+                // Line comment
+                Some(';') => self.skip_to_linebreak(),
+                Some('#') => match self.next_char() {
+                    // Datum comment
+                    Some(';') => {
+                        self.parse_required("Expected an expression after '#;'.")?;
+                    }
+
+                    // Line comment
+                    Some(c) if c.is_whitespace() => self.skip_to_linebreak(),
+
+                    // Reader syntax
+                    Some(c) => {
+                        self.undo_char(c);
+                        self.undo_char('#');
+                        break;
+                    }
+                    None => break,
                 },
-                Err(_) => return None,
+                Some(c) => {
+                    self.undo_char(c);
+                    break;
+                }
+                None => break,
             }
-            let ch = buf[0] as char;
-            Some(ch)
-        } else {
-            self.stack.pop()
         }
+        Ok(())
     }
 
     fn skip_whitespace(&mut self) {
@@ -305,12 +540,45 @@ impl<R: Read> Parser<R> {
 
     /// Undoes the last read `char`.
     fn undo_char(&mut self, c: char) {
+        if c == '\n' {
+            self.position.line -= 1;
+            self.position.column = self.prev_line_end;
+        } else {
+            self.position.column -= 1;
+        }
         self.stack.push(c);
     }
 }
 
+/// Attempts to split the specified atom into an identifier's name and whether
+/// or not it is variadic. An identifier ending in `...` is variadic.
+fn split_ident(s: String) -> Result<(String, bool), String> {
+    if s.chars().all(|c| c.is_valid_ident()) {
+        const ELLIPSIS: &str = "...";
+        let variadic = s.ends_with(ELLIPSIS);
+        let name = if variadic {
+            s[..s.len() - ELLIPSIS.len()].to_string()
+        } else {
+            s
+        };
+        if name.is_empty() {
+            Err("Empty identifier.".to_string())
+        } else {
+            Ok((name, variadic))
+        }
+    } else {
+        Err(format!("Invalid identifier {}.", s))
+    }
+}
+
 // val
 
+/// Produces the application of `format` to the specified string, such that
+/// its interpolations are evaluated inline.
+fn format_call(s: SExpr) -> SExpr {
+    List(vec![Ident("format".to_string(), false), s])
+}
+
 trait ValidParse {
     fn is_valid_atom(&self) -> bool;
     fn is_valid_ident(&self) -> bool;
@@ -342,7 +610,6 @@ impl ValidParse for char {
             | '&'
             | '$'
             | '.'
-            | '#'
             | ':'
             | 'λ'
             | 'a'...'z'
@@ -357,7 +624,302 @@ impl ValidParse for char {
 mod test {
     use super::*;
 
-    impl Into<BufReader<char>> for String {
-        fn into(self) -> BufReader<char> {}
+    fn parse_all(s: &str) -> Result<Vec<SExpr>, String> {
+        Parser::parse_from_str(s).map_err(|why| why.to_string())
+    }
+
+    #[test]
+    fn empty_inputs() {
+        let inputs = ["", "  \n\t\r\n", "; comment\n;; another", "#; (datum) ; c", "#!/bin/rl"];
+        for input in &inputs {
+            assert_eq!(parse_all(input), Ok(vec![]), "input: {:?}", input);
+        }
+    }
+
+    #[test]
+    fn atom_at_end_of_input() {
+        assert_eq!(parse_all("abc"), Ok(vec![ident("abc")]));
+        assert_eq!(parse_all("(a) 12.5"), Ok(vec![List(vec![ident("a")]), Num(12.5)]));
+        assert_eq!(parse_all("'x"), Ok(vec![Quote(Box::new(ident("x")))]));
+
+        let mut parser = Parser::for_str("true");
+        assert_eq!(parser.parse(), Ok(Bool(true)));
+        assert_eq!(parser.parse(), Err(ParseError::Eof));
+        assert_eq!(parser.parse_atom(), Err(ParseError::Eof));
+    }
+
+    #[test]
+    fn stray_close_paren() {
+        let why = parse_all(")").unwrap_err();
+        assert_eq!(why, "Unexpected ')' at line 1, column 1.");
+    }
+
+    #[test]
+    fn stray_close_after_expr() {
+        let why = parse_all("(foo)\n  ]").unwrap_err();
+        assert_eq!(why, "Unexpected ']' at line 2, column 3.");
+    }
+
+    #[test]
+    fn mismatched_close() {
+        let why = parse_all("(foo]").unwrap_err();
+        assert_eq!(why, "Expected ')' but found ']' at line 1, column 5.");
+    }
+
+    #[test]
+    fn datum_comment() {
+        let exprs = parse_all("#;(a b c) d").unwrap();
+        assert_eq!(exprs, vec![Ident("d".to_string(), false)]);
+    }
+
+    #[test]
+    fn nested_datum_comment() {
+        let exprs = parse_all("#; #; a b c").unwrap();
+        assert_eq!(exprs, vec![Ident("c".to_string(), false)]);
+
+        let exprs = parse_all("#;(a #;(b) c) d").unwrap();
+        assert_eq!(exprs, vec![Ident("d".to_string(), false)]);
+    }
+
+    #[test]
+    fn datum_comment_with_line_comments() {
+        let exprs = parse_all("#; ; comment\n  (a b)\n; another\n5").unwrap();
+        assert_eq!(exprs, vec![Num(5.0)]);
+    }
+
+    #[test]
+    fn datum_comment_in_list() {
+        let exprs = parse_all("(a #;b c #;d)").unwrap();
+        let list = List(vec![
+            Ident("a".to_string(), false),
+            Ident("c".to_string(), false),
+        ]);
+        assert_eq!(exprs, vec![list]);
+    }
+
+    #[test]
+    fn datum_comment_at_eof() {
+        let why = parse_all("a #;").unwrap_err();
+        assert_eq!(why, "Expected an expression after '#;'.");
+    }
+
+    #[test]
+    fn vector_literal() {
+        let exprs = parse_all("#(1 2 3)").unwrap();
+        let vector = List(vec![
+            Ident("vector".to_string(), false),
+            Num(1.0),
+            Num(2.0),
+            Num(3.0),
+        ]);
+        assert_eq!(exprs, vec![vector]);
+    }
+
+    #[test]
+    fn hash_bools() {
+        let exprs = parse_all("#t #f #true #false true false").unwrap();
+        let expected = vec![
+            Bool(true),
+            Bool(false),
+            Bool(true),
+            Bool(false),
+            Bool(true),
+            Bool(false),
+        ];
+        assert_eq!(exprs, expected);
+    }
+
+    #[test]
+    fn hash_super_ident() {
+        let exprs = parse_all("#super:foo").unwrap();
+        assert_eq!(exprs, vec![Ident("#super:foo".to_string(), false)]);
+    }
+
+    #[test]
+    fn hash_keyword() {
+        let exprs = parse_all("#:when").unwrap();
+        assert_eq!(exprs, vec![Ident("#:when".to_string(), false)]);
+        assert!(parse_all("#:when...").is_err());
+    }
+
+    #[test]
+    fn hash_line_comment() {
+        let exprs = parse_all("# comment\n5").unwrap();
+        assert_eq!(exprs, vec![Num(5.0)]);
+    }
+
+    #[test]
+    fn hash_inside_ident() {
+        let why = parse_all("a#b").unwrap_err();
+        assert_eq!(why, "Invalid identifier a#b.");
+
+        let why = parse_all("#super:a#b").unwrap_err();
+        assert_eq!(why, "Invalid identifier a#b.");
+    }
+
+    #[test]
+    fn unknown_reader_syntax() {
+        let why = parse_all("(a #foo)").unwrap_err();
+        assert_eq!(why, "Unknown reader syntax '#foo' at line 1, column 5.");
+    }
+
+    #[test]
+    fn matched_delimiters() {
+        let list = List(vec![
+            Ident("a".to_string(), false),
+            Ident("b".to_string(), false),
+        ]);
+        assert_eq!(parse_all("[a b]"), Ok(vec![list.clone()]));
+        assert_eq!(parse_all("(a b)"), Ok(vec![list]));
+    }
+
+    #[test]
+    fn mismatched_nested_close() {
+        let why = parse_all("[(a b] c)").unwrap_err();
+        assert_eq!(why, "Expected ')' but found ']' at line 1, column 6.");
+
+        let why = parse_all("(a b]").unwrap_err();
+        assert_eq!(why, "Expected ')' but found ']' at line 1, column 5.");
+    }
+
+    #[test]
+    fn parse_all_clean_eof() {
+        let mut parser = Parser::for_str("1 (a) \"b\" ; done\n");
+        let exprs = parser.parse_all().unwrap();
+        let expected = vec![
+            Num(1.0),
+            List(vec![Ident("a".to_string(), false)]),
+            Str("b".to_string()),
+        ];
+        assert_eq!(exprs, expected);
+        assert_eq!(parser.parse(), Err(ParseError::Eof));
+    }
+
+    #[test]
+    fn parse_all_trailing_incomplete() {
+        let mut parser = Parser::for_str("1 2 (a b");
+        let why = parser.parse_all().unwrap_err();
+        assert_eq!(why.parsed, vec![Num(1.0), Num(2.0)]);
+        let expected = ParseError::Syntax("Unexpected EOF before end of list.".to_string());
+        assert_eq!(why.error, expected);
+    }
+
+    #[test]
+    fn trailing_quote_is_not_eof() {
+        let why = parse_all("1 '").unwrap_err();
+        assert_eq!(why, "Expected an expression after a quote.");
+        let why = parse_all("(a ')").unwrap_err();
+        assert!(why.contains("Unexpected ')'"));
+    }
+
+    #[test]
+    fn parse_from_str() {
+        let exprs = Parser::parse_from_str("#!/usr/bin/env rlisp\n(f 1) x").unwrap();
+        let call = List(vec![Ident("f".to_string(), false), Num(1.0)]);
+        assert_eq!(exprs, vec![call, Ident("x".to_string(), false)]);
+        assert_eq!(Parser::parse_from_str("  ").unwrap(), vec![]);
+
+        let why = Parser::parse_from_str("x (").unwrap_err();
+        assert_eq!(why.parsed, vec![Ident("x".to_string(), false)]);
+    }
+
+    #[test]
+    fn shebang_line() {
+        let script = "#!/usr/bin/env rustlisp\n(define x 1)\n\n(println x)\n";
+        let exprs = Parser::parse_from_str(script).unwrap();
+        assert_eq!(exprs.len(), 2);
+        assert_eq!(exprs[1], List(vec![ident("println"), ident("x")]));
+
+        // Only the very first line may be a shebang
+        let why = Parser::parse_from_str("#!/bin/rl\n(f)\n#!/bin/rl\n").unwrap_err();
+        assert_eq!(why.parsed, vec![List(vec![ident("f")])]);
+        assert_eq!(why.position.line, 3);
+        assert!(Parser::parse_from_str(" #!/bin/rl").is_err());
+    }
+
+    #[test]
+    fn for_str() {
+        let mut parser = Parser::for_str("a 'b");
+        assert_eq!(parser.parse(), Ok(Ident("a".to_string(), false)));
+        let quoted = Quote(Box::new(Ident("b".to_string(), false)));
+        assert_eq!(parser.parse(), Ok(quoted));
+        assert_eq!(parser.parse(), Err(ParseError::Eof));
+    }
+
+    fn ident(s: &str) -> SExpr {
+        Ident(s.to_string(), false)
+    }
+
+    #[test]
+    fn numbers() {
+        let exprs = parse_all("1 -2.5 1e3 .5").unwrap();
+        assert_eq!(exprs, vec![Num(1.0), Num(-2.5), Num(1000.0), Num(0.5)]);
+        assert_eq!(parse_all("-").unwrap(), vec![ident("-")]);
+    }
+
+    #[test]
+    fn string_escapes() {
+        let exprs = parse_all(r#""a\nb\t\"c\" \\""#).unwrap();
+        assert_eq!(exprs, vec![Str("a\nb\t\"c\" \\".to_string())]);
+        assert!(parse_all(r#""\q""#).unwrap_err().contains("Unknown escape character"));
+        assert!(parse_all("\"abc").unwrap_err().contains("before end of string"));
+    }
+
+    #[test]
+    fn multibyte_chars() {
+        let exprs = parse_all("(\"h\u{e9}\u{1f600}\" \u{3bb})").unwrap();
+        let expected = List(vec![Str("h\u{e9}\u{1f600}".to_string()), ident("\u{3bb}")]);
+        assert_eq!(exprs, vec![expected]);
+    }
+
+    #[test]
+    fn raw_strings() {
+        let exprs = parse_all(r###"#"say "hi" \n C:\dir\"# x"###).unwrap();
+        assert_eq!(exprs, vec![Str(r#"say "hi" \n C:\dir\"#.to_string()), ident("x")]);
+
+        let exprs = parse_all("#\"{\n  \"a\": 1\n}\"#").unwrap();
+        assert_eq!(exprs, vec![Str("{\n  \"a\": 1\n}".to_string())]);
+
+        assert_eq!(parse_all(r###"#""#"###).unwrap(), vec![Str(String::new())]);
+        assert!(parse_all(r#"#"abc" "#).unwrap_err().contains("before end of raw string"));
+    }
+
+    #[test]
+    fn interpolated_strings() {
+        let exprs = parse_all(r#"~"x is ${x}\n" `y`"#).unwrap();
+        let format = |s: &str| List(vec![ident("format"), Str(s.to_string())]);
+        assert_eq!(exprs, vec![format("x is ${x}\n"), format("y")]);
+        assert!(parse_all("~x").unwrap_err().contains("Expected '\"' after '~'"));
+    }
+
+    #[test]
+    fn nested_lists() {
+        let exprs = parse_all("(a (b [c]) ())").unwrap();
+        let expected = List(vec![
+            ident("a"),
+            List(vec![ident("b"), List(vec![ident("c")])]),
+            List(vec![]),
+        ]);
+        assert_eq!(exprs, vec![expected]);
+    }
+
+    #[test]
+    fn quotes() {
+        let exprs = parse_all("'a '(1 'b)").unwrap();
+        let quoted_list = List(vec![Num(1.0), Quote(Box::new(ident("b")))]);
+        let expected = vec![Quote(Box::new(ident("a"))), Quote(Box::new(quoted_list))];
+        assert_eq!(exprs, expected);
+    }
+
+    #[test]
+    fn line_comments() {
+        let exprs = parse_all("; leading\n(a ; inner\n b) ; trailing").unwrap();
+        assert_eq!(exprs, vec![List(vec![ident("a"), ident("b")])]);
+    }
+
+    #[test]
+    fn unclosed_list() {
+        let why = parse_all("(a (b)").unwrap_err();
+        assert_eq!(why, "Unexpected EOF before end of list.");
     }
 }
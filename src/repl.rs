@@ -1,62 +1,285 @@
-use std::io::{self, BufReader, Error, Write};
+use std::fmt;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{self, Context, Editor, Helper};
 
 use color;
 use parser::*;
-use utils::*;
 use interpreter::*;
 
-fn read_input_line() -> Result<String, Error> {
-    let mut buf = String::new();
-    io::stdin().read_line(&mut buf)?;
-    Ok(buf)
+/// The prompt displayed before each line of input.
+const PROMPT: &str = "> ";
+
+fn parse_line<S: AsRef<str>>(line: S) -> Result<Vec<SExpr>, ParseAllError> {
+    Parser::parse_from_str(line.as_ref())
+}
+
+/// Reports the specified error to the error output of the specified
+/// environment.
+pub fn print_err<S: fmt::Display>(env: &Environment, why: S) {
+    let err = color::err(format!("ERROR: {}", why));
+    // Failing to report an error should not interrupt the REPL
+    let _ = env.write_error(&format!("{}\n", err));
+}
+
+/// Produces the line of the specified input at the specified position, along
+/// with a line containing a caret under its column. Tabs before the column are
+/// kept, so that the caret lines up however they are displayed.
+fn caret_lines(input: &str, position: Position) -> Option<(&str, String)> {
+    let line = input.lines().nth(position.line.checked_sub(1)?)?;
+    let caret = line
+        .chars()
+        .take(position.column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .chain(Some('^'))
+        .collect();
+    Some((line, caret))
+}
+
+/// Prints the specified parse error, followed by the offending line of the
+/// specified input with a caret under the position of the error.
+fn print_parse_err(env: &Environment, input: &str, why: &ParseAllError) {
+    print_err(env, why);
+    if let Some((line, caret)) = caret_lines(input, why.position) {
+        let _ = env.write_error(&format!("{}\n{}\n", line, color::err(caret)));
+    }
+}
+
+/// Prints all warnings recorded in the specified environment.
+pub fn print_warnings(env: &mut Environment) {
+    for warning in env.take_warnings() {
+        let warning = color::warn(format!("WARNING: {}", warning));
+        let _ = env.write_error(&format!("{}\n", warning));
+    }
+}
+
+/// The number of previous results that remain available, as `$1` through
+/// `$5`.
+const HISTORY_LEN: usize = 5;
+
+/// Records the specified result as the most recent one, available as both `$`
+/// and `$1`. Each previous result moves back by one, such that `$2` refers to
+/// the result before it, etc.
+fn record_result(env: &mut Environment, res: Value) {
+    for i in (1..HISTORY_LEN).rev() {
+        if let Some(prev) = env.get(format!("${}", i)) {
+            env.define(format!("${}", i + 1), prev);
+        }
+    }
+    env.define("$1", res.clone());
+    env.define("$", res);
 }
 
-fn parse_line<S: AsRef<str>>(line: S) -> Result<Vec<SExpr>, String> {
-    let bytes = line.as_ref().as_bytes();
-    let mut parser = Parser::new(BufReader::new(bytes));
+/// Determines whether or not the specified char separates identifiers.
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "()[]{}'`,\"".contains(c)
+}
+
+/// Completes the partially typed identifier at the end of the specified line
+/// from the specified names. Produces the position in the line at which the
+/// identifier begins, along with each name it is a prefix of, in order. An
+/// empty identifier has no completions.
+fn complete<'a>(line: &str, names: &'a [String]) -> (usize, Vec<&'a str>) {
+    let start = line
+        .rfind(is_delimiter)
+        .map(|i| i + line[i..].chars().next().map_or(0, char::len_utf8))
+        .unwrap_or(0);
+    let prefix = &line[start..];
+    if prefix.is_empty() {
+        return (start, vec![]);
+    }
+
+    let mut completions: Vec<&str> = names
+        .iter()
+        .map(String::as_str)
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    completions.sort();
+    completions.dedup();
+    (start, completions)
+}
 
-    parser.parse_all()
+/// Completes identifiers in the line reader from the names bound in the
+/// environment when the line was started.
+struct NameCompleter {
+    names: Vec<String>,
 }
 
-pub fn print_prompt<S: AsRef<str>>(prompt: S) -> io::Result<()> {
-    // print!("{}", color::prompt(prompt.as_ref()));
-    // print!("{} ", color::carrot(">"));
-    print!("> ");
-    io::stdout().flush()
+impl Completer for NameCompleter {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let (start, completions) = complete(&line[..pos], &self.names);
+        Ok((start, completions.into_iter().map(String::from).collect()))
+    }
 }
 
-pub fn print_err<S: AsRef<str>>(why: S) {
-    let err = color::err(format!("ERROR: {}", why.as_ref()));
-    println!("{}", err);
+impl Hinter for NameCompleter {
+    type Hint = String;
 }
 
+impl Highlighter for NameCompleter {}
+
+impl Validator for NameCompleter {}
+
+impl Helper for NameCompleter {}
+
 /// Evaluates the specified expressions.
 fn eval_exprs(env: &mut Environment, exprs: &[SExpr]) {
     for expr in exprs {
-        expr.eval(env)
-            .map(|res| match res {
-                Value::List(ref vals) if vals.is_empty() => (),
-                _ => {
-                    let out = format!("{}", res);
-                    println!("{}", out);
+        match expr.eval(env) {
+            Ok(Value::List(ref vals)) if vals.is_empty() => (),
+            Ok(res) => {
+                if env.write_output(&format!("{}\n", res)).is_err() {
+                    print_err(env, "Could not write output.");
                 }
-            })
-            .unwrap_or_else(|why| print_err(why));
+                record_result(env, res);
+            }
+            Err(why) => print_err(env, why),
+        }
+        print_warnings(env);
     }
 }
 
-/// Runs a REPL for the specified environment.
+/// Runs a REPL for the specified environment. Lines are read with history,
+/// and Tab completes the identifier before the cursor.
 pub fn run(env: &mut Environment) {
-    let prompt = format!("{}", user_name());
+    let mut editor = match Editor::<NameCompleter>::new() {
+        Ok(editor) => editor,
+        Err(_) => return print_err(env, "Could not read input."),
+    };
+    editor.set_helper(Some(NameCompleter { names: vec![] }));
     loop {
-        print_prompt(&prompt).expect("Failed to print prompt.");
-        if let Ok(line) = read_input_line() {
-            match parse_line(line) {
-                Ok(ref exprs) => eval_exprs(env, exprs),
-                Err(why) => print_err(why),
+        if let Some(completer) = editor.helper_mut() {
+            completer.names = env.names();
+        }
+        match editor.readline(PROMPT) {
+            // Empty, whitespace-only, and comment-only lines parse to nothing
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    editor.add_history_entry(line.as_str());
+                }
+                match parse_line(&line) {
+                    Ok(ref exprs) => eval_exprs(env, exprs),
+                    Err(why) => print_parse_err(env, &line, &why),
+                }
             }
-        } else {
-            print_err("Could not read input.");
+            // Interrupting discards the line being typed
+            Err(ReadlineError::Interrupted) => (),
+            Err(ReadlineError::Eof) => {
+                let _ = env.write_output("\n");
+                break;
+            }
+            Err(_) => {
+                print_err(env, "Could not read input.");
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use intrinsics::test::*;
+    use rustyline::history::History;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn eval_line(env: &mut Environment, line: &str) {
+        eval_exprs(env, &parse_line(line).unwrap());
+    }
+
+    #[test]
+    fn caret_under_column() {
+        let why = parse_line("(+ 1 2))").unwrap_err();
+        let (line, caret) = caret_lines("(+ 1 2))", why.position).unwrap();
+        assert_eq!(line, "(+ 1 2))");
+        assert_eq!(caret, "       ^");
+
+        let position = Position { line: 2, column: 3 };
+        let (line, caret) = caret_lines("a\n\tbc\n", position).unwrap();
+        assert_eq!((line, caret.as_str()), ("\tbc", "\t ^"));
+        assert_eq!(caret_lines("a", Position { line: 3, column: 1 }), None);
+    }
+
+    #[test]
+    fn blank_lines_are_ignored() {
+        let mut env = new_env();
+        eval_line(&mut env, "(+ 1 2)");
+        for line in &["", "\n", "  \t \n", "; comment\n", "#; (datum)\n"] {
+            assert_eq!(parse_line(line).unwrap(), vec![]);
+            eval_line(&mut env, line);
+        }
+        assert!(env.get("$") == Some(Value::Num(3.0)));
+        assert!(env.get("$2").is_none());
+    }
+
+    #[test]
+    fn redirected_output() {
+        let mut env = new_env();
+        let (out, err) = (Rc::new(RefCell::new(vec![])), Rc::new(RefCell::new(vec![])));
+        env.set_output(out.clone());
+        env.set_error_output(err.clone());
+        eval_line(&mut env, "(print \"hi\") (+ 1 2) (car 1)");
+
+        let out = String::from_utf8(out.borrow().clone()).unwrap();
+        assert_eq!(out, format!("hi{}\n", Value::Num(3.0)));
+        let err = String::from_utf8(err.borrow().clone()).unwrap();
+        assert!(err.contains("ERROR: ") && err.ends_with('\n'));
+    }
+
+    #[test]
+    fn identifier_completion() {
+        let names: Vec<String> = ["map", "max", "min", "make-point", "$1"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(complete("(ma", &names), (1, vec!["make-point", "map", "max"]));
+        assert_eq!(complete("(+ 1 (mi", &names), (6, vec!["min"]));
+        assert_eq!(complete("'$", &names), (1, vec!["$1"]));
+        assert_eq!(complete("(map ", &names), (5, vec![]));
+        assert_eq!(complete("foo", &names), (0, vec![]));
+
+        let mut env = new_env();
+        eval_line(&mut env, "(define my-value 1)");
+        let names = env.names();
+        assert_eq!(complete("(+ my-v", &names).1, vec!["my-value"]);
+        assert!(complete("(car", &names).1.contains(&"car"));
+
+        // Only the text before the cursor is completed
+        let history = History::new();
+        let completer = NameCompleter { names };
+        let res = completer.complete("(my x)", 3, &Context::new(&history)).unwrap();
+        assert_eq!(res, (1, vec!["my-value".to_string()]));
+    }
+
+    #[test]
+    fn result_history() {
+        let mut env = new_env();
+        eval_line(&mut env, "(+ 1 2)");
+        eval_line(&mut env, "(* $ 10)");
+        assert!(env.get("$") == Some(Value::Num(30.0)));
+        assert!(env.get("$2") == Some(Value::Num(3.0)));
+
+        // Nil results are not recorded
+        eval_line(&mut env, "(define x 1)");
+        assert!(env.get("$") == Some(Value::Num(30.0)));
+
+        for n in 0..HISTORY_LEN + 2 {
+            eval_line(&mut env, &n.to_string());
         }
+        assert!(env.get("$1") == Some(Value::Num(6.0)));
+        assert!(env.get("$5") == Some(Value::Num(2.0)));
+        assert!(env.get("$6").is_none());
     }
 }